@@ -0,0 +1,55 @@
+//! Language server for atto files, speaking JSON-RPC over stdio
+//!
+//! Supported are diagnostics, document symbols, hover, go to definition,
+//! formatting and semantic tokens. Documents are synchronized incrementally.
+
+mod rpc;
+mod semantic;
+mod server;
+mod text;
+
+use serde_json::{json, Value as Json};
+use server::Server;
+use std::io::{self, BufReader};
+use std::process::ExitCode;
+
+const PARSE_ERROR: i64 = -32700;
+
+fn main() -> ExitCode {
+  let mut input = BufReader::new(io::stdin().lock());
+  let mut output = io::stdout().lock();
+  let mut server = Server::default();
+
+  loop {
+    let body = match rpc::read_message(&mut input) {
+      Ok(Some(body)) => body,
+      Ok(None) => return ExitCode::FAILURE,
+      Err(err) => {
+        eprintln!("atto-lsp: {err}");
+        return ExitCode::FAILURE;
+      }
+    };
+
+    let replies = match serde_json::from_str::<Json>(&body) {
+      Ok(message) if message["method"] == "exit" => {
+        return match server.is_shut_down() {
+          true => ExitCode::SUCCESS,
+          false => ExitCode::FAILURE,
+        };
+      }
+      Ok(message) => server.handle(&message),
+      Err(err) => vec![json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": PARSE_ERROR, "message": err.to_string() },
+      })],
+    };
+
+    for reply in replies {
+      if let Err(err) = rpc::write_message(&mut output, &reply) {
+        eprintln!("atto-lsp: {err}");
+        return ExitCode::FAILURE;
+      }
+    }
+  }
+}
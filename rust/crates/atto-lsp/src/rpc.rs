@@ -0,0 +1,83 @@
+//! JSON-RPC framing: messages with a `Content-Length` header
+
+use serde_json::Value as Json;
+use std::io::{self, BufRead, Write};
+
+/// The maximum length of a message body, enough for a document of the
+/// maximum input length of the default parse limits, escaped as JSON
+pub const MAX_LENGTH: usize = 64 << 20;
+
+/// Read the body of the next message, `None` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+  let mut length = None;
+  loop {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = line.split_once(':') {
+      if name.eq_ignore_ascii_case("content-length") {
+        length = value.trim().parse::<usize>().ok();
+      }
+    }
+  }
+
+  let length = length.ok_or_else(|| invalid("missing content length"))?;
+  if length > MAX_LENGTH {
+    return Err(invalid("content length exceeds the maximum"));
+  }
+  let mut body = vec![0; length];
+  input.read_exact(&mut body)?;
+  String::from_utf8(body).map(Some).map_err(|_| invalid("body is not UTF-8"))
+}
+
+pub fn write_message(
+  output: &mut impl Write,
+  message: &Json,
+) -> io::Result<()> {
+  let body = message.to_string();
+  write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+  output.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_round_trip() {
+    let mut buffer = vec![];
+    write_message(&mut buffer, &json!({ "id": 1, "x": "ö" })).unwrap();
+    write_message(&mut buffer, &json!(null)).unwrap();
+
+    let mut input = buffer.as_slice();
+    let body = read_message(&mut input).unwrap().unwrap();
+    assert_eq!(body, r#"{"id":1,"x":"ö"}"#);
+    assert_eq!(read_message(&mut input).unwrap().unwrap(), "null");
+    assert_eq!(read_message(&mut input).unwrap(), None);
+  }
+
+  #[test]
+  fn test_missing_length() {
+    let mut input = "Content-Type: x\r\n\r\n{}".as_bytes();
+    let err = read_message(&mut input).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn test_length_limit() {
+    let mut input = "Content-Length: 99999999999\r\n\r\n{}".as_bytes();
+    let err = read_message(&mut input).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "content length exceeds the maximum");
+  }
+}
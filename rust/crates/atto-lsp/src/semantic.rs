@@ -0,0 +1,154 @@
+//! Semantic tokens from the rule ids of the atto lexer
+
+use crate::text::LineIndex;
+use atto::lex::{self, *};
+use atto::syntax::{Node, NodeKind, Span};
+
+/// The token types, the index is the type in the encoded tokens
+pub const LEGEND: [&str; 6] =
+  ["comment", "string", "operator", "property", "number", "keyword"];
+
+const COMMENT: u32 = 0;
+const STRING: u32 = 1;
+const OPERATOR: u32 = 2;
+const PROPERTY: u32 = 3;
+const NUMBER: u32 = 4;
+const KEYWORD: u32 = 5;
+
+/// The semantic tokens of `text` in the relative LSP encoding.
+///
+/// Keys are properties, whether bare or quoted. Bare atoms are only
+/// highlighted if they look like numbers, and `#nil` as a keyword. Invalid
+/// tokens and undefined special atoms are not highlighted at all since they
+/// are already reported as diagnostics.
+pub fn tokens(text: &str, root: &Node) -> Vec<u32> {
+  let mut keys = vec![];
+  collect_keys(root, &mut keys);
+  let index = LineIndex::new(text);
+  let mut encoder = Encoder { data: vec![], line: 0, column: 0 };
+
+  for token in lex::tokens(text.as_bytes()) {
+    let span = Span::new(token.index - token.data.len(), token.index);
+    let kind = match token_type(token.rule_id, &text[span.start..span.end]) {
+      Some(COMMENT) => COMMENT,
+      _ if is_key(&keys, span) => PROPERTY,
+      Some(kind) => kind,
+      None => continue,
+    };
+    encoder.push(&index, text, span, kind);
+  }
+  encoder.data
+}
+
+#[allow(non_upper_case_globals)]
+fn token_type(rule_id: u16, data: &str) -> Option<u32> {
+  match rule_id {
+    R_ID_comment => Some(COMMENT),
+    R_ID_colon | R_ID_open_paren | R_ID_close_paren => Some(OPERATOR),
+    R_ID_bare if is_number(data) => Some(NUMBER),
+    R_ID_bare => None,
+    R_ID_special_atom if data == "#nil" => Some(KEYWORD),
+    R_ID_start_string | R_ID_string | R_ID_start_esc | R_ID_simple_esc
+    | R_ID_x_esc | R_ID_u_esc | R_ID_end_string | R_ID_start_gd_string
+    | R_ID_gd_string | R_ID_gd_quote | R_ID_end_gd_string => Some(STRING),
+    _ => None,
+  }
+}
+
+fn is_number(atom: &str) -> bool {
+  atom.parse::<f64>().is_ok() && atom.bytes().any(|b| b.is_ascii_digit())
+}
+
+// Key spans in source order
+fn collect_keys(node: &Node, keys: &mut Vec<Span>) {
+  match &node.kind {
+    NodeKind::List(list) => {
+      list.iter().for_each(|node| collect_keys(node, keys))
+    }
+    NodeKind::Document(entries) => {
+      for entry in entries {
+        keys.push(entry.key_span);
+        collect_keys(&entry.value, keys);
+      }
+    }
+    _ => {}
+  }
+}
+
+fn is_key(keys: &[Span], span: Span) -> bool {
+  let i = keys.partition_point(|key| key.start <= span.start);
+  i > 0 && span.end <= keys[i - 1].end
+}
+
+struct Encoder {
+  data:   Vec<u32>,
+  line:   usize,
+  column: usize,
+}
+
+impl Encoder {
+  // Tokens must not span lines, so multi-line tokens are split
+  fn push(&mut self, index: &LineIndex, text: &str, span: Span, kind: u32) {
+    let mut start = span.start;
+    for line in text[span.start..span.end].split_inclusive('\n') {
+      let content = line.trim_end_matches(['\r', '\n']);
+      let length = content.encode_utf16().count();
+      if length > 0 {
+        let (line, column) = index.position(start);
+        let delta_line = line - self.line;
+        let delta_column =
+          if delta_line == 0 { column - self.column } else { column };
+        let token = [delta_line, delta_column, length];
+        self.data.extend(token.map(|n| n as u32));
+        self.data.extend([kind, 0]);
+        (self.line, self.column) = (line, column);
+      }
+      start += line.len();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode(text: &str) -> Vec<(usize, usize, usize, &'static str)> {
+    let root = atto::parser::parse_tree(text).root;
+    let (mut line, mut column) = (0, 0);
+    let chunks = tokens(text, &root);
+    let decode = |chunk: &[u32]| {
+      let chunk = chunk.iter().map(|&n| n as usize).collect::<Vec<_>>();
+      line += chunk[0];
+      column = if chunk[0] == 0 { column + chunk[1] } else { chunk[1] };
+      (line, column, chunk[2], LEGEND[chunk[3]])
+    };
+    chunks.chunks(5).map(decode).collect()
+  }
+
+  #[test]
+  fn test_tokens() {
+    assert_eq!(decode("# c\n\"k\": (1 x #nil #x)"), [
+      (0, 0, 3, "comment"),
+      (1, 0, 1, "property"),
+      (1, 1, 1, "property"),
+      (1, 2, 1, "property"),
+      (1, 3, 1, "operator"),
+      (1, 5, 1, "operator"),
+      (1, 6, 1, "number"),
+      (1, 10, 4, "keyword"),
+      (1, 17, 1, "operator"),
+    ]);
+  }
+
+  #[test]
+  fn test_multi_line_string() {
+    assert_eq!(decode("a: #\"x\ny\"#"), [
+      (0, 0, 1, "property"),
+      (0, 1, 1, "operator"),
+      (0, 3, 2, "string"),
+      (0, 5, 1, "string"),
+      (1, 0, 1, "string"),
+      (1, 1, 2, "string"),
+    ]);
+  }
+}
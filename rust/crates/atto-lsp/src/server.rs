@@ -0,0 +1,340 @@
+//! The language server: open documents and the handlers of all methods
+
+use crate::semantic;
+use crate::text::LineIndex;
+use atto::incremental::{Edit, Incremental};
+use atto::lex::{self, R_ID_comment};
+use atto::parser::Parsed;
+use atto::path::Path;
+use atto::syntax::{Entry, Node, NodeKind, Span};
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+
+// JSON-RPC error codes
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// The state of the server between messages
+#[derive(Default)]
+pub struct Server {
+  documents: HashMap<String, Document>,
+  shut_down: bool,
+}
+
+// Documents are reparsed incrementally on every change
+struct Document(Incremental);
+
+impl Document {
+  fn new(text: &str) -> Document { Document(Incremental::new(text)) }
+
+  fn text(&self) -> &str { self.0.text() }
+
+  fn parsed(&self) -> &Parsed { self.0.parsed() }
+
+  fn index(&self) -> LineIndex<'_> { LineIndex::new(self.text()) }
+
+  // A change replaces a range, or the whole text if it has none
+  fn change(&mut self, change: &Json) {
+    let Some(text) = change["text"].as_str() else {
+      return;
+    };
+    let index = self.index();
+    let start = index.offset_of(&change["range"]["start"]);
+    let end = index.offset_of(&change["range"]["end"]);
+    match start.zip(end) {
+      Some((start, end)) => {
+        self.0.edit(&Edit::new(Span::new(start, end.max(start)), text));
+      }
+      None => self.0 = Incremental::new(text),
+    }
+  }
+}
+
+type Reply = Result<Json, (i64, String)>;
+
+impl Server {
+  /// Whether a `shutdown` request was received, so `exit` is expected.
+  pub fn is_shut_down(&self) -> bool { self.shut_down }
+
+  /// Handle a request or notification and return the messages to send.
+  pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+    let method = message["method"].as_str().unwrap_or_default();
+    let params = &message["params"];
+    let Some(id) = message.get("id") else {
+      return self.notify(method, params);
+    };
+
+    let result = if self.shut_down {
+      Err((INVALID_REQUEST, "server is shut down".to_owned()))
+    } else {
+      self.request(method, params)
+    };
+    let response = match result {
+      Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+      Err((code, message)) => json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+      }),
+    };
+    vec![response]
+  }
+
+  fn request(&mut self, method: &str, params: &Json) -> Reply {
+    match method {
+      "initialize" => Ok(capabilities()),
+      "shutdown" => {
+        self.shut_down = true;
+        Ok(Json::Null)
+      }
+      "textDocument/documentSymbol" => {
+        let document = self.document(params)?;
+        Ok(symbols(&document.index(), document.parsed().root.entries()))
+      }
+      "textDocument/hover" => {
+        let (document, offset) = self.document_at(params)?;
+        Ok(hover(document, offset))
+      }
+      "textDocument/definition" => {
+        let (document, offset) = self.document_at(params)?;
+        let uri = &params["textDocument"]["uri"];
+        Ok(
+          definition(document, offset)
+            .map_or(Json::Null, |range| json!({ "uri": uri, "range": range })),
+        )
+      }
+      "textDocument/formatting" => Ok(formatting(self.document(params)?)),
+      "textDocument/semanticTokens/full" => {
+        let document = self.document(params)?;
+        let data = semantic::tokens(document.text(), &document.parsed().root);
+        Ok(json!({ "data": data }))
+      }
+      _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+    }
+  }
+
+  fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+    match method {
+      "textDocument/didOpen" => {
+        let Some(text) = params["textDocument"]["text"].as_str() else {
+          return vec![];
+        };
+        self.documents.insert(uri.to_owned(), Document::new(text));
+      }
+      "textDocument/didChange" => {
+        let Some(document) = self.documents.get_mut(uri) else {
+          return vec![];
+        };
+        let changes = params["contentChanges"].as_array();
+        changes.into_iter().flatten().for_each(|c| document.change(c));
+      }
+      "textDocument/didClose" => {
+        self.documents.remove(uri);
+        return vec![publish_diagnostics(uri, json!([]))];
+      }
+      _ => return vec![],
+    }
+
+    let diagnostics = diagnostics(&self.documents[uri]);
+    vec![publish_diagnostics(uri, diagnostics)]
+  }
+
+  fn document(&self, params: &Json) -> Result<&Document, (i64, String)> {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+    let unknown = || (INVALID_PARAMS, format!("unknown document `{uri}`"));
+    self.documents.get(uri).ok_or_else(unknown)
+  }
+
+  fn document_at(
+    &self,
+    params: &Json,
+  ) -> Result<(&Document, usize), (i64, String)> {
+    let document = self.document(params)?;
+    let offset = document.index().offset_of(&params["position"]);
+    let invalid = || (INVALID_PARAMS, "invalid position".to_owned());
+    Ok((document, offset.ok_or_else(invalid)?))
+  }
+}
+
+fn capabilities() -> Json {
+  json!({
+    "capabilities": {
+      "textDocumentSync": 2,
+      "documentSymbolProvider": true,
+      "hoverProvider": true,
+      "definitionProvider": true,
+      "documentFormattingProvider": true,
+      "semanticTokensProvider": {
+        "legend": { "tokenTypes": semantic::LEGEND, "tokenModifiers": [] },
+        "full": true,
+      },
+    },
+    "serverInfo": { "name": "atto-lsp", "version": env!("CARGO_PKG_VERSION") },
+  })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Json) -> Json {
+  json!({
+    "jsonrpc": "2.0",
+    "method": "textDocument/publishDiagnostics",
+    "params": { "uri": uri, "diagnostics": diagnostics },
+  })
+}
+
+fn diagnostics(document: &Document) -> Json {
+  let index = document.index();
+  let diagnostic = |err: &atto::parser::ParseError| {
+    json!({
+      "range": index.range(err.span),
+      "severity": 1,
+      "source": "atto",
+      "message": err.kind.to_string(),
+    })
+  };
+  document.parsed().errors.iter().map(diagnostic).collect()
+}
+
+// Hierarchical symbols of the keys, documents in lists are named by index
+fn symbols(index: &LineIndex, entries: &[Entry]) -> Json {
+  let symbol = |entry: &Entry| {
+    let mut symbol = json!({
+      "name": entry.key,
+      "kind": symbol_kind(&entry.value),
+      "range": index.range(entry.span()),
+      "selectionRange": index.range(entry.key_span),
+    });
+    let children = children(index, &entry.value);
+    if !children.is_empty() {
+      symbol["children"] = Json::Array(children);
+    }
+    symbol
+  };
+  entries.iter().map(symbol).collect()
+}
+
+fn children(index: &LineIndex, node: &Node) -> Vec<Json> {
+  match &node.kind {
+    NodeKind::Document(entries) => {
+      symbols(index, entries).as_array().cloned().unwrap_or_default()
+    }
+    NodeKind::List(list) => list
+      .iter()
+      .enumerate()
+      .filter(|(_, node)| matches!(node.kind, NodeKind::Document(_)))
+      .map(|(i, node)| {
+        json!({
+          "name": i.to_string(),
+          "kind": symbol_kind(node),
+          "range": index.range(node.span),
+          "selectionRange": index.range(node.span),
+          "children": children(index, node),
+        })
+      })
+      .collect(),
+    _ => vec![],
+  }
+}
+
+// LSP symbol kinds
+fn symbol_kind(node: &Node) -> u32 {
+  match node.kind {
+    NodeKind::Nil => 21,
+    NodeKind::Atom(_) => 15,
+    NodeKind::List(_) => 18,
+    NodeKind::Document(_) => 19,
+  }
+}
+
+fn hover(document: &Document, offset: usize) -> Json {
+  let root = &document.parsed().root;
+  match root.path_at(offset) {
+    Some(path) if !path.is_root() => {
+      let node = root.get_path(&path).unwrap_or(root);
+      json!({
+        "contents": { "kind": "markdown", "value": format!("`{path}`") },
+        "range": document.index().range(node.span),
+      })
+    }
+    _ => Json::Null,
+  }
+}
+
+// An atom value that is the path of an entry refers to that entry
+fn definition(document: &Document, offset: usize) -> Option<Json> {
+  let root = &document.parsed().root;
+  let node = root.get_path(&root.path_at(offset)?)?;
+  let NodeKind::Atom(atom) = &node.kind else {
+    return None;
+  };
+  if !node.span.contains(offset) {
+    return None;
+  }
+  let path = atom.parse::<Path>().ok().filter(|path| !path.is_root())?;
+  let entry = root.entry_at_path(&path)?;
+  Some(document.index().range(entry.key_span))
+}
+
+// The whole document is replaced by the pretty printed value with its doc
+// comments. Texts with errors or other comments are left alone, because
+// these would be lost.
+fn formatting(document: &Document) -> Json {
+  if !document.parsed().errors.is_empty() {
+    return json!([]);
+  }
+  let root = &document.parsed().root;
+  let docs = root.docs();
+  let comments = lex::tokens(document.text().as_bytes())
+    .filter(|token| token.rule_id == R_ID_comment)
+    .count();
+  let lines = docs.values().map(|doc| doc.split('\n').count());
+  if comments != lines.sum::<usize>() {
+    return json!([]);
+  }
+
+  let options = atto::format::Options::default();
+  let formatted =
+    atto::format::pretty_with_docs(&root.to_value(), &options, &docs);
+  if formatted == document.text() {
+    return json!([]);
+  }
+  let all = document.parsed().root.span;
+  json!([{ "range": document.index().range(all), "newText": formatted }])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn document(text: &str) -> Document { Document::new(text) }
+
+  #[test]
+  fn test_hover() {
+    let document = document("a: (b: (x y))");
+    let hover = hover(&document, 10);
+    assert_eq!(hover["contents"]["value"], "`a.b.1`");
+    assert_eq!(hover["range"]["start"]["character"], 10);
+  }
+
+  #[test]
+  fn test_definition() {
+    let document = document("a: (b: 1)\nc: a.b\nd: x");
+    let range = definition(&document, 15).unwrap();
+    assert_eq!(range["start"], json!({ "line": 0, "character": 4 }));
+    assert_eq!(definition(&document, 20), None);
+    assert_eq!(definition(&document, 11), None);
+  }
+
+  #[test]
+  fn test_formatting() {
+    let edits = formatting(&document("a:   b"));
+    assert_eq!(edits[0]["newText"], "a: b\n");
+    assert_eq!(formatting(&document("a: b\n")), json!([]));
+    let edits = formatting(&document("# c\n#\n#  d\na:   (\n# e\nb: c)"));
+    assert_eq!(edits[0]["newText"], "# c\n#\n#  d\na: (\n  # e\n  b: c\n)\n");
+    assert_eq!(formatting(&document("a: b # c\nd: e")), json!([]));
+    assert_eq!(formatting(&document("# c\n\na:   b")), json!([]));
+    assert_eq!(formatting(&document("a: (b")), json!([]));
+  }
+}
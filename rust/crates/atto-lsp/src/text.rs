@@ -0,0 +1,86 @@
+//! Conversion between byte offsets and LSP positions
+//!
+//! LSP positions are a zero-based line and a column in UTF-16 code units.
+
+use atto::syntax::Span;
+use serde_json::{json, Value as Json};
+
+/// The start offsets of the lines of a text
+pub struct LineIndex<'t> {
+  text:   &'t str,
+  starts: Vec<usize>,
+}
+
+impl<'t> LineIndex<'t> {
+  pub fn new(text: &'t str) -> LineIndex<'t> {
+    let newlines = text.match_indices('\n').map(|(i, _)| i + 1);
+    LineIndex { text, starts: std::iter::once(0).chain(newlines).collect() }
+  }
+
+  /// The line and UTF-16 column of the byte `offset`.
+  pub fn position(&self, offset: usize) -> (usize, usize) {
+    let offset = offset.min(self.text.len());
+    let line = self.starts.partition_point(|&start| start <= offset) - 1;
+    let before = &self.text[self.starts[line]..offset];
+    (line, before.encode_utf16().count())
+  }
+
+  /// The byte offset of a line and UTF-16 column, clamped to the line.
+  pub fn offset(&self, line: usize, column: usize) -> usize {
+    let Some(&start) = self.starts.get(line) else {
+      return self.text.len();
+    };
+    let end = self.starts.get(line + 1).map_or(self.text.len(), |&end| end);
+    let mut units = 0;
+    for (i, c) in self.text[start..end].char_indices() {
+      if units >= column || c == '\n' || c == '\r' {
+        return start + i;
+      }
+      units += c.len_utf16();
+    }
+    end
+  }
+
+  /// The offset of an LSP position object.
+  pub fn offset_of(&self, position: &Json) -> Option<usize> {
+    let line = position["line"].as_u64()?;
+    let column = position["character"].as_u64()?;
+    Some(self.offset(line as usize, column as usize))
+  }
+
+  /// An LSP range object.
+  pub fn range(&self, span: Span) -> Json {
+    json!({
+      "start": self.position_json(span.start),
+      "end": self.position_json(span.end),
+    })
+  }
+
+  fn position_json(&self, offset: usize) -> Json {
+    let (line, character) = self.position(offset);
+    json!({ "line": line, "character": character })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_position() {
+    let index = LineIndex::new("a: b\r\n💩: ö x\n");
+    assert_eq!(index.position(0), (0, 0));
+    assert_eq!(index.position(6), (1, 0));
+    assert_eq!(index.position(14), (1, 5));
+    assert_eq!(index.position(99), (2, 0));
+  }
+
+  #[test]
+  fn test_offset() {
+    let index = LineIndex::new("a: b\r\n💩: ö x\n");
+    assert_eq!(index.offset(1, 2), 10);
+    assert_eq!(index.offset(1, 5), 14);
+    assert_eq!(index.offset(0, 9), 4);
+    assert_eq!(index.offset(7, 0), 17);
+  }
+}
@@ -0,0 +1,155 @@
+//! Drive the server binary with a scripted client over stdio
+
+use serde_json::{json, Value as Json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+const URI: &str = "file:///test.atto";
+
+struct Client {
+  child:  Child,
+  stdin:  ChildStdin,
+  stdout: BufReader<ChildStdout>,
+  id:     u64,
+}
+
+impl Client {
+  fn start() -> Client {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_atto-lsp"))
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .spawn()
+      .unwrap();
+    let stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    Client { child, stdin, stdout, id: 0 }
+  }
+
+  fn send(&mut self, message: Json) {
+    let body = message.to_string();
+    write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    self.stdin.flush().unwrap();
+  }
+
+  fn receive(&mut self) -> Json {
+    let mut length = 0;
+    loop {
+      let mut line = String::new();
+      self.stdout.read_line(&mut line).unwrap();
+      match line.trim_end().split_once(": ") {
+        Some(("Content-Length", value)) => length = value.parse().unwrap(),
+        None if line.trim_end().is_empty() => break,
+        _ => {}
+      }
+    }
+    let mut body = vec![0; length];
+    self.stdout.read_exact(&mut body).unwrap();
+    serde_json::from_slice(&body).unwrap()
+  }
+
+  fn notify(&mut self, method: &str, params: Json) {
+    self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+  }
+
+  fn request(&mut self, method: &str, params: Json) -> Json {
+    self.id += 1;
+    let id = self.id;
+    self.send(json!({
+      "jsonrpc": "2.0",
+      "id": id,
+      "method": method,
+      "params": params,
+    }));
+    let response = self.receive();
+    assert_eq!(response["id"], id, "{response}");
+    response
+  }
+
+  fn open(&mut self, text: &str) -> Json {
+    let document = json!({ "uri": URI, "languageId": "atto", "text": text });
+    self.notify("textDocument/didOpen", json!({ "textDocument": document }));
+    self.receive()
+  }
+
+  fn at(&mut self, method: &str, line: u32, character: u32) -> Json {
+    let position = json!({ "line": line, "character": character });
+    let params =
+      json!({ "textDocument": { "uri": URI }, "position": position });
+    self.request(method, params)["result"].take()
+  }
+
+  fn exit(mut self) -> bool {
+    assert_eq!(self.request("shutdown", Json::Null)["result"], Json::Null);
+    self.notify("exit", Json::Null);
+    self.child.wait().unwrap().success()
+  }
+}
+
+fn document() -> Json { json!({ "textDocument": { "uri": URI } }) }
+
+#[test]
+fn test_session() {
+  let mut client = Client::start();
+  let init = client.request("initialize", json!({ "capabilities": {} }));
+  let capabilities = &init["result"]["capabilities"];
+  assert_eq!(capabilities["hoverProvider"], true);
+  assert_eq!(capabilities["semanticTokensProvider"]["full"], true);
+  client.notify("initialized", json!({}));
+
+  let published =
+    client.open("name: atto\nparts: (lexer: (axlex x)\nref: parts.lexer\n");
+  let diagnostics = &published["params"]["diagnostics"];
+  assert_eq!(diagnostics[0]["message"], "unclosed parenthesis");
+  assert_eq!(
+    diagnostics[0]["range"]["start"],
+    json!({ "line": 1, "character": 7 })
+  );
+
+  let range = json!({
+    "start": { "line": 1, "character": 23 },
+    "end": { "line": 1, "character": 23 },
+  });
+  let change = json!({
+    "textDocument": { "uri": URI, "version": 2 },
+    "contentChanges": [{ "range": range, "text": ")" }],
+  });
+  client.notify("textDocument/didChange", change);
+  assert_eq!(client.receive()["params"]["diagnostics"], json!([]));
+
+  let symbols = client.request("textDocument/documentSymbol", document());
+  let symbols = &symbols["result"];
+  assert_eq!(symbols[1]["name"], "parts");
+  assert_eq!(symbols[1]["children"][0]["name"], "lexer");
+  assert_eq!(symbols[2]["name"], "ref");
+
+  let hover = client.at("textDocument/hover", 1, 17);
+  assert_eq!(hover["contents"]["value"], "`parts.lexer.0`");
+
+  let definition = client.at("textDocument/definition", 2, 8);
+  assert_eq!(definition["uri"], URI);
+  assert_eq!(
+    definition["range"]["start"],
+    json!({ "line": 1, "character": 8 })
+  );
+
+  let edits = client.request("textDocument/formatting", document());
+  assert_eq!(edits["result"], json!([]));
+
+  let tokens = client.request("textDocument/semanticTokens/full", document());
+  let data = tokens["result"]["data"].as_array().unwrap();
+  assert_eq!(data[..5], [0, 0, 4, 3, 0]);
+
+  let unknown = client.request("textDocument/rename", document());
+  assert_eq!(unknown["error"]["code"], -32601);
+
+  client.notify("textDocument/didClose", document());
+  assert_eq!(client.receive()["params"]["diagnostics"], json!([]));
+  assert!(client.exit());
+}
+
+#[test]
+fn test_exit_without_shutdown() {
+  let mut client = Client::start();
+  client.notify("exit", Json::Null);
+  assert!(!client.child.wait().unwrap().success());
+}
@@ -0,0 +1,214 @@
+//! Compile-time embedding of atto files
+//!
+//! [`include_atto!`] parses a file while the crate is compiled, so that a
+//! syntax error in an embedded default config fails the build instead of
+//! the program at startup. [`test_atto!`] checks the conversion to a type
+//! in a test. The crate using the macros needs `atto` as a dependency as
+//! well.
+
+use atto::diagnostic::{Diagnostic, Renderer};
+use atto::Value;
+use proc_macro::{
+  Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream,
+  TokenTree,
+};
+use std::path::Path;
+
+/// Embed an atto file as a [`Value`], or as a type implementing
+/// `FromAtto` with `as`.
+///
+/// The path is relative to the directory of the crate's `Cargo.toml`. A
+/// syntax error is a compile error pointing to its line and column in the
+/// file.
+///
+/// The conversion to a type is not checked at compile time, as `FromAtto`
+/// implementations cannot run in the compiler: a file which parses but does
+/// not convert, for example with a missing field, still builds, and panics
+/// when the expression is evaluated, with the position of the offending
+/// value. Check the conversion with a test from [`test_atto!`] to fail
+/// `cargo test` instead of the program at startup.
+///
+/// ```ignore
+/// use atto_macros::include_atto;
+///
+/// let defaults: atto::Value = include_atto!("defaults.atto");
+/// let server = include_atto!("defaults.atto" as Server);
+/// ```
+#[proc_macro]
+pub fn include_atto(input: TokenStream) -> TokenStream {
+  match include(input.into_iter()) {
+    Ok(code) => code.parse().unwrap(),
+    Err(error) => error,
+  }
+}
+
+/// A test that the file of an [`include_atto!`] converts to its type.
+///
+/// Expands to a `#[test]` function with the given name, which fails with
+/// the position of the value which does not convert.
+///
+/// ```ignore
+/// atto_macros::test_atto!(defaults_convert, "defaults.atto" as Server);
+/// ```
+#[proc_macro]
+pub fn test_atto(input: TokenStream) -> TokenStream {
+  let mut tokens = input.into_iter();
+  let name = match tokens.next() {
+    Some(TokenTree::Ident(ident)) => ident,
+    _ => return error("expected a test name", Span::call_site()),
+  };
+  match tokens.next() {
+    Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => {}
+    _ => return error("expected `,` after the name", name.span()),
+  }
+  match include(tokens) {
+    Ok(code) => {
+      format!("#[test] fn {name}() {{ let _ = {code}; }}").parse().unwrap()
+    }
+    Err(error) => error,
+  }
+}
+
+// The code of the expansion for the tokens of `include_atto!`, or a
+// compile error
+fn include(
+  mut tokens: impl Iterator<Item = TokenTree>,
+) -> Result<String, TokenStream> {
+  let (name, span) = match tokens.next() {
+    Some(TokenTree::Literal(literal)) => {
+      (atto::macros::literal(&literal.to_string()), literal.span())
+    }
+    _ => return Err(error("expected a file name", Span::call_site())),
+  };
+  let target = match tokens.next() {
+    None => None,
+    Some(TokenTree::Ident(ident)) if ident.to_string() == "as" => {
+      Some(tokens.collect::<TokenStream>().to_string())
+    }
+    Some(token) => return Err(error("expected `as` and a type", token.span())),
+  };
+
+  let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+  let path = Path::new(&root).join(&name);
+  let text = std::fs::read_to_string(&path).map_err(|err| {
+    error(&format!("cannot read {}: {err}", path.display()), span)
+  })?;
+  expand(&name, &path.to_string_lossy(), &text, target)
+    .map_err(|message| error(&message, span))
+}
+
+// The code of the expansion for the file `name` at `path`, or the message
+// of its syntax error
+fn expand(
+  name: &str,
+  path: &str,
+  text: &str,
+  target: Option<String>,
+) -> Result<String, String> {
+  let value = atto::parse(text).map_err(|err| {
+    let diagnostic = Diagnostic::from(&err);
+    let rendered = Renderer::new().origin(name).render(&diagnostic, text);
+    // The compiler writes the first line with its own severity
+    let snippet = rendered.split_once('\n').map_or("", |(_, rest)| rest);
+    format!("{}\n{}", err.kind, snippet.trim_end())
+  })?;
+
+  // Reading the file with `include_str!` rebuilds the crate when it changes
+  let mut code =
+    format!("{{ const TEXT: &str = ::std::include_str!({path:?});");
+  code.push_str(&format!("let value = {};", value_code(&value)));
+  match target {
+    None => code.push_str("value }"),
+    Some(target) => code.push_str(&format!(
+      "match <{target} as ::atto::decode::FromAtto>::from_atto(&value) {{
+        Ok(value) => value,
+        Err(err) => {{
+          let root = ::atto::parser::parse_tree(TEXT).root;
+          ::std::panic!(\"{{}}: {{}}\", {name:?}, err.locate(TEXT, &root))
+        }}
+      }} }}"
+    )),
+  }
+  Ok(code)
+}
+
+// An expression constructing `value`
+fn value_code(value: &Value) -> String {
+  match value {
+    Value::Nil => "::atto::Value::Nil".to_owned(),
+    Value::Atom(atom) => {
+      format!("::atto::Value::Atom(::std::string::String::from({atom:?}))")
+    }
+    Value::List(list) => {
+      let elements = list.iter().map(value_code).collect::<Vec<_>>();
+      format!("::atto::Value::List(::std::vec![{}])", elements.join(", "))
+    }
+    Value::Document(document) if document.is_empty() => {
+      "::atto::Value::Document(::atto::value::Document::new())".to_owned()
+    }
+    Value::Document(document) => {
+      let entries = document
+        .iter()
+        .map(|(key, value)| {
+          let value = value_code(value);
+          format!("(::std::string::String::from({key:?}), {value})")
+        })
+        .collect::<Vec<_>>();
+      format!(
+        "::atto::Value::Document(<::atto::value::Document as \
+         ::std::iter::FromIterator<_>>::from_iter([{}]))",
+        entries.join(", ")
+      )
+    }
+  }
+}
+
+// `compile_error!(message)` at `span`
+fn error(message: &str, span: Span) -> TokenStream {
+  let mut message = Literal::string(message);
+  message.set_span(span);
+  let mut bang = Punct::new('!', Spacing::Alone);
+  bang.set_span(span);
+  let mut arguments =
+    Group::new(Delimiter::Parenthesis, TokenTree::from(message).into());
+  arguments.set_span(span);
+  let tokens: [TokenTree; 3] =
+    [Ident::new("compile_error", span).into(), bang.into(), arguments.into()];
+  tokens.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_value_code() {
+    let value = atto::parse("a: (\"x\\\"y\" z) b: ()").unwrap();
+    let Value::Document(document) = value else { panic!() };
+    assert_eq!(
+      value_code(&document["a"]),
+      "::atto::Value::List(::std::vec![\
+       ::atto::Value::Atom(::std::string::String::from(\"x\\\"y\")), \
+       ::atto::Value::Atom(::std::string::String::from(\"z\"))])"
+    );
+    assert_eq!(
+      value_code(&document["b"]),
+      "::atto::Value::List(::std::vec![])"
+    );
+  }
+
+  #[test]
+  fn test_expand_error() {
+    let text = "a: 1\nb: (c d\n";
+    let message = expand("defaults.atto", "/defaults.atto", text, None);
+    assert_eq!(
+      message.unwrap_err(),
+      "\
+unclosed parenthesis
+ --> defaults.atto:2:4
+  |
+2 | b: (c d
+  |    ^"
+    );
+  }
+}
@@ -0,0 +1,51 @@
+use atto::decode::{document, field, DecodeError, FromAtto};
+use atto::Value;
+use atto_macros::{include_atto, test_atto};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+struct Server {
+  port:    u16,
+  timeout: Duration,
+  hosts:   Vec<String>,
+}
+
+impl FromAtto for Server {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> {
+    let server = document(value)?;
+    Ok(Server {
+      port:    field(server, "port")?,
+      timeout: field(server, "timeout")?,
+      hosts:   field(server, "hosts")?,
+    })
+  }
+}
+
+#[test]
+fn test_value() {
+  let value: Value = include_atto!("tests/defaults.atto");
+  let text = include_str!("defaults.atto");
+  assert_eq!(value, atto::parse(text).unwrap());
+}
+
+#[test]
+fn test_from_atto() {
+  let server = include_atto!("tests/defaults.atto" as Server);
+  assert_eq!(server, Server {
+    port:    8080,
+    timeout: Duration::from_secs(90),
+    hosts:   vec!["a.example.com".into(), "b.example.com".into()],
+  });
+}
+
+#[test]
+#[should_panic(
+  expected = "tests/defaults.atto: port: invalid integer: out of range at 2:7"
+)]
+fn test_from_atto_error() {
+  include_atto!(
+    "tests/defaults.atto" as std::collections::BTreeMap<String, u8>
+  );
+}
+
+test_atto!(test_converts, "tests/defaults.atto" as Server);
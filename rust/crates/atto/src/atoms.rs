@@ -0,0 +1,1063 @@
+//! Typed interpretation of atoms: durations, sizes, timestamps and more
+//!
+//! atto only knows strings, so `30s`, `10MiB` or `2023-10-14T15:06:05Z` are
+//! atoms like any other. [`FromAtom`] parses an atom into a typed value and
+//! [`ToAtom`] writes it back such that parsing gives the same value again.
+//! Both are implemented for numbers, `bool`, [`Duration`], the IP and socket
+//! addresses of std and the types of this module. The [`atom`] module makes
+//! them usable with serde.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::IntErrorKind;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A value which can be parsed from an atom.
+pub trait FromAtom: Sized {
+  /// A description of the expected atom for error messages
+  const EXPECTED: &'static str;
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError>;
+}
+
+/// A value which can be written as an atom.
+pub trait ToAtom {
+  fn to_atom(&self) -> String;
+}
+
+/// An atom which could not be parsed, with the byte offset in the atom
+/// where it became invalid
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AtomError {
+  pub expected: &'static str,
+  pub offset:   usize,
+  pub reason:   Reason,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reason {
+  Empty,
+  Expected(&'static str),
+  UnknownUnit(String),
+  OutOfRange,
+}
+
+impl fmt::Display for Reason {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Reason::Empty => write!(f, "empty atom"),
+      Reason::Expected(what) => write!(f, "expected {what}"),
+      Reason::UnknownUnit(unit) => write!(f, "unknown unit `{unit}`"),
+      Reason::OutOfRange => write!(f, "out of range"),
+    }
+  }
+}
+
+impl fmt::Display for AtomError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid {}: {}", self.expected, self.reason)
+  }
+}
+
+impl std::error::Error for AtomError {}
+
+// Reads an atom from left to right, keeping the offset for errors
+struct Cursor<'a> {
+  atom:     &'a str,
+  at:       usize,
+  expected: &'static str,
+}
+
+impl<'a> Cursor<'a> {
+  fn new<T: FromAtom>(atom: &'a str) -> Result<Cursor<'a>, AtomError> {
+    let cursor = Cursor { atom, at: 0, expected: T::EXPECTED };
+    match atom.is_empty() {
+      true => Err(cursor.error(Reason::Empty)),
+      false => Ok(cursor),
+    }
+  }
+
+  fn rest(&self) -> &'a str { &self.atom[self.at..] }
+
+  fn is_end(&self) -> bool { self.at == self.atom.len() }
+
+  fn error(&self, reason: Reason) -> AtomError {
+    AtomError { expected: self.expected, offset: self.at, reason }
+  }
+
+  fn error_at(&self, offset: usize, reason: Reason) -> AtomError {
+    AtomError { expected: self.expected, offset, reason }
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let eaten = self.rest().starts_with(c);
+    if eaten {
+      self.at += c.len_utf8();
+    }
+    eaten
+  }
+
+  fn expect(&mut self, c: char, what: &'static str) -> Result<(), AtomError> {
+    match self.eat(c) {
+      true => Ok(()),
+      false => Err(self.error(Reason::Expected(what))),
+    }
+  }
+
+  fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+    let rest = self.rest();
+    let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+    self.at += len;
+    &rest[..len]
+  }
+
+  fn digits(&mut self) -> &'a str { self.take_while(|c| c.is_ascii_digit()) }
+
+  // A number of exactly `len` digits
+  fn fixed(
+    &mut self,
+    len: usize,
+    what: &'static str,
+  ) -> Result<u32, AtomError> {
+    let start = self.at;
+    let digits = self.digits();
+    if digits.len() != len {
+      return Err(self.error_at(start, Reason::Expected(what)));
+    }
+    Ok(digits.parse().unwrap())
+  }
+
+  // A field of `len` digits which is at most `max`
+  fn field(
+    &mut self,
+    len: usize,
+    max: u32,
+    what: &'static str,
+  ) -> Result<u32, AtomError> {
+    let start = self.at;
+    let value = self.fixed(len, what)?;
+    match value <= max {
+      true => Ok(value),
+      false => Err(self.error_at(start, Reason::OutOfRange)),
+    }
+  }
+
+  // A decimal number as its integer part and its fraction digits
+  fn decimal(&mut self) -> Result<(u128, &'a str), AtomError> {
+    let start = self.at;
+    let integer = self.digits();
+    let fraction = match self.eat('.') {
+      true => self.digits(),
+      false => "",
+    };
+    if integer.is_empty() && fraction.is_empty() {
+      return Err(self.error_at(start, Reason::Expected("number")));
+    }
+    match integer {
+      "" => Ok((0, fraction)),
+      _ => match integer.parse() {
+        Ok(integer) => Ok((integer, fraction)),
+        Err(_) => Err(self.error_at(start, Reason::OutOfRange)),
+      },
+    }
+  }
+
+  fn end(&self) -> Result<(), AtomError> {
+    match self.is_end() {
+      true => Ok(()),
+      false => Err(self.error(Reason::Expected("end of atom"))),
+    }
+  }
+}
+
+// `integer.fraction` times `unit`, rounded down
+fn scale(integer: u128, fraction: &str, unit: u128) -> Option<u128> {
+  let fraction = &fraction[..fraction.len().min(19)];
+  let numerator = fraction.parse::<u128>().unwrap_or(0) * unit;
+  let part = numerator / 10u128.pow(fraction.len() as u32);
+  integer.checked_mul(unit)?.checked_add(part)
+}
+
+impl FromAtom for String {
+  const EXPECTED: &'static str = "string";
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError> { Ok(atom.to_owned()) }
+}
+
+impl ToAtom for String {
+  fn to_atom(&self) -> String { self.clone() }
+}
+
+impl FromAtom for bool {
+  const EXPECTED: &'static str = "boolean";
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError> {
+    match atom {
+      "true" => Ok(true),
+      "false" => Ok(false),
+      _ => {
+        let reason = Reason::Expected("`true` or `false`");
+        Err(AtomError { expected: Self::EXPECTED, offset: 0, reason })
+      }
+    }
+  }
+}
+
+macro_rules! from_str_atoms {
+  ($expected:literal, $reason:literal: $($type:ty),*) => {$(
+    impl FromAtom for $type {
+      const EXPECTED: &'static str = $expected;
+
+      fn from_atom(atom: &str) -> Result<Self, AtomError> {
+        let reason = match atom.is_empty() {
+          true => Reason::Empty,
+          false => Reason::Expected($reason),
+        };
+        atom
+          .parse()
+          .map_err(|_| AtomError { expected: Self::EXPECTED, offset: 0, reason })
+      }
+    }
+  )*};
+}
+
+macro_rules! integer_atoms {
+  ($($type:ty),*) => {$(
+    impl FromAtom for $type {
+      const EXPECTED: &'static str = "integer";
+
+      fn from_atom(atom: &str) -> Result<Self, AtomError> {
+        atom.parse().map_err(|err: std::num::ParseIntError| {
+          let reason = match err.kind() {
+            IntErrorKind::Empty => Reason::Empty,
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+              Reason::OutOfRange
+            }
+            _ => Reason::Expected("digits"),
+          };
+          AtomError { expected: Self::EXPECTED, offset: 0, reason }
+        })
+      }
+    }
+  )*};
+}
+
+integer_atoms!(u8, u16, u32, u64, u128, usize);
+integer_atoms!(i8, i16, i32, i64, i128, isize);
+from_str_atoms!("number", "decimal number": f32, f64);
+from_str_atoms!("IP address", "IPv4 or IPv6 address": IpAddr);
+from_str_atoms!("IPv4 address", "four decimal octets": Ipv4Addr);
+from_str_atoms!("IPv6 address", "hexadecimal groups": Ipv6Addr);
+from_str_atoms!("socket address", "address and port": SocketAddr);
+
+macro_rules! display_atoms {
+  ($($type:ty),*) => {$(
+    impl ToAtom for $type {
+      fn to_atom(&self) -> String { self.to_string() }
+    }
+  )*};
+}
+
+display_atoms!(bool, u8, u16, u32, u64, u128, usize);
+display_atoms!(i8, i16, i32, i64, i128, isize, f32, f64);
+display_atoms!(IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr);
+
+const DURATION_UNITS: [(&str, u128); 8] = [
+  ("d", 86_400_000_000_000),
+  ("h", 3_600_000_000_000),
+  ("m", 60_000_000_000),
+  ("s", 1_000_000_000),
+  ("ms", 1_000_000),
+  ("us", 1_000),
+  ("µs", 1_000),
+  ("ns", 1),
+];
+
+/// A duration is a sequence of decimal numbers with units, the units are
+/// `d`, `h`, `m`, `s`, `ms`, `us` or `µs` and `ns`. A bare `0` is allowed.
+///
+/// ```
+/// # use atto::atoms::{FromAtom, ToAtom};
+/// # use std::time::Duration;
+/// let duration = Duration::from_atom("1h30m").unwrap();
+/// assert_eq!(duration, Duration::from_secs(5400));
+/// assert_eq!(Duration::from_atom("1.5s").unwrap().to_atom(), "1s500ms");
+/// ```
+impl FromAtom for Duration {
+  const EXPECTED: &'static str = "duration";
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError> {
+    let mut cursor = Cursor::new::<Self>(atom)?;
+    if atom == "0" {
+      return Ok(Duration::ZERO);
+    }
+
+    let mut nanos = 0u128;
+    while !cursor.is_end() {
+      let start = cursor.at;
+      let (integer, fraction) = cursor.decimal()?;
+      let unit_start = cursor.at;
+      let unit = cursor.take_while(|c| c.is_alphabetic());
+      let Some(&(_, scale_by)) =
+        DURATION_UNITS.iter().find(|(name, _)| *name == unit)
+      else {
+        let reason = match unit.is_empty() {
+          true => Reason::Expected("unit"),
+          false => Reason::UnknownUnit(unit.to_owned()),
+        };
+        return Err(cursor.error_at(unit_start, reason));
+      };
+      nanos = scale(integer, fraction, scale_by)
+        .and_then(|part| nanos.checked_add(part))
+        .ok_or(cursor.error_at(start, Reason::OutOfRange))?;
+    }
+
+    let seconds = u64::try_from(nanos / 1_000_000_000)
+      .map_err(|_| cursor.error_at(0, Reason::OutOfRange))?;
+    Ok(Duration::new(seconds, (nanos % 1_000_000_000) as u32))
+  }
+}
+
+impl ToAtom for Duration {
+  fn to_atom(&self) -> String {
+    if self.is_zero() {
+      return "0s".to_owned();
+    }
+    let mut nanos = self.as_nanos();
+    let mut atom = String::new();
+    // Days are not written, `36h` is easier to read than `1d12h`
+    for (unit, scale) in DURATION_UNITS.iter().skip(1).filter(|u| u.0 != "µs")
+    {
+      if nanos >= *scale {
+        atom.push_str(&format!("{}{unit}", nanos / scale));
+        nanos %= scale;
+      }
+    }
+    atom
+  }
+}
+
+/// A number of bytes, written with an SI or IEC suffix
+///
+/// The suffixes are `B`, the SI ones `kB`, `MB`, `GB`, `TB`, `PB` and `EB`
+/// with powers of 1000 and the IEC ones `KiB`, `MiB`, `GiB`, `TiB`, `PiB`
+/// and `EiB` with powers of 1024. Without a suffix the number is in bytes.
+/// Fractions are rounded down to whole bytes.
+///
+/// ```
+/// # use atto::atoms::{ByteSize, FromAtom, ToAtom};
+/// assert_eq!(ByteSize::from_atom("10MiB").unwrap(), ByteSize(10 << 20));
+/// assert_eq!(ByteSize::from_atom("1.5kB").unwrap(), ByteSize(1500));
+/// assert_eq!(ByteSize(3 << 30).to_atom(), "3GiB");
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ByteSize(pub u64);
+
+const BYTE_UNITS: [(&str, u128); 13] = [
+  ("EiB", 1 << 60),
+  ("EB", 1_000_000_000_000_000_000),
+  ("PiB", 1 << 50),
+  ("PB", 1_000_000_000_000_000),
+  ("TiB", 1 << 40),
+  ("TB", 1_000_000_000_000),
+  ("GiB", 1 << 30),
+  ("GB", 1_000_000_000),
+  ("MiB", 1 << 20),
+  ("MB", 1_000_000),
+  ("KiB", 1 << 10),
+  ("kB", 1_000),
+  ("B", 1),
+];
+
+impl FromAtom for ByteSize {
+  const EXPECTED: &'static str = "byte size";
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError> {
+    let mut cursor = Cursor::new::<Self>(atom)?;
+    let (integer, fraction) = cursor.decimal()?;
+    let unit_start = cursor.at;
+    let unit = cursor.take_while(|c| c.is_alphabetic());
+    let scale_by = match BYTE_UNITS.iter().find(|(name, _)| *name == unit) {
+      Some((_, scale_by)) => *scale_by,
+      None if unit.is_empty() => 1,
+      None => {
+        let reason = Reason::UnknownUnit(unit.to_owned());
+        return Err(cursor.error_at(unit_start, reason));
+      }
+    };
+    cursor.end()?;
+
+    scale(integer, fraction, scale_by)
+      .and_then(|bytes| u64::try_from(bytes).ok())
+      .map(ByteSize)
+      .ok_or(cursor.error_at(0, Reason::OutOfRange))
+  }
+}
+
+impl ToAtom for ByteSize {
+  /// The size with the unit which gives the smallest whole number.
+  fn to_atom(&self) -> String {
+    let bytes = u128::from(self.0);
+    let unit =
+      BYTE_UNITS.iter().find(|(_, scale)| bytes.is_multiple_of(*scale));
+    match unit {
+      Some((unit, scale)) if bytes > 0 => format!("{}{unit}", bytes / scale),
+      _ => format!("{bytes}B"),
+    }
+  }
+}
+
+impl fmt::Display for ByteSize {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.to_atom())
+  }
+}
+
+/// A percentage like `12.5%`, the field is the number before the `%`
+///
+/// ```
+/// # use atto::atoms::{FromAtom, Percent};
+/// let percent = Percent::from_atom("12.5%").unwrap();
+/// assert_eq!(percent, Percent(12.5));
+/// assert_eq!(percent.ratio(), 0.125);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Percent(pub f64);
+
+impl Percent {
+  /// The percentage as a fraction of one.
+  pub fn ratio(self) -> f64 { self.0 / 100.0 }
+}
+
+impl FromAtom for Percent {
+  const EXPECTED: &'static str = "percentage";
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError> {
+    let mut cursor = Cursor::new::<Self>(atom)?;
+    cursor.eat('-');
+    cursor.decimal()?;
+    cursor.expect('%', "`%`")?;
+    cursor.end()?;
+    let number = atom[..atom.len() - 1].parse::<f64>();
+    match number {
+      Ok(number) if number.is_finite() => Ok(Percent(number)),
+      _ => Err(cursor.error_at(0, Reason::OutOfRange)),
+    }
+  }
+}
+
+impl ToAtom for Percent {
+  fn to_atom(&self) -> String { format!("{}%", self.0) }
+}
+
+impl fmt::Display for Percent {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}%", self.0)
+  }
+}
+
+/// A point in time with the UTC offset it was written in, as in RFC 3339
+///
+/// Equal points in time with different offsets are different timestamps.
+/// Leap seconds are not supported.
+///
+/// ```
+/// # use atto::atoms::{FromAtom, Timestamp, ToAtom};
+/// let timestamp = Timestamp::from_atom("2023-10-14T15:06:05Z").unwrap();
+/// assert_eq!(timestamp.unix_seconds(), 1697295965);
+///
+/// let local = Timestamp::from_atom("2023-10-14t17:06:05.25+02:00").unwrap();
+/// assert_eq!(local.unix_seconds(), 1697295965);
+/// assert_eq!(local.to_atom(), "2023-10-14T17:06:05.25+02:00");
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Timestamp {
+  seconds: i64,
+  nanos:   u32,
+  offset:  i16,
+}
+
+impl Timestamp {
+  /// A timestamp in UTC. `nanos` must be less than a second.
+  pub fn from_unix(seconds: i64, nanos: u32) -> Timestamp {
+    assert!(nanos < 1_000_000_000, "nanoseconds out of range");
+    Timestamp { seconds, nanos, offset: 0 }
+  }
+
+  /// The same point in time written with `minutes` east of UTC.
+  pub fn with_offset(self, minutes: i16) -> Timestamp {
+    assert!(minutes.abs() < 24 * 60, "UTC offset out of range");
+    Timestamp { offset: minutes, ..self }
+  }
+
+  /// Seconds since 1970-01-01T00:00:00Z
+  pub fn unix_seconds(&self) -> i64 { self.seconds }
+
+  /// Nanoseconds after [`unix_seconds`](Self::unix_seconds)
+  pub fn nanos(&self) -> u32 { self.nanos }
+
+  /// The UTC offset in minutes
+  pub fn offset_minutes(&self) -> i16 { self.offset }
+
+  pub fn to_system_time(&self) -> SystemTime {
+    let seconds = Duration::from_secs(self.seconds.unsigned_abs());
+    let nanos = Duration::from_nanos(self.nanos.into());
+    match self.seconds < 0 {
+      true => UNIX_EPOCH - seconds + nanos,
+      false => UNIX_EPOCH + seconds + nanos,
+    }
+  }
+}
+
+impl From<SystemTime> for Timestamp {
+  fn from(time: SystemTime) -> Self {
+    match time.duration_since(UNIX_EPOCH) {
+      Ok(since) => {
+        Timestamp::from_unix(since.as_secs() as i64, since.subsec_nanos())
+      }
+      Err(err) => {
+        let before = err.duration();
+        let nanos = before.subsec_nanos();
+        let seconds = -(before.as_secs() as i64);
+        match nanos {
+          0 => Timestamp::from_unix(seconds, 0),
+          _ => Timestamp::from_unix(seconds - 1, 1_000_000_000 - nanos),
+        }
+      }
+    }
+  }
+}
+
+impl FromAtom for Timestamp {
+  const EXPECTED: &'static str = "RFC 3339 timestamp";
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError> {
+    let mut cursor = Cursor::new::<Self>(atom)?;
+    let year = cursor.field(4, 9999, "four digit year")?;
+    cursor.expect('-', "`-`")?;
+    let month_start = cursor.at;
+    let month = cursor.field(2, 12, "two digit month")?;
+    cursor.expect('-', "`-`")?;
+    let day_start = cursor.at;
+    let day = cursor.field(2, 31, "two digit day")?;
+    if month == 0 {
+      return Err(cursor.error_at(month_start, Reason::OutOfRange));
+    }
+    if day == 0 || day > days_in_month(year, month) {
+      return Err(cursor.error_at(day_start, Reason::OutOfRange));
+    }
+    if !(cursor.eat('T') || cursor.eat('t') || cursor.eat(' ')) {
+      return Err(cursor.error(Reason::Expected("`T`")));
+    }
+    let hour = cursor.field(2, 23, "two digit hour")?;
+    cursor.expect(':', "`:`")?;
+    let minute = cursor.field(2, 59, "two digit minute")?;
+    cursor.expect(':', "`:`")?;
+    let second = cursor.field(2, 59, "two digit second")?;
+
+    let mut nanos = 0;
+    if cursor.eat('.') {
+      let start = cursor.at;
+      let fraction = cursor.digits();
+      if fraction.is_empty() {
+        return Err(cursor.error_at(start, Reason::Expected("digits")));
+      }
+      let fraction = format!("{:0<9}", &fraction[..fraction.len().min(9)]);
+      nanos = fraction.parse().unwrap();
+    }
+
+    let offset = match cursor.rest().chars().next() {
+      Some('Z' | 'z') => {
+        cursor.at += 1;
+        0
+      }
+      Some(sign @ ('+' | '-')) => {
+        cursor.at += 1;
+        let hours = cursor.field(2, 23, "two digit hour")?;
+        cursor.expect(':', "`:`")?;
+        let minutes = cursor.field(2, 59, "two digit minute")?;
+        let offset = (hours * 60 + minutes) as i16;
+        if sign == '-' {
+          -offset
+        } else {
+          offset
+        }
+      }
+      _ => return Err(cursor.error(Reason::Expected("`Z` or UTC offset"))),
+    };
+    cursor.end()?;
+
+    let days = days_from_civil(year.into(), month, day);
+    let local = days * 86_400 + i64::from(hour * 3600 + minute * 60 + second);
+    let seconds = local - i64::from(offset) * 60;
+    Ok(Timestamp { seconds, nanos, offset })
+  }
+}
+
+impl ToAtom for Timestamp {
+  fn to_atom(&self) -> String {
+    let local = self.seconds + i64::from(self.offset) * 60;
+    let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+    let time = local.rem_euclid(86_400);
+    let (hour, minute, second) = (time / 3600, time / 60 % 60, time % 60);
+    let mut atom = format!(
+      "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}"
+    );
+    if self.nanos > 0 {
+      let fraction = format!("{:09}", self.nanos);
+      atom.push('.');
+      atom.push_str(fraction.trim_end_matches('0'));
+    }
+    match self.offset {
+      0 => atom.push('Z'),
+      offset => {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.unsigned_abs();
+        atom.push_str(&format!("{sign}{:02}:{:02}", offset / 60, offset % 60));
+      }
+    }
+    atom
+  }
+}
+
+impl fmt::Display for Timestamp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.to_atom())
+  }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+  let leap = year.is_multiple_of(4)
+    && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+  match month {
+    2 if leap => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let month = i64::from(month);
+  let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+  let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+  let day_of_era =
+    year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let days = days + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days - era * 146_097;
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+    - day_of_era / 146_096)
+    / 365;
+  let day_of_year =
+    day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+  let month =
+    if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  (year, month as u32, day)
+}
+
+/// A semantic version as in semver 2.0, like `1.2.3-rc.1+build.5`
+///
+/// Versions are ordered by precedence, with the build metadata only
+/// deciding between otherwise equal versions.
+///
+/// ```
+/// # use atto::atoms::{FromAtom, Version};
+/// let version = Version::from_atom("1.2.3-rc.1+build.5").unwrap();
+/// assert_eq!((version.major, version.minor, version.patch), (1, 2, 3));
+/// assert_eq!(version.pre, "rc.1");
+/// assert!(version < Version::from_atom("1.2.3").unwrap());
+/// ```
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Version {
+  pub major: u64,
+  pub minor: u64,
+  pub patch: u64,
+  /// The dot separated pre-release identifiers, empty for a release
+  pub pre:   String,
+  /// The dot separated build metadata
+  pub build: String,
+}
+
+impl Version {
+  pub fn new(major: u64, minor: u64, patch: u64) -> Version {
+    Version { major, minor, patch, ..Version::default() }
+  }
+}
+
+impl FromAtom for Version {
+  const EXPECTED: &'static str = "semantic version";
+
+  fn from_atom(atom: &str) -> Result<Self, AtomError> {
+    let mut cursor = Cursor::new::<Self>(atom)?;
+    let number = |cursor: &mut Cursor, dot| {
+      if dot {
+        cursor.expect('.', "`.`")?;
+      }
+      let start = cursor.at;
+      let digits = cursor.digits();
+      match digits.parse() {
+        Ok(_) if digits.len() > 1 && digits.starts_with('0') => {
+          Err(cursor.error_at(start, Reason::Expected("no leading zero")))
+        }
+        Ok(number) => Ok(number),
+        Err(_) if digits.is_empty() => {
+          Err(cursor.error_at(start, Reason::Expected("digits")))
+        }
+        Err(_) => Err(cursor.error_at(start, Reason::OutOfRange)),
+      }
+    };
+    let major = number(&mut cursor, false)?;
+    let minor = number(&mut cursor, true)?;
+    let patch = number(&mut cursor, true)?;
+
+    let identifiers = |cursor: &mut Cursor<'_>, numeric_check| {
+      let start = cursor.at;
+      loop {
+        let identifier_start = cursor.at;
+        let identifier =
+          cursor.take_while(|c| c.is_ascii_alphanumeric() || c == '-');
+        let numeric = identifier.bytes().all(|b| b.is_ascii_digit());
+        if identifier.is_empty() {
+          let reason = Reason::Expected("identifier");
+          return Err(cursor.error_at(identifier_start, reason));
+        }
+        if numeric_check
+          && numeric
+          && identifier.len() > 1
+          && identifier.starts_with('0')
+        {
+          let reason = Reason::Expected("no leading zero");
+          return Err(cursor.error_at(identifier_start, reason));
+        }
+        if !cursor.eat('.') {
+          return Ok(cursor.atom[start..cursor.at].to_owned());
+        }
+      }
+    };
+    let pre = match cursor.eat('-') {
+      true => identifiers(&mut cursor, true)?,
+      false => String::new(),
+    };
+    let build = match cursor.eat('+') {
+      true => identifiers(&mut cursor, false)?,
+      false => String::new(),
+    };
+    cursor.end()?;
+
+    Ok(Version { major, minor, patch, pre, build })
+  }
+}
+
+impl ToAtom for Version {
+  fn to_atom(&self) -> String { self.to_string() }
+}
+
+impl fmt::Display for Version {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+    if !self.pre.is_empty() {
+      write!(f, "-{}", self.pre)?;
+    }
+    if !self.build.is_empty() {
+      write!(f, "+{}", self.build)?;
+    }
+    Ok(())
+  }
+}
+
+impl Ord for Version {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let numbers = |v: &Version| (v.major, v.minor, v.patch);
+    numbers(self)
+      .cmp(&numbers(other))
+      .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => compare_pre(&self.pre, &other.pre),
+      })
+      .then_with(|| self.build.cmp(&other.build))
+  }
+}
+
+impl PartialOrd for Version {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+// Numeric identifiers compare as numbers and before alphanumeric ones
+fn compare_pre(a: &str, b: &str) -> Ordering {
+  let key = |identifier: &str| match identifier.parse::<u64>() {
+    Ok(number) => (0, number, String::new()),
+    Err(_) => (1, 0, identifier.to_owned()),
+  };
+  a.split('.').map(key).cmp(b.split('.').map(key))
+}
+
+/// Serde support for atoms with [`FromAtom`] and [`ToAtom`]
+///
+/// Use it as `#[serde(with = "atto::atoms::atom")]` on a field, for
+/// example for a [`Duration`] which serde would otherwise expect as a
+/// struct. The types of this module implement `Serialize` and
+/// `Deserialize` as atoms themselves.
+pub mod atom {
+  use super::{FromAtom, ToAtom};
+  use serde::{de, Deserializer, Serializer};
+  use std::fmt;
+  use std::marker::PhantomData;
+
+  pub fn serialize<T: ToAtom, S: Serializer>(
+    value: &T,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_atom())
+  }
+
+  pub fn deserialize<'de, T: FromAtom, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<T, D::Error> {
+    deserializer.deserialize_str(AtomVisitor(PhantomData))
+  }
+
+  pub(super) struct AtomVisitor<T>(pub(super) PhantomData<T>);
+
+  impl<T: FromAtom> de::Visitor<'_> for AtomVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "a {}", T::EXPECTED)
+    }
+
+    fn visit_str<E: de::Error>(self, atom: &str) -> Result<T, E> {
+      T::from_atom(atom).map_err(E::custom)
+    }
+  }
+}
+
+macro_rules! serde_atoms {
+  ($($type:ty),*) => {$(
+    impl serde::Serialize for $type {
+      fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+      ) -> Result<S::Ok, S::Error> {
+        atom::serialize(self, serializer)
+      }
+    }
+
+    impl<'de> serde::Deserialize<'de> for $type {
+      fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+      ) -> Result<Self, D::Error> {
+        atom::deserialize(deserializer)
+      }
+    }
+  )*};
+}
+
+serde_atoms!(ByteSize, Percent, Timestamp, Version);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::de::IntoDeserializer;
+
+  fn error<T: FromAtom + fmt::Debug>(atom: &str) -> (usize, String) {
+    let err = T::from_atom(atom).unwrap_err();
+    (err.offset, err.to_string())
+  }
+
+  #[test]
+  fn test_duration() {
+    let duration = |atom| Duration::from_atom(atom).unwrap();
+    assert_eq!(duration("0"), Duration::ZERO);
+    assert_eq!(duration("2d"), Duration::from_secs(2 * 86_400));
+    assert_eq!(duration(".5ms"), Duration::from_micros(500));
+    assert_eq!(duration("1µs1ns"), Duration::from_nanos(1001));
+    assert_eq!(duration("36h1ns").to_atom(), "36h1ns");
+    assert_eq!(Duration::ZERO.to_atom(), "0s");
+
+    assert_eq!(
+      error::<Duration>(""),
+      (0, "invalid duration: empty atom".into())
+    );
+    assert_eq!(
+      error::<Duration>("30x"),
+      (2, "invalid duration: unknown unit `x`".into())
+    );
+    assert_eq!(
+      error::<Duration>("1m30"),
+      (4, "invalid duration: expected unit".into())
+    );
+    assert_eq!(error::<Duration>("1m-3s").0, 2);
+    assert_eq!(
+      error::<Duration>("999999999999999d").1,
+      "invalid duration: out of range"
+    );
+  }
+
+  #[test]
+  fn test_byte_size() {
+    let size = |atom| ByteSize::from_atom(atom).unwrap().0;
+    assert_eq!(size("512"), 512);
+    assert_eq!(size("512B"), 512);
+    assert_eq!(size("2kB"), 2000);
+    assert_eq!(size("1.5KiB"), 1536);
+    assert_eq!(size("6EiB"), 6 << 60);
+    assert_eq!(ByteSize(0).to_atom(), "0B");
+    assert_eq!(ByteSize(1500).to_atom(), "1500B");
+    assert_eq!(ByteSize(1_024_000).to_atom(), "1000KiB");
+    assert_eq!(ByteSize(5_000_000).to_atom(), "5MB");
+
+    assert_eq!(
+      error::<ByteSize>("10mb"),
+      (2, "invalid byte size: unknown unit `mb`".into())
+    );
+    assert_eq!(error::<ByteSize>("16EiB").1, "invalid byte size: out of range");
+    assert_eq!(error::<ByteSize>("1 MB").0, 1);
+  }
+
+  #[test]
+  fn test_timestamp() {
+    let timestamp = |atom| Timestamp::from_atom(atom).unwrap();
+    assert_eq!(timestamp("1970-01-01T00:00:00Z"), Timestamp::from_unix(0, 0));
+    assert_eq!(
+      timestamp("1969-12-31 23:59:59.5z"),
+      Timestamp::from_unix(-1, 500_000_000)
+    );
+    assert_eq!(
+      timestamp("2000-02-29T00:00:00-00:30").unix_seconds(),
+      951_784_200
+    );
+    for atom in [
+      "0001-01-01T00:00:00Z",
+      "1969-07-20T20:17:40.000000001-05:00",
+      "9999-12-31T23:59:59Z",
+    ] {
+      assert_eq!(timestamp(atom).to_atom(), atom);
+    }
+    let time = timestamp("1969-12-31T23:59:58.75Z").to_system_time();
+    assert_eq!(Timestamp::from(time), Timestamp::from_unix(-2, 750_000_000));
+
+    let message = |what: &str| format!("invalid RFC 3339 timestamp: {what}");
+    assert_eq!(
+      error::<Timestamp>("2023-02-29T00:00:00Z"),
+      (8, message("out of range"))
+    );
+    assert_eq!(error::<Timestamp>("2023-10-14"), (10, message("expected `T`")));
+    assert_eq!(
+      error::<Timestamp>("2023-10-14T15:6:05Z"),
+      (14, message("expected two digit minute"))
+    );
+    assert_eq!(
+      error::<Timestamp>("2023-10-14T15:06:05"),
+      (19, message("expected `Z` or UTC offset"))
+    );
+    assert_eq!(
+      error::<Timestamp>("2023-10-14T15:06:05+25:00"),
+      (20, message("out of range"))
+    );
+  }
+
+  #[test]
+  fn test_version() {
+    let version = |atom| Version::from_atom(atom).unwrap();
+    let mut versions = [
+      "1.0.0",
+      "1.0.0-rc.1",
+      "1.0.0-beta.11",
+      "1.0.0-beta.2",
+      "1.0.0-beta",
+      "1.0.0-alpha.beta",
+      "1.0.0-alpha.1",
+      "1.0.0-alpha",
+      "0.9.10+b.01",
+    ]
+    .map(version);
+    versions.sort();
+    let sorted = versions.iter().map(Version::to_atom).collect::<Vec<_>>();
+    assert_eq!(sorted, [
+      "0.9.10+b.01",
+      "1.0.0-alpha",
+      "1.0.0-alpha.1",
+      "1.0.0-alpha.beta",
+      "1.0.0-beta",
+      "1.0.0-beta.2",
+      "1.0.0-beta.11",
+      "1.0.0-rc.1",
+      "1.0.0",
+    ]);
+
+    let message = |what: &str| format!("invalid semantic version: {what}");
+    assert_eq!(
+      error::<Version>("1.02.3"),
+      (2, message("expected no leading zero"))
+    );
+    assert_eq!(error::<Version>("1.2"), (3, message("expected `.`")));
+    assert_eq!(
+      error::<Version>("1.2.3-rc..1"),
+      (9, message("expected identifier"))
+    );
+    assert_eq!(
+      error::<Version>("1.2.3-01"),
+      (6, message("expected no leading zero"))
+    );
+    assert_eq!(
+      error::<Version>("1.2.3 "),
+      (5, message("expected end of atom"))
+    );
+  }
+
+  #[test]
+  fn test_others() {
+    assert_eq!(Percent::from_atom("-5%").unwrap().ratio(), -0.05);
+    assert_eq!(Percent(200.0).to_atom(), "200%");
+    assert_eq!(
+      error::<Percent>("50"),
+      (2, "invalid percentage: expected `%`".into())
+    );
+    assert_eq!(error::<Percent>("inf%").0, 0);
+
+    let address = SocketAddr::from_atom("[::1]:8080").unwrap();
+    assert_eq!(address.to_atom(), "[::1]:8080");
+    assert_eq!(
+      error::<IpAddr>("127.0.0.256").1,
+      "invalid IP address: expected IPv4 or IPv6 address"
+    );
+    assert!(bool::from_atom("yes").is_err());
+    assert_eq!(u8::from_atom("256").unwrap_err().reason, Reason::OutOfRange);
+  }
+
+  #[test]
+  fn test_serde() {
+    type Error = serde::de::value::Error;
+    let duration: Result<Duration, Error> =
+      atom::deserialize("1m".into_deserializer());
+    assert_eq!(duration.unwrap(), Duration::from_secs(60));
+
+    let version: Result<Version, Error> =
+      serde::Deserialize::deserialize("1.x.0".into_deserializer());
+    assert_eq!(
+      version.unwrap_err().to_string(),
+      "invalid semantic version: expected digits"
+    );
+
+    let yaml =
+      serde_yaml::to_string(&(ByteSize(10 << 20), Percent(50.0))).unwrap();
+    assert_eq!(yaml, "- 10MiB\n- 50%\n");
+  }
+}
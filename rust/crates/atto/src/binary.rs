@@ -0,0 +1,509 @@
+//! A compact binary encoding of values
+//!
+//! Loading the binary form skips lexing and unescaping, which makes it
+//! suited for caches and for large datasets sent between services. The
+//! layout is
+//!
+//! ```text
+//! file     = "atto" version:u8 flags:u8 [table] value
+//! table    = count:varint string*          if flags has TABLE
+//! value    = 0                             nil
+//!          | 1 string                      atom
+//!          | 2 count:varint value*         list
+//!          | 3 count:varint (key value)*   document
+//! key      = index:varint                  with a table
+//!          | string                        without
+//! string   = length:varint UTF-8 bytes
+//! ```
+//!
+//! where a varint is an unsigned LEB128 number. Decoding an encoded value
+//! gives the same value, so text to binary to text preserves it.
+
+use crate::parser::{Limit, ParseLimits};
+use crate::value::{Document, Value};
+use indexmap::IndexSet;
+use std::fmt;
+
+/// The first bytes of every encoding
+pub const MAGIC: &[u8; 4] = b"atto";
+/// The version written by the [`Encoder`], and the only one decoded
+pub const VERSION: u8 = 1;
+/// The flag for a string table of the keys
+pub const TABLE: u8 = 1;
+
+const NIL: u8 = 0;
+const ATOM: u8 = 1;
+const LIST: u8 = 2;
+const DOCUMENT: u8 = 3;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BinaryErrorKind {
+  InvalidMagic,
+  UnsupportedVersion(u8),
+  UnknownFlags(u8),
+  InvalidTag(u8),
+  InvalidVarint,
+  InvalidUtf8,
+  InvalidKeyIndex(u64),
+  DuplicateKey(String),
+  UnexpectedEnd,
+  TrailingBytes,
+  LimitExceeded { limit: Limit, max: usize },
+}
+
+impl fmt::Display for BinaryErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use BinaryErrorKind::*;
+
+    match self {
+      InvalidMagic => write!(f, "not an atto binary"),
+      UnsupportedVersion(version) => {
+        write!(f, "unsupported version {version}")
+      }
+      UnknownFlags(flags) => write!(f, "unknown flags {flags:#04x}"),
+      InvalidTag(tag) => write!(f, "invalid tag {tag}"),
+      InvalidVarint => write!(f, "invalid varint"),
+      InvalidUtf8 => write!(f, "invalid UTF-8"),
+      InvalidKeyIndex(index) => write!(f, "invalid key index {index}"),
+      DuplicateKey(key) => write!(f, "duplicate key `{key}`"),
+      UnexpectedEnd => write!(f, "unexpected end of input"),
+      TrailingBytes => write!(f, "trailing bytes"),
+      LimitExceeded { limit, max } => write!(f, "{limit} exceeds {max}"),
+    }
+  }
+}
+
+/// An error of [`decode()`], with the offset of the offending byte
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BinaryError {
+  pub kind:   BinaryErrorKind,
+  pub offset: usize,
+}
+
+impl fmt::Display for BinaryError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at byte {}", self.kind, self.offset)
+  }
+}
+
+impl std::error::Error for BinaryError {}
+
+/// Encodes values, by default with a string table.
+///
+/// ```
+/// use atto::binary::{decode, Encoder};
+///
+/// let value = atto::parse("a: (name: 1) b: (name: 2) c: (name: 3)").unwrap();
+/// let with_table = Encoder::new().encode(&value);
+/// let without = Encoder::new().string_table(false).encode(&value);
+///
+/// assert!(with_table.len() < without.len());
+/// assert_eq!(decode(&with_table).unwrap(), value);
+/// assert_eq!(decode(&without).unwrap(), value);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Encoder {
+  string_table: bool,
+}
+
+impl Default for Encoder {
+  fn default() -> Self { Encoder { string_table: true } }
+}
+
+impl Encoder {
+  pub fn new() -> Encoder { Encoder::default() }
+
+  /// Write every key once, in a table before the value, and refer to keys
+  /// by their index in it. Values with more distinct keys than the default
+  /// [`ParseLimits::max_entries`] are written without table, as the table
+  /// would exceed the limit when decoding.
+  pub fn string_table(mut self, string_table: bool) -> Encoder {
+    self.string_table = string_table;
+    self
+  }
+
+  pub fn encode(&self, value: &Value) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    let mut keys = IndexSet::new();
+    if self.string_table {
+      collect_keys(value, &mut keys);
+    }
+    let mut table = None;
+    if self.string_table && keys.len() <= ParseLimits::default().max_entries {
+      bytes.push(TABLE);
+      write_varint(&mut bytes, keys.len() as u64);
+      for key in &keys {
+        write_string(&mut bytes, key);
+      }
+      table = Some(keys);
+    } else {
+      bytes.push(0);
+    }
+    write_value(&mut bytes, value, table.as_ref());
+    bytes
+  }
+}
+
+fn collect_keys<'v>(value: &'v Value, keys: &mut IndexSet<&'v str>) {
+  match value {
+    Value::List(list) => {
+      list.iter().for_each(|value| collect_keys(value, keys))
+    }
+    Value::Document(document) => {
+      for (key, value) in document {
+        keys.insert(key);
+        collect_keys(value, keys);
+      }
+    }
+    _ => {}
+  }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut n: u64) {
+  while n >= 0x80 {
+    bytes.push(n as u8 | 0x80);
+    n >>= 7;
+  }
+  bytes.push(n as u8);
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &str) {
+  write_varint(bytes, string.len() as u64);
+  bytes.extend_from_slice(string.as_bytes());
+}
+
+fn write_value(
+  bytes: &mut Vec<u8>,
+  value: &Value,
+  table: Option<&IndexSet<&str>>,
+) {
+  match value {
+    Value::Nil => bytes.push(NIL),
+    Value::Atom(atom) => {
+      bytes.push(ATOM);
+      write_string(bytes, atom);
+    }
+    Value::List(list) => {
+      bytes.push(LIST);
+      write_varint(bytes, list.len() as u64);
+      for value in list {
+        write_value(bytes, value, table);
+      }
+    }
+    Value::Document(document) => {
+      bytes.push(DOCUMENT);
+      write_varint(bytes, document.len() as u64);
+      for (key, value) in document {
+        match table {
+          Some(table) => write_varint(
+            bytes,
+            table.get_index_of(key.as_str()).unwrap() as u64,
+          ),
+          None => write_string(bytes, key),
+        }
+        write_value(bytes, value, table);
+      }
+    }
+  }
+}
+
+/// Encode `value` with a string table.
+pub fn encode(value: &Value) -> Vec<u8> { Encoder::new().encode(value) }
+
+/// Decode a value written by an [`Encoder`].
+///
+/// ```
+/// let value = atto::parse("name: John powers: (fly \"x ray\")").unwrap();
+/// let bytes = atto::binary::encode(&value);
+///
+/// assert_eq!(atto::binary::decode(&bytes).unwrap(), value);
+///
+/// let err = atto::binary::decode(&bytes[..20]).unwrap_err();
+/// assert_eq!(err.to_string(), "unexpected end of input at byte 20");
+/// ```
+pub fn decode(bytes: &[u8]) -> Result<Value, BinaryError> {
+  decode_with(bytes, &ParseLimits::default())
+}
+
+/// Decode a value within `limits`, as for parsing the text of untrusted
+/// input.
+pub fn decode_with(
+  bytes: &[u8],
+  limits: &ParseLimits,
+) -> Result<Value, BinaryError> {
+  let mut decoder = Decoder { bytes, offset: 0, limits, nodes: 0 };
+  if bytes.len() > limits.max_input_len {
+    let max = limits.max_input_len;
+    return Err(decoder.limit(Limit::InputLength, max));
+  }
+  if !bytes.starts_with(MAGIC) {
+    return Err(decoder.error(BinaryErrorKind::InvalidMagic));
+  }
+  decoder.offset = MAGIC.len();
+  let version = decoder.byte()?;
+  if version != VERSION {
+    decoder.offset -= 1;
+    return Err(decoder.error(BinaryErrorKind::UnsupportedVersion(version)));
+  }
+  let flags = decoder.byte()?;
+  if flags & !TABLE != 0 {
+    decoder.offset -= 1;
+    return Err(decoder.error(BinaryErrorKind::UnknownFlags(flags)));
+  }
+
+  let mut table = None;
+  if flags & TABLE != 0 {
+    // Every key of the table is in a document, so the table is no longer
+    // than the values
+    let start = decoder.offset;
+    let count = decoder.entries()?;
+    if count > limits.max_nodes {
+      decoder.offset = start;
+      return Err(decoder.limit(Limit::Nodes, limits.max_nodes));
+    }
+    let mut keys = Vec::with_capacity(count);
+    for _ in 0..count {
+      keys.push(decoder.string()?);
+    }
+    table = Some(keys);
+  }
+  let value = decoder.value(table.as_deref(), 0)?;
+  match decoder.offset == bytes.len() {
+    true => Ok(value),
+    false => Err(decoder.error(BinaryErrorKind::TrailingBytes)),
+  }
+}
+
+struct Decoder<'b, 'l> {
+  bytes:  &'b [u8],
+  offset: usize,
+  limits: &'l ParseLimits,
+  nodes:  usize,
+}
+
+impl Decoder<'_, '_> {
+  fn error(&self, kind: BinaryErrorKind) -> BinaryError {
+    BinaryError { kind, offset: self.offset }
+  }
+
+  fn limit(&self, limit: Limit, max: usize) -> BinaryError {
+    self.error(BinaryErrorKind::LimitExceeded { limit, max })
+  }
+
+  fn byte(&mut self) -> Result<u8, BinaryError> {
+    let byte = self.bytes.get(self.offset).copied();
+    let byte =
+      byte.ok_or_else(|| self.error(BinaryErrorKind::UnexpectedEnd))?;
+    self.offset += 1;
+    Ok(byte)
+  }
+
+  fn varint(&mut self) -> Result<u64, BinaryError> {
+    let start = self.offset;
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+      let byte = self.byte()?;
+      let bits = u64::from(byte & 0x7f);
+      if bits << shift >> shift != bits {
+        break;
+      }
+      n |= bits << shift;
+      if byte & 0x80 == 0 {
+        return Ok(n);
+      }
+    }
+    self.offset = start;
+    Err(self.error(BinaryErrorKind::InvalidVarint))
+  }
+
+  // A count of entries, which can not be more than the bytes left as
+  // every entry takes at least one
+  fn count(&mut self) -> Result<usize, BinaryError> {
+    let start = self.offset;
+    let count = self.varint()?;
+    if count > (self.bytes.len() - self.offset) as u64 {
+      self.offset = start;
+      return Err(self.error(BinaryErrorKind::UnexpectedEnd));
+    }
+    Ok(count as usize)
+  }
+
+  fn string(&mut self) -> Result<String, BinaryError> {
+    let start = self.offset;
+    let len = self.count()?;
+    if len > self.limits.max_atom_len {
+      self.offset = start;
+      return Err(self.limit(Limit::AtomLength, self.limits.max_atom_len));
+    }
+    let bytes = &self.bytes[self.offset..self.offset + len];
+    let string = std::str::from_utf8(bytes)
+      .map_err(|err| BinaryError {
+        kind:   BinaryErrorKind::InvalidUtf8,
+        offset: self.offset + err.valid_up_to(),
+      })?
+      .to_owned();
+    self.offset += len;
+    Ok(string)
+  }
+
+  fn key(&mut self, table: Option<&[String]>) -> Result<String, BinaryError> {
+    let Some(table) = table else { return self.string() };
+    let start = self.offset;
+    let index = self.varint()?;
+    match table.get(index as usize) {
+      Some(key) => Ok(key.clone()),
+      None => {
+        self.offset = start;
+        Err(self.error(BinaryErrorKind::InvalidKeyIndex(index)))
+      }
+    }
+  }
+
+  fn value(
+    &mut self,
+    table: Option<&[String]>,
+    depth: usize,
+  ) -> Result<Value, BinaryError> {
+    self.nodes += 1;
+    if self.nodes > self.limits.max_nodes {
+      return Err(self.limit(Limit::Nodes, self.limits.max_nodes));
+    }
+    let start = self.offset;
+    let tag = self.byte()?;
+    if matches!(tag, LIST | DOCUMENT) && depth > self.limits.max_depth {
+      self.offset = start;
+      return Err(self.limit(Limit::Depth, self.limits.max_depth));
+    }
+    match tag {
+      NIL => Ok(Value::Nil),
+      ATOM => Ok(Value::Atom(self.string()?)),
+      LIST => {
+        let count = self.entries()?;
+        let mut list = Vec::with_capacity(count);
+        for _ in 0..count {
+          list.push(self.value(table, depth + 1)?);
+        }
+        Ok(Value::List(list))
+      }
+      DOCUMENT => {
+        let count = self.entries()?;
+        let mut document = Document::with_capacity(count);
+        for _ in 0..count {
+          let key_start = self.offset;
+          let key = self.key(table)?;
+          if document.contains_key(&key) {
+            self.offset = key_start;
+            return Err(self.error(BinaryErrorKind::DuplicateKey(key)));
+          }
+          let value = self.value(table, depth + 1)?;
+          document.insert(key, value);
+        }
+        Ok(Value::Document(document))
+      }
+      _ => {
+        self.offset = start;
+        Err(self.error(BinaryErrorKind::InvalidTag(tag)))
+      }
+    }
+  }
+
+  fn entries(&mut self) -> Result<usize, BinaryError> {
+    let start = self.offset;
+    let count = self.count()?;
+    if count > self.limits.max_entries {
+      self.offset = start;
+      return Err(self.limit(Limit::Entries, self.limits.max_entries));
+    }
+    Ok(count)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_trip() {
+    let texts = [
+      "",
+      "a: 1",
+      "squadName: \"Super hero squad\" active: true members: (\n\
+       (name: \"Molecule Man\" age: 29 powers: (radiation \"turning tiny\"))\n\
+       (name: \"Madame Uppercut\" age: 39 powers: ())\n\
+       (name: \"Eternal Flame\" age: 1000000 powers: (\"\\u{1F525}\" \"\"))\n\
+       )",
+      "a: (b: (c: (d: (e: \"tab\\tline\\n\"))))",
+    ];
+    for text in texts {
+      let value = crate::parse(text).unwrap();
+      for encoder in [Encoder::new(), Encoder::new().string_table(false)] {
+        let decoded = decode(&encoder.encode(&value)).unwrap();
+        assert_eq!(decoded, value);
+        assert_eq!(decoded.to_string(), value.to_string());
+      }
+    }
+
+    let long = Value::Atom("x".repeat(300));
+    let bytes = Encoder::new().string_table(false).encode(&long);
+    assert_eq!(&bytes[..9], b"atto\x01\x00\x01\xac\x02");
+    assert_eq!(decode(&bytes).unwrap(), long);
+    assert_eq!(decode(&encode(&Value::Nil)).unwrap(), Value::Nil);
+  }
+
+  #[test]
+  fn test_errors() {
+    let error = |bytes: &[u8]| decode(bytes).unwrap_err().to_string();
+    assert_eq!(error(b"json"), "not an atto binary at byte 0");
+    assert_eq!(error(b"atto\x02\x00\x00"), "unsupported version 2 at byte 4");
+    assert_eq!(error(b"atto\x01\x02\x00"), "unknown flags 0x02 at byte 5");
+    assert_eq!(error(b"atto\x01\x00\x07"), "invalid tag 7 at byte 6");
+    assert_eq!(error(b"atto\x01\x00\x00\x00"), "trailing bytes at byte 7");
+    assert_eq!(
+      error(b"atto\x01\x00\x01\x02\xff\xfe"),
+      "invalid UTF-8 at byte 8"
+    );
+    assert_eq!(
+      error(b"atto\x01\x00\x02\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01"),
+      "invalid varint at byte 7"
+    );
+    assert_eq!(
+      error(b"atto\x01\x00\x02\x05\x00"),
+      "unexpected end of input at byte 7"
+    );
+    assert_eq!(
+      error(b"atto\x01\x01\x01\x01a\x03\x01\x01\x00"),
+      "invalid key index 1 at byte 11"
+    );
+    assert_eq!(
+      error(b"atto\x01\x00\x03\x02\x01a\x00\x01a\x00"),
+      "duplicate key `a` at byte 11"
+    );
+
+    let limits = ParseLimits { max_depth: 2, ..ParseLimits::default() };
+    let value = crate::parse("a: ((b))").unwrap();
+    assert!(decode_with(&encode(&value), &limits).is_ok());
+    let value = crate::parse("a: (((b)))").unwrap();
+    let err = decode_with(&encode(&value), &limits).unwrap_err();
+    assert_eq!(err.to_string(), "nesting depth exceeds 2 at byte 16");
+
+    // The table is checked before it is read
+    let limits = ParseLimits { max_entries: 2, ..ParseLimits::default() };
+    let value = crate::parse("a: (b: (c: 1))").unwrap();
+    let err = decode_with(&encode(&value), &limits).unwrap_err();
+    assert_eq!(err.to_string(), "number of entries exceeds 2 at byte 6");
+    let limits = ParseLimits { max_nodes: 2, ..ParseLimits::default() };
+    let err = decode_with(&encode(&value), &limits).unwrap_err();
+    assert_eq!(err.to_string(), "number of values exceeds 2 at byte 6");
+    let mut bytes = b"atto\x01\x01\xff\xff\x7f".to_vec();
+    bytes.resize(bytes.len() + (1 << 21), 0);
+    assert_eq!(error(&bytes), "number of entries exceeds 65536 at byte 6");
+
+    // Keys beyond the limit of the table are written inline
+    let document = (0..=1 << 16).map(|i| (i.to_string(), Value::Nil));
+    let value = Value::Document(document.collect());
+    let bytes = encode(&value);
+    assert_eq!(bytes[5], 0);
+    assert_eq!(decode_with(&bytes, &ParseLimits::trusted()).unwrap(), value);
+  }
+}
@@ -0,0 +1,207 @@
+//! Canonical text form and content digest of atto values
+//!
+//! The canonical text is the compact form: a single space separates list
+//! elements and entries, and a key is followed by a colon and a space.
+//! Nested documents and lists are enclosed in parentheses.
+//!
+//! Unlike [`format_atom()`](crate::value::format_atom), atoms are written
+//! without Unicode tables, which change between versions: an atom is bare
+//! if it only has printable ASCII characters other than `():"\#`, and
+//! otherwise a string in which every character outside of printable ASCII
+//! is escaped.
+//!
+//! This form is part of the stable format: the [`digest()`] depends on it
+//! and must not change between platforms and versions.
+
+use crate::value::{Document, Value};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// Options for the canonical text form
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  /// Write document entries sorted by key instead of in document order
+  pub sort_keys: bool,
+}
+
+/// The canonical text form of a value.
+///
+/// ```
+/// # use atto::{canonical::{to_string, Options}, value::Document, Value};
+/// let mut document = Document::new();
+/// document.insert("b".to_owned(), Value::Atom("x y".to_owned()));
+/// document.insert("a".to_owned(), Value::List(vec![]));
+/// let value = Value::Document(document);
+///
+/// assert_eq!(to_string(&value, &Options::default()), r#"(b: "x y" a: ())"#);
+/// let options = Options { sort_keys: true };
+/// assert_eq!(to_string(&value, &options), r#"(a: () b: "x y")"#);
+/// ```
+pub fn to_string(value: &Value, options: &Options) -> String {
+  let mut out = String::new();
+  write_value(&mut out, value, options);
+  out
+}
+
+fn write_value(out: &mut String, value: &Value, options: &Options) {
+  match value {
+    Value::Nil => out.push_str("#nil"),
+    Value::Atom(atom) => write_atom(out, atom),
+    Value::List(list) => {
+      out.push('(');
+      for (i, value) in list.iter().enumerate() {
+        if i > 0 {
+          out.push(' ');
+        }
+        write_value(out, value, options);
+      }
+      out.push(')');
+    }
+    Value::Document(document) => {
+      out.push('(');
+      for (i, (key, value)) in entries(document, options).enumerate() {
+        if i > 0 {
+          out.push(' ');
+        }
+        write_atom(out, key);
+        out.push_str(": ");
+        write_value(out, value, options);
+      }
+      out.push(')');
+    }
+  }
+}
+
+fn write_atom(out: &mut String, atom: &str) {
+  let bare = |c: char| c.is_ascii_graphic() && !"():\"\\#".contains(c);
+  if !atom.is_empty() && atom.chars().all(bare) {
+    out.push_str(atom);
+    return;
+  }
+  out.push('"');
+  for c in atom.chars() {
+    match c {
+      '"' => out.push_str(r#"\""#),
+      '\\' => out.push_str(r"\\"),
+      '\x1b' => out.push_str(r"\e"),
+      '\n' => out.push_str(r"\n"),
+      '\r' => out.push_str(r"\r"),
+      '\t' => out.push_str(r"\t"),
+      '\0' => out.push_str(r"\0"),
+      ' '..='~' => out.push(c),
+      c => write!(out, "\\u{{{:03x}}}", c as u32).unwrap(),
+    }
+  }
+  out.push('"');
+}
+
+fn entries<'d>(
+  document: &'d Document,
+  options: &Options,
+) -> impl Iterator<Item = (&'d String, &'d Value)> {
+  let mut entries = document.iter().collect::<Vec<_>>();
+  if options.sort_keys {
+    entries.sort_unstable_by_key(|(key, _)| *key);
+  }
+  entries.into_iter()
+}
+
+/// SHA-256 of the canonical text with sorted keys, encoded as UTF-8.
+///
+/// Like `Eq` the digest does not depend on the order of document entries.
+/// Because both are written as `()`, an empty document and an empty list
+/// have the same digest.
+pub fn digest(value: &Value) -> [u8; 32] {
+  let text = to_string(value, &Options { sort_keys: true });
+  Sha256::digest(text.as_bytes()).into()
+}
+
+/// The [`digest()`] as lowercase hexadecimal string.
+///
+/// ```
+/// # use atto::{canonical::digest_hex, Value};
+/// let value = Value::Atom("atto".to_owned());
+///
+/// assert_eq!(
+///   digest_hex(&value),
+///   "e134234e1fa04b9668b52c4f510e54d8e6c29577fd5e22383780e88f167abaa2"
+/// );
+/// ```
+pub fn digest_hex(value: &Value) -> String {
+  digest(value).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::hash_map::DefaultHasher;
+  use std::hash::{Hash, Hasher};
+
+  fn atom(s: &str) -> Value { Value::Atom(s.to_owned()) }
+
+  fn doc(entries: &[(&str, Value)]) -> Value {
+    let entries = entries.iter().map(|(k, v)| (k.to_string(), v.clone()));
+    Value::Document(entries.collect())
+  }
+
+  fn hash(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+  }
+
+  #[test]
+  fn test_key_order_is_irrelevant() {
+    let a = doc(&[("x", atom("1")), ("y", Value::Nil)]);
+    let b = doc(&[("y", Value::Nil), ("x", atom("1"))]);
+
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+    assert_eq!(a.cmp(&b), std::cmp::Ordering::Equal);
+    assert_eq!(digest(&a), digest(&b));
+    assert_ne!(
+      to_string(&a, &Options::default()),
+      to_string(&b, &Options::default())
+    );
+  }
+
+  #[test]
+  fn test_digest_distinguishes_values() {
+    let values = [
+      Value::Nil,
+      atom("#nil"),
+      atom(""),
+      atom("a b"),
+      Value::List(vec![atom("a"), atom("b")]),
+      Value::List(vec![atom("a b")]),
+      doc(&[("a", atom("b"))]),
+    ];
+    for (i, a) in values.iter().enumerate() {
+      for b in &values[i + 1..] {
+        assert_ne!(digest(a), digest(b), "{a} {b}");
+        assert_ne!(a.cmp(b), std::cmp::Ordering::Equal, "{a} {b}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_canonical_escapes() {
+    let value = Value::List(vec![atom("a\tb\u{a0}"), atom("(x)"), atom("é")]);
+    let text = to_string(&value, &Options::default());
+    assert_eq!(text, r#"("a\tb\u{0a0}" "(x)" "\u{0e9}")"#);
+
+    // Unassigned, private use and formerly separator characters
+    let atoms = ["\u{378}", "\u{e000}", "\u{180e}", "💩", "#nil", "", "a\"\\"];
+    let value = Value::List(atoms.iter().map(|a| atom(a)).collect());
+    let text = to_string(&value, &Options::default());
+    assert_eq!(
+      text,
+      r##"("\u{378}" "\u{e000}" "\u{180e}" "\u{1f4a9}" "#nil" "" "a\"\\")"##
+    );
+    assert!(text.is_ascii());
+    assert_eq!(
+      crate::parse(&format!("a: {text}")).unwrap(),
+      doc(&[("a", value)])
+    );
+  }
+}
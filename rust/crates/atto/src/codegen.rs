@@ -0,0 +1,432 @@
+//! Generation of Rust types from schemas
+//!
+//! [`rust()`] writes structs and enums deriving `serde::Deserialize` for a
+//! [`Schema`], to be read with [`de::from_str`](crate::de::from_str).
+//! Documents become structs named after their key, optional keys `Option`
+//! fields, lists `Vec`s, maps `indexmap::IndexMap`s and enums of atoms
+//! enums. `any` and a `one-of` other than with `nil` become
+//! [`atto::Value`](crate::Value). The generated code needs `serde` with
+//! the `derive` feature, and `indexmap` with the `serde` feature for maps.
+//!
+//! In a build script, the types stay in sync with the schema:
+//!
+//! ```no_run
+//! // build.rs
+//! let text = std::fs::read_to_string("config.schema.atto").unwrap();
+//! let schema = atto::decode::from_str(&text).unwrap();
+//! let code = atto::codegen::rust(&schema, &Default::default());
+//! let out = std::env::var("OUT_DIR").unwrap();
+//! std::fs::write(format!("{out}/config.rs"), code).unwrap();
+//! println!("cargo::rerun-if-changed=config.schema.atto");
+//! ```
+//!
+//! and `include!(concat!(env!("OUT_DIR"), "/config.rs"));` in the crate.
+
+use crate::schema::{Field, Schema};
+use indexmap::IndexMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Options for [`rust()`]
+#[derive(Clone, Debug)]
+pub struct RustOptions {
+  /// The name of the type of the root document
+  pub root:    String,
+  /// The derived traits, `serde::Deserialize` is always derived
+  pub derives: Vec<String>,
+}
+
+impl Default for RustOptions {
+  fn default() -> Self {
+    RustOptions {
+      root:    "Config".to_owned(),
+      derives: vec!["Clone".to_owned(), "Debug".to_owned()],
+    }
+  }
+}
+
+/// The Rust types for `schema`, the root type first.
+///
+/// ```
+/// # use atto::{codegen::rust, decode::from_str, schema::Schema};
+/// let text = "document: (port: integer log-level: (enum: (debug info)))";
+/// let schema = from_str::<Schema>(text).unwrap();
+///
+/// assert_eq!(
+///   rust(&schema, &Default::default()),
+///   "\
+/// #[derive(Clone, Debug, serde::Deserialize)]
+/// pub struct Config {
+///   pub port: i64,
+///   #[serde(rename = \"log-level\")]
+///   pub log_level: LogLevel,
+/// }
+///
+/// #[derive(Clone, Debug, serde::Deserialize)]
+/// pub enum LogLevel {
+///   #[serde(rename = \"debug\")]
+///   Debug,
+///   #[serde(rename = \"info\")]
+///   Info,
+/// }
+/// "
+/// );
+/// ```
+pub fn rust(schema: &Schema, options: &RustOptions) -> String {
+  // Items must not shadow the types of the prelude which the fields use
+  let names = PRELUDE.iter().map(|name| name.to_string()).collect();
+  let mut generator = Generator { options, items: vec![], names };
+  // Other root types are aliased, and their items are named differently
+  let item = matches!(schema, Schema::Document(_) | Schema::Enum(_));
+  if !item {
+    generator.names.insert(options.root.clone());
+  }
+  let root = generator.type_of(schema, &options.root);
+  if !item {
+    let alias = format!("pub type {} = {root};\n", options.root);
+    generator.items.insert(0, alias);
+  }
+  generator.items.join("\n")
+}
+
+// The types and traits of the prelude
+const PRELUDE: &[&str] = &[
+  "Box",
+  "Clone",
+  "Copy",
+  "Default",
+  "Drop",
+  "Eq",
+  "Fn",
+  "FnMut",
+  "FnOnce",
+  "From",
+  "Into",
+  "Iterator",
+  "Option",
+  "Ord",
+  "PartialEq",
+  "PartialOrd",
+  "Result",
+  "Send",
+  "Sized",
+  "String",
+  "Sync",
+  "ToOwned",
+  "ToString",
+  "Vec",
+];
+
+struct Generator<'o> {
+  options: &'o RustOptions,
+  items:   Vec<String>,
+  names:   HashSet<String>,
+}
+
+impl Generator<'_> {
+  // The type for `schema`, generating the items it needs. `hint` is the
+  // key of the value, for the names of new items.
+  fn type_of(&mut self, schema: &Schema, hint: &str) -> String {
+    match schema {
+      Schema::Any => "atto::Value".to_owned(),
+      Schema::Nil => "()".to_owned(),
+      Schema::String => "String".to_owned(),
+      Schema::Integer => "i64".to_owned(),
+      Schema::Number => "f64".to_owned(),
+      Schema::Boolean => "bool".to_owned(),
+      Schema::Enum(atoms) => self.enum_item(hint, atoms),
+      Schema::List(element) => {
+        format!("Vec<{}>", self.type_of(element, &singular(hint)))
+      }
+      Schema::Map(value) => {
+        let value = self.type_of(value, &singular(hint));
+        format!("indexmap::IndexMap<String, {value}>")
+      }
+      Schema::Document(fields) => self.struct_item(hint, fields),
+      Schema::OneOf(schemas) => match schemas.as_slice() {
+        [Schema::Nil, schema] | [schema, Schema::Nil] => {
+          format!("Option<{}>", self.type_of(schema, hint))
+        }
+        _ => "atto::Value".to_owned(),
+      },
+    }
+  }
+
+  fn struct_item(
+    &mut self,
+    hint: &str,
+    fields: &IndexMap<String, Field>,
+  ) -> String {
+    let name = self.type_name(hint);
+    // The struct comes before the types of its fields
+    let index = self.items.len();
+    self.items.push(String::new());
+
+    let mut item = self.derive();
+    if fields.is_empty() {
+      writeln!(item, "pub struct {name} {{}}").unwrap();
+      self.items[index] = item;
+      return name;
+    }
+    writeln!(item, "pub struct {name} {{").unwrap();
+    let mut idents = HashSet::new();
+    for (key, field) in fields {
+      let mut ty = self.type_of(&field.schema, key);
+      if field.optional && !ty.starts_with("Option<") {
+        ty = format!("Option<{ty}>");
+      }
+      let ident = unique(field_ident(key), &mut idents);
+      if ident.trim_start_matches("r#") != key {
+        writeln!(item, "  #[serde(rename = {key:?})]").unwrap();
+      }
+      writeln!(item, "  pub {ident}: {ty},").unwrap();
+    }
+    item.push_str("}\n");
+    self.items[index] = item;
+    name
+  }
+
+  fn enum_item(&mut self, hint: &str, atoms: &[String]) -> String {
+    let name = self.type_name(hint);
+    let mut item = self.derive();
+    writeln!(item, "pub enum {name} {{").unwrap();
+    let mut idents = HashSet::new();
+    for atom in atoms {
+      writeln!(item, "  #[serde(rename = {atom:?})]").unwrap();
+      let ident = unique(type_ident(atom, "Variant"), &mut idents);
+      writeln!(item, "  {ident},").unwrap();
+    }
+    item.push_str("}\n");
+    self.items.push(item);
+    name
+  }
+
+  fn derive(&self) -> String {
+    let mut derives = self.options.derives.clone();
+    derives.push("serde::Deserialize".to_owned());
+    format!("#[derive({})]\n", derives.join(", "))
+  }
+
+  fn type_name(&mut self, hint: &str) -> String {
+    unique(type_ident(hint, "Type"), &mut self.names)
+  }
+}
+
+// `ident`, or with the lowest number appended which makes it unique
+fn unique(ident: String, taken: &mut HashSet<String>) -> String {
+  let mut unique = ident.clone();
+  let mut number = 1;
+  while taken.contains(&unique) {
+    number += 1;
+    unique = format!("{ident}{number}");
+  }
+  taken.insert(unique.clone());
+  unique
+}
+
+// The words of `key`, split at non-alphanumeric characters and before
+// capitals which follow a lower case letter
+fn words(key: &str) -> Vec<String> {
+  let mut words = vec![];
+  let mut word = String::new();
+  let mut lower = false;
+  for c in key.chars() {
+    let boundary =
+      !c.is_ascii_alphanumeric() || (lower && c.is_ascii_uppercase());
+    if boundary && !word.is_empty() {
+      words.push(std::mem::take(&mut word));
+    }
+    if c.is_ascii_alphanumeric() {
+      word.push(c.to_ascii_lowercase());
+    }
+    lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+  }
+  if !word.is_empty() {
+    words.push(word);
+  }
+  words
+}
+
+fn type_ident(key: &str, fallback: &str) -> String {
+  let ident = words(key)
+    .iter()
+    .map(|word| word[..1].to_ascii_uppercase() + &word[1..])
+    .collect::<String>();
+  match ident.chars().next() {
+    None => fallback.to_owned(),
+    Some(c) if c.is_ascii_digit() => format!("{fallback}{ident}"),
+    Some(_) if ident == "Self" => format!("{ident}_"),
+    Some(_) => ident,
+  }
+}
+
+const KEYWORDS: [&str; 51] = [
+  "abstract", "as", "async", "await", "become", "box", "break", "const",
+  "continue", "crate", "do", "dyn", "else", "enum", "extern", "false", "final",
+  "fn", "for", "gen", "if", "impl", "in", "let", "loop", "macro", "match",
+  "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self",
+  "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+  "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+fn field_ident(key: &str) -> String {
+  let ident = words(key).join("_");
+  match ident.chars().next() {
+    None => "field".to_owned(),
+    Some(c) if c.is_ascii_digit() => format!("_{ident}"),
+    Some(_) if matches!(ident.as_str(), "crate" | "self" | "super") => {
+      format!("{ident}_")
+    }
+    Some(_) if KEYWORDS.contains(&ident.as_str()) => format!("r#{ident}"),
+    Some(_) => ident,
+  }
+}
+
+// The name of an element of the list or map `key`
+fn singular(key: &str) -> String {
+  match key.strip_suffix('s') {
+    Some(stem) if !stem.is_empty() && !stem.ends_with('s') => stem.to_owned(),
+    _ => format!("{key}-item"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::decode::from_str;
+
+  #[test]
+  fn test_rust() {
+    let text = r#"
+      document: (
+        squadName: string
+        "home town": (one-of: (nil string))
+        members: (list: (document: (
+          name: string
+          type: (enum: (a "b c" 1 B))
+          powers: (list: any)
+        ) optional: (powers)))
+        labels: (map: (document: (x: number)))
+        self: boolean
+        "2nd": (one-of: (string integer))
+        Members: (document: ())
+      )
+    "#;
+    let schema = from_str::<Schema>(text).unwrap();
+    let options = RustOptions { root: "Squad".to_owned(), derives: vec![] };
+    assert_eq!(
+      rust(&schema, &options),
+      r#"#[derive(serde::Deserialize)]
+pub struct Squad {
+  #[serde(rename = "squadName")]
+  pub squad_name: String,
+  #[serde(rename = "home town")]
+  pub home_town: Option<String>,
+  pub members: Vec<Member>,
+  pub labels: indexmap::IndexMap<String, Label>,
+  #[serde(rename = "self")]
+  pub self_: bool,
+  #[serde(rename = "2nd")]
+  pub _2nd: atto::Value,
+  #[serde(rename = "Members")]
+  pub members2: Members,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Member {
+  pub name: String,
+  pub r#type: Type,
+  pub powers: Option<Vec<atto::Value>>,
+}
+
+#[derive(serde::Deserialize)]
+pub enum Type {
+  #[serde(rename = "a")]
+  A,
+  #[serde(rename = "b c")]
+  BC,
+  #[serde(rename = "1")]
+  Variant1,
+  #[serde(rename = "B")]
+  B,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Label {
+  pub x: f64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Members {}
+"#
+    );
+
+    let options = RustOptions::default();
+    assert_eq!(
+      rust(&Schema::List(Box::new(Schema::Integer)), &options),
+      "pub type Config = Vec<i64>;\n"
+    );
+
+    let text = "list: (document: (name: string))";
+    assert_eq!(
+      rust(&from_str::<Schema>(text).unwrap(), &options),
+      "\
+pub type Config = Vec<ConfigItem>;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ConfigItem {
+  pub name: String,
+}
+"
+    );
+    let text = "map: (document: (ports: (list: integer)))";
+    assert_eq!(
+      rust(&from_str::<Schema>(text).unwrap(), &options),
+      "\
+pub type Config = indexmap::IndexMap<String, ConfigItem>;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ConfigItem {
+  pub ports: Vec<i64>,
+}
+"
+    );
+    let text = "document: (string: (document: (a: string)) name: string)";
+    assert_eq!(
+      rust(&from_str::<Schema>(text).unwrap(), &options),
+      "\
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Config {
+  pub string: String2,
+  pub name: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct String2 {
+  pub a: String,
+}
+"
+    );
+    let text = "one-of: (nil (document: ()))";
+    assert_eq!(
+      rust(&from_str::<Schema>(text).unwrap(), &options),
+      "\
+pub type Config = Option<Config2>;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Config2 {}
+"
+    );
+  }
+
+  #[test]
+  fn test_idents() {
+    assert_eq!(words("HTTPServer-port_2x"), ["httpserver", "port", "2x"]);
+    assert_eq!(type_ident("max-size", "Type"), "MaxSize");
+    assert_eq!(type_ident("Self", "Type"), "Self_");
+    assert_eq!(type_ident("ö", "Type"), "Type");
+    assert_eq!(field_ident("fn"), "r#fn");
+    assert_eq!(singular("hosts"), "host");
+    assert_eq!(singular("address"), "address-item");
+  }
+}
@@ -0,0 +1,633 @@
+//! Loading configuration from several files and the environment
+//!
+//! A [`Loader`] merges its sources in the order they are added, later ones
+//! overriding earlier ones, and environment variables last. Documents are
+//! merged entry by entry, any other value replaces the one before. The
+//! resulting [`Config`] remembers where each entry came from.
+//!
+//! ```
+//! use atto::config::{Loader, MemoryFileSystem};
+//!
+//! let mut fs = MemoryFileSystem::new();
+//! fs.insert("/etc/app/config.atto", "port: 80 log: (level: info)");
+//! fs.insert("app.atto", "log: (level: debug)");
+//!
+//! let config = Loader::new()
+//!   .filesystem(fs)
+//!   .vars([("APP_PORT", "8080")])
+//!   .required("/etc/app/config.atto")
+//!   .file("app.atto")
+//!   .file("missing.atto")
+//!   .env("APP_")
+//!   .load()?;
+//!
+//! assert_eq!(config.value.to_string(), "(port: 8080 log: (level: debug))");
+//! let origin = config.origin(&"log.level".parse().unwrap()).unwrap();
+//! assert_eq!(origin.to_string(), "app.atto:1:7");
+//! # Ok::<(), atto::config::ConfigError>(())
+//! ```
+
+use crate::parser::{parse_tree_with, ParseError, ParseLimits};
+use crate::path::{Path, Segment};
+use crate::syntax::{LineIndex, Node, NodeKind, Position};
+use crate::value::{Document, Value};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::{fmt, io};
+
+/// Where the loader reads files from
+pub trait FileSystem {
+  /// The contents of the file at `path`, an error of kind
+  /// [`io::ErrorKind::NotFound`] if there is none.
+  fn read(&self, path: &std::path::Path) -> io::Result<String>;
+}
+
+/// The file system of the operating system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+  fn read(&self, path: &std::path::Path) -> io::Result<String> {
+    std::fs::read_to_string(path)
+  }
+}
+
+/// Files in memory, for tests
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFileSystem {
+  files: HashMap<PathBuf, String>,
+}
+
+impl MemoryFileSystem {
+  pub fn new() -> MemoryFileSystem { MemoryFileSystem::default() }
+
+  pub fn insert(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) {
+    self.files.insert(path.into(), text.into());
+  }
+}
+
+impl FileSystem for MemoryFileSystem {
+  fn read(&self, path: &std::path::Path) -> io::Result<String> {
+    self.files.get(path).cloned().ok_or_else(|| {
+      io::Error::new(io::ErrorKind::NotFound, "no such file in memory")
+    })
+  }
+}
+
+/// Where a configuration entry came from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+  Defaults,
+  /// The position of the key in a file
+  File {
+    path:     PathBuf,
+    position: Position,
+  },
+  /// An environment variable, by name
+  Env(String),
+}
+
+impl fmt::Display for Origin {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Origin::Defaults => write!(f, "defaults"),
+      Origin::File { path, position } => {
+        write!(f, "{}:{position}", path.display())
+      }
+      Origin::Env(name) => write!(f, "environment variable {name}"),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum ConfigErrorKind {
+  /// A required file does not exist
+  NotFound,
+  Io(io::Error),
+  Parse(ParseError),
+}
+
+impl fmt::Display for ConfigErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ConfigErrorKind::NotFound => write!(f, "required file not found"),
+      ConfigErrorKind::Io(err) => write!(f, "{err}"),
+      ConfigErrorKind::Parse(err) => write!(f, "{err}"),
+    }
+  }
+}
+
+/// An error in a configuration file
+#[derive(Debug)]
+pub struct ConfigError {
+  pub path: PathBuf,
+  pub kind: ConfigErrorKind,
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.path.display(), self.kind)
+  }
+}
+
+impl std::error::Error for ConfigError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match &self.kind {
+      ConfigErrorKind::Io(err) => Some(err),
+      ConfigErrorKind::Parse(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+/// A merged configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+  /// Always a document
+  pub value: Value,
+  /// The files found, in merge order
+  pub files: Vec<PathBuf>,
+  origins:   BTreeMap<Path, Origin>,
+}
+
+impl Config {
+  /// Where the entry at `path` came from. List elements have the origin of
+  /// their list.
+  pub fn origin(&self, path: &Path) -> Option<&Origin> {
+    let mut path = path.clone();
+    loop {
+      if let Some(origin) = self.origins.get(&path) {
+        return Some(origin);
+      }
+      path.pop()?;
+    }
+  }
+
+  /// The origins of all entries, by path.
+  pub fn origins(&self) -> &BTreeMap<Path, Origin> { &self.origins }
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+  Defaults(Value),
+  File { path: PathBuf, required: bool },
+  Search(String),
+}
+
+/// Builds a [`Config`] from defaults, files and environment variables.
+#[derive(Clone, Debug)]
+pub struct Loader<F: FileSystem = OsFileSystem> {
+  fs:         F,
+  // The environment of the process is read on loading if this is None
+  vars:       Option<BTreeMap<String, String>>,
+  sources:    Vec<Source>,
+  env_prefix: Option<String>,
+  limits:     ParseLimits,
+}
+
+impl Default for Loader {
+  fn default() -> Self { Loader::new() }
+}
+
+impl Loader {
+  /// A loader reading from the operating system, with the environment
+  /// variables of the process when loading. Variables which are not unicode
+  /// are ignored.
+  pub fn new() -> Loader {
+    Loader {
+      fs:         OsFileSystem,
+      vars:       None,
+      sources:    vec![],
+      env_prefix: None,
+      limits:     ParseLimits::default(),
+    }
+  }
+}
+
+impl<F: FileSystem> Loader<F> {
+  /// Read files from `fs`.
+  pub fn filesystem<G: FileSystem>(self, fs: G) -> Loader<G> {
+    Loader {
+      fs,
+      vars: self.vars,
+      sources: self.sources,
+      env_prefix: self.env_prefix,
+      limits: self.limits,
+    }
+  }
+
+  /// Use `vars` instead of the environment variables of the process, for
+  /// [`env`](Loader::env) and [`search`](Loader::search).
+  pub fn vars<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+  where
+    K: Into<String>,
+    V: Into<String>,
+  {
+    self.vars =
+      Some(vars.into_iter().map(|(k, v)| (k.into(), v.into())).collect());
+    self
+  }
+
+  pub fn limits(mut self, limits: ParseLimits) -> Self {
+    self.limits = limits;
+    self
+  }
+
+  /// Merge `defaults`, which must be a document.
+  pub fn defaults(mut self, defaults: Value) -> Self {
+    self.sources.push(Source::Defaults(defaults));
+    self
+  }
+
+  /// Merge the file at `path` if it exists.
+  pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+    let path = path.into();
+    self.sources.push(Source::File { path, required: false });
+    self
+  }
+
+  /// Merge the file at `path`, which must exist.
+  pub fn required(mut self, path: impl Into<PathBuf>) -> Self {
+    let path = path.into();
+    self.sources.push(Source::File { path, required: true });
+    self
+  }
+
+  /// Merge the usual files of `app` which exist, in this order:
+  /// `/etc/<app>/config.atto`, `<config>/<app>/config.atto` and
+  /// `./<app>.atto`. `<config>` is `$XDG_CONFIG_HOME`, or `$HOME/.config`.
+  pub fn search(mut self, app: &str) -> Self {
+    self.sources.push(Source::Search(app.to_owned()));
+    self
+  }
+
+  /// Override entries with the environment variables starting with
+  /// `prefix`, after all other sources.
+  ///
+  /// The rest of the name is the path of the entry in lower case, with
+  /// `__` between keys: `APP_LOG__LEVEL=debug` sets `log.level` to the
+  /// atom `debug` with the prefix `APP_`.
+  pub fn env(mut self, prefix: &str) -> Self {
+    self.env_prefix = Some(prefix.to_owned());
+    self
+  }
+
+  /// Read and merge all sources.
+  pub fn load(&self) -> Result<Config, ConfigError> {
+    let mut config = Config {
+      value:   Value::Document(Document::new()),
+      files:   vec![],
+      origins: BTreeMap::new(),
+    };
+    let search = self.sources.iter().any(|s| matches!(s, Source::Search(_)));
+    let vars = match &self.vars {
+      Some(vars) => Cow::Borrowed(vars),
+      None if search || self.env_prefix.is_some() => Cow::Owned(env_vars()),
+      None => Cow::Owned(BTreeMap::new()),
+    };
+    for source in &self.sources {
+      match source {
+        Source::Defaults(defaults) => {
+          merge_value(&mut config, defaults, &mut Path::root(), &|| {
+            Origin::Defaults
+          });
+        }
+        Source::File { path, required } => {
+          self.merge_file(&mut config, path, *required)?
+        }
+        Source::Search(app) => {
+          for path in search_paths(app, &vars) {
+            self.merge_file(&mut config, &path, false)?;
+          }
+        }
+      }
+    }
+    if let Some(prefix) = &self.env_prefix {
+      merge_env(&mut config, prefix, &vars);
+    }
+    Ok(config)
+  }
+
+  fn merge_file(
+    &self,
+    config: &mut Config,
+    path: &std::path::Path,
+    required: bool,
+  ) -> Result<(), ConfigError> {
+    let error = |kind| ConfigError { path: path.to_owned(), kind };
+    let text = match self.fs.read(path) {
+      Ok(text) => text,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        return match required {
+          true => Err(error(ConfigErrorKind::NotFound)),
+          false => Ok(()),
+        };
+      }
+      Err(err) => return Err(error(ConfigErrorKind::Io(err))),
+    };
+    let parsed = parse_tree_with(&text, &self.limits);
+    if let Some(err) = parsed.errors.into_iter().next() {
+      return Err(error(ConfigErrorKind::Parse(err)));
+    }
+    let origin = |position| Origin::File { path: path.to_owned(), position };
+    let mut lines = LineIndex::new(&text);
+    merge_node(config, &parsed.root, &mut Path::root(), &mut lines, &origin);
+    config.files.push(path.to_owned());
+    Ok(())
+  }
+}
+
+// The environment variables of the process, without those which are not
+// unicode
+fn env_vars() -> BTreeMap<String, String> {
+  let vars = std::env::vars_os();
+  vars
+    .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+    .collect()
+}
+
+// The usual config files of `app`
+fn search_paths(app: &str, vars: &BTreeMap<String, String>) -> Vec<PathBuf> {
+  let mut paths = vec![PathBuf::from(format!("/etc/{app}/config.atto"))];
+  let config = match vars.get("XDG_CONFIG_HOME") {
+    Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+    _ => vars.get("HOME").map(|home| PathBuf::from(home).join(".config")),
+  };
+  if let Some(config) = config {
+    paths.push(config.join(app).join("config.atto"));
+  }
+  paths.push(PathBuf::from(format!("{app}.atto")));
+  paths
+}
+
+// Merge the environment variables starting with `prefix`
+fn merge_env(
+  config: &mut Config,
+  prefix: &str,
+  vars: &BTreeMap<String, String>,
+) {
+  for (name, value) in vars {
+    let Some(rest) = name.strip_prefix(prefix) else { continue };
+    let keys = rest.split("__").map(str::to_lowercase).collect::<Vec<_>>();
+    if keys.iter().any(String::is_empty) {
+      continue;
+    }
+    let path = Path(keys.into_iter().map(Segment::Key).collect());
+    let value = Value::Atom(value.clone());
+    set(config, &path, value, Origin::Env(name.clone()));
+  }
+}
+
+// Merge the document `node` into the document at `path`
+fn merge_node(
+  config: &mut Config,
+  node: &Node,
+  path: &mut Path,
+  lines: &mut LineIndex,
+  origin: &dyn Fn(Position) -> Origin,
+) {
+  for entry in node.entries() {
+    path.push(Segment::Key(entry.key.clone()));
+    let both_documents = matches!(entry.value.kind, NodeKind::Document(_))
+      && matches!(config.value.get_path(path), Some(Value::Document(_)));
+    if both_documents {
+      merge_node(config, &entry.value, path, lines, origin);
+    } else {
+      let position = lines.position(entry.key_span.start);
+      set(config, path, entry.value.to_value(), origin(position));
+      record_node(config, &entry.value, path, lines, origin);
+    }
+    path.pop();
+  }
+}
+
+// Record the origins of the entries below `node`
+fn record_node(
+  config: &mut Config,
+  node: &Node,
+  path: &mut Path,
+  lines: &mut LineIndex,
+  origin: &dyn Fn(Position) -> Origin,
+) {
+  match &node.kind {
+    NodeKind::List(list) => {
+      for (index, child) in list.iter().enumerate() {
+        path.push(Segment::Index(index));
+        record_node(config, child, path, lines, origin);
+        path.pop();
+      }
+    }
+    NodeKind::Document(entries) => {
+      for entry in entries {
+        path.push(Segment::Key(entry.key.clone()));
+        let position = lines.position(entry.key_span.start);
+        config.origins.insert(path.clone(), origin(position));
+        record_node(config, &entry.value, path, lines, origin);
+        path.pop();
+      }
+    }
+    _ => {}
+  }
+}
+
+// Merge the document `value` into the document at `path`
+fn merge_value(
+  config: &mut Config,
+  value: &Value,
+  path: &mut Path,
+  origin: &dyn Fn() -> Origin,
+) {
+  let Value::Document(document) = value else { return };
+  for (key, value) in document {
+    path.push(Segment::Key(key.clone()));
+    let both_documents = matches!(value, Value::Document(_))
+      && matches!(config.value.get_path(path), Some(Value::Document(_)));
+    if both_documents {
+      merge_value(config, value, path, origin);
+    } else {
+      set(config, path, value.clone(), origin());
+      record_value(config, value, path, origin);
+    }
+    path.pop();
+  }
+}
+
+// Record the origins of the entries below `value`
+fn record_value(
+  config: &mut Config,
+  value: &Value,
+  path: &mut Path,
+  origin: &dyn Fn() -> Origin,
+) {
+  match value {
+    Value::List(list) => {
+      for (index, value) in list.iter().enumerate() {
+        path.push(Segment::Index(index));
+        record_value(config, value, path, origin);
+        path.pop();
+      }
+    }
+    Value::Document(document) => {
+      for (key, value) in document {
+        path.push(Segment::Key(key.clone()));
+        config.origins.insert(path.clone(), origin());
+        record_value(config, value, path, origin);
+        path.pop();
+      }
+    }
+    _ => {}
+  }
+}
+
+// Set the entry at `path`, replacing values in the way by documents, and
+// forget the origins of what it replaces
+fn set(config: &mut Config, path: &Path, value: Value, origin: Origin) {
+  let mut target = &mut config.value;
+  for (depth, segment) in path.segments().iter().enumerate() {
+    if !matches!(target, Value::Document(_)) {
+      *target = Value::Document(Document::new());
+      forget(&mut config.origins, &Path(path.segments()[..depth].to_vec()));
+    }
+    let (Value::Document(document), Segment::Key(key)) = (target, segment)
+    else {
+      unreachable!("configuration paths only have keys")
+    };
+    target = document.entry(key.clone()).or_insert(Value::Nil);
+  }
+  *target = value;
+  forget(&mut config.origins, path);
+  config.origins.insert(path.clone(), origin);
+}
+
+// Remove the origins of `path` and the paths below it, which sort right
+// after it
+fn forget(origins: &mut BTreeMap<Path, Origin>, path: &Path) {
+  let below = origins.range(path..).take_while(|(p, _)| p.starts_with(path));
+  for p in below.map(|(p, _)| p.clone()).collect::<Vec<_>>() {
+    origins.remove(&p);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn origin(config: &Config, path: &str) -> String {
+    config.origin(&path.parse().unwrap()).unwrap().to_string()
+  }
+
+  #[test]
+  fn test_merge() {
+    let mut fs = MemoryFileSystem::new();
+    fs.insert("/etc/app/config.atto", "a: 1\nb: (c: 2 d: (3 4))\ne: (f: 5)");
+    fs.insert("/home/me/.config/app/config.atto", "b: (d: ())\ne: x");
+    fs.insert("app.atto", "b: (\n  g: (h: 6)\n)");
+
+    let loader = Loader::new()
+      .filesystem(fs)
+      .vars([("HOME", "/home/me"), ("APP_B__G__I", "7"), ("APP_A", "8")])
+      .defaults(crate::parse("a: 0 z: (y: 9)").unwrap())
+      .search("app");
+    let config = loader.load().unwrap();
+    assert_eq!(
+      config.value.to_string(),
+      "(a: 1 z: (y: 9) b: (c: 2 d: () g: (h: 6)) e: x)"
+    );
+    assert_eq!(config.files.len(), 3);
+    assert_eq!(origin(&config, "a"), "/etc/app/config.atto:1:1");
+    assert_eq!(origin(&config, "z.y"), "defaults");
+    assert_eq!(origin(&config, "b.c"), "/etc/app/config.atto:2:5");
+    assert_eq!(origin(&config, "b.d"), "/home/me/.config/app/config.atto:1:5");
+    assert_eq!(origin(&config, "b.g.h"), "app.atto:2:7");
+    assert_eq!(origin(&config, "e"), "/home/me/.config/app/config.atto:2:1");
+    assert_eq!(origin(&config, "b"), "/etc/app/config.atto:2:1");
+
+    let config = loader.env("APP_").load().unwrap();
+    assert_eq!(
+      config.value.to_string(),
+      "(a: 8 z: (y: 9) b: (c: 2 d: () g: (h: 6 i: 7)) e: x)"
+    );
+    assert_eq!(origin(&config, "a"), "environment variable APP_A");
+    assert_eq!(origin(&config, "b.g.i"), "environment variable APP_B__G__I");
+    assert_eq!(origin(&config, "b.g.h"), "app.atto:2:7");
+  }
+
+  #[test]
+  fn test_env_replaces_values() {
+    let mut fs = MemoryFileSystem::new();
+    fs.insert("a.atto", "a: ((x: 1) 2)\nb: (c: 3)");
+    let config = Loader::new()
+      .filesystem(fs)
+      .vars([("X_A__D", "4"), ("X_B", "5"), ("X_", "6"), ("X_C____E", "7")])
+      .file("a.atto")
+      .env("X_")
+      .load()
+      .unwrap();
+    assert_eq!(config.value.to_string(), "(a: (d: 4) b: 5)");
+    assert_eq!(origin(&config, "a.d"), "environment variable X_A__D");
+    assert!(config.origin(&"a".parse().unwrap()).is_none());
+    let paths = config.origins().keys().map(Path::to_string);
+    assert_eq!(paths.collect::<Vec<_>>(), ["a.d", "b"]);
+  }
+
+  #[test]
+  fn test_large_files() {
+    let entries = (0..20_000).map(|i| format!("k{i}: (v: {i})"));
+    let text = entries.collect::<Vec<_>>().join(" ");
+    let mut fs = MemoryFileSystem::new();
+    fs.insert("a.atto", text.as_str());
+    fs.insert("b.atto", text.replace("(v: ", "\n(v: "));
+    let config = Loader::new()
+      .filesystem(fs)
+      .file("a.atto")
+      .file("b.atto")
+      .load()
+      .unwrap();
+    assert_eq!(config.origins().len(), 40_000);
+    assert_eq!(origin(&config, "k19999"), "a.atto:1:357762");
+    assert_eq!(origin(&config, "k19999.v"), "b.atto:20001:2");
+  }
+
+  #[test]
+  fn test_search_paths() {
+    let vars =
+      |value: &str| BTreeMap::from([("XDG_CONFIG_HOME".into(), value.into())]);
+    assert_eq!(
+      search_paths("app", &vars("/xdg")),
+      ["/etc/app/config.atto", "/xdg/app/config.atto", "app.atto"]
+        .map(PathBuf::from)
+    );
+    assert_eq!(search_paths("app", &vars("")).len(), 2);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_env_vars() {
+    use std::os::unix::ffi::OsStrExt;
+    let invalid = std::ffi::OsStr::from_bytes(b"\xff");
+    std::env::set_var("ATTO_TEST_INVALID", invalid);
+    std::env::set_var("ATTO_TEST_VALID", "x");
+    let config = Loader::new().env("ATTO_TEST_").load().unwrap();
+    assert_eq!(config.value.to_string(), "(valid: x)");
+  }
+
+  #[test]
+  fn test_errors() {
+    let mut fs = MemoryFileSystem::new();
+    fs.insert("invalid.atto", "a: (b");
+    let loader = Loader::new().filesystem(fs).vars(Vec::<(&str, &str)>::new());
+
+    let error =
+      |loader: Loader<MemoryFileSystem>| loader.load().unwrap_err().to_string();
+    assert_eq!(
+      error(loader.clone().required("missing.atto")),
+      "missing.atto: required file not found"
+    );
+    assert_eq!(
+      error(loader.clone().file("invalid.atto")),
+      "invalid.atto: unclosed parenthesis at 1:4"
+    );
+    assert!(loader.file("missing.atto").load().is_ok());
+  }
+}
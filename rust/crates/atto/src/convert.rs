@@ -0,0 +1,349 @@
+//! Import of TOML and YAML texts into atto values, and export to JSON
+//!
+//! Mapping: tables and mappings become documents, arrays and sequences
+//! become lists, and scalars become atoms in their textual form, for
+//! example TOML datetimes in RFC 3339. YAML `null` becomes [`Value::Nil`].
+//! Comments before TOML keys and table headers become doc comments.
+//!
+//! [`to_json()`] maps nil to `null`, atoms to strings, lists to arrays and
+//! documents to objects, the mapping of
+//! [`to_json_schema()`](crate::json_schema::to_json_schema).
+
+use crate::format::Docs;
+use crate::path::{Path, Segment};
+use crate::value::{Document, Value};
+use serde::Deserialize;
+use serde_json::Value as Json;
+use std::fmt;
+
+/// The input formats supported by [`convert()`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+  Toml,
+  Yaml,
+}
+
+impl std::str::FromStr for Format {
+  type Err = ConvertError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "toml" => Ok(Format::Toml),
+      "yaml" | "yml" => Ok(Format::Yaml),
+      _ => Err(ConvertError::UnknownFormat(s.to_owned())),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum ConvertError {
+  UnknownFormat(String),
+  Toml(toml::de::Error),
+  Yaml(serde_yaml::Error),
+}
+
+impl fmt::Display for ConvertError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ConvertError::UnknownFormat(s) => write!(f, "unknown format `{s}`"),
+      ConvertError::Toml(err) => write!(f, "invalid TOML: {err}"),
+      ConvertError::Yaml(err) => write!(f, "invalid YAML: {err}"),
+    }
+  }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// Convert a text in the given format to an atto value.
+pub fn convert(text: &str, format: Format) -> Result<Value, ConvertError> {
+  match format {
+    Format::Toml => from_toml(text),
+    Format::Yaml => from_yaml(text),
+  }
+}
+
+/// Convert a text in the given format to an atto value and the doc
+/// comments of its entries. Only TOML texts have doc comments.
+pub fn convert_with_docs(
+  text: &str,
+  format: Format,
+) -> Result<(Value, Docs), ConvertError> {
+  match format {
+    Format::Toml => from_toml_with_docs(text),
+    Format::Yaml => Ok((from_yaml(text)?, Docs::new())),
+  }
+}
+
+/// Convert a TOML text to an atto document.
+///
+/// ```
+/// let value = atto::convert::from_toml("[package]\nname = \"atto\"").unwrap();
+///
+/// assert_eq!(value.to_string(), "(package: (name: atto))");
+/// ```
+pub fn from_toml(text: &str) -> Result<Value, ConvertError> {
+  let table = text.parse::<toml::Table>().map_err(ConvertError::Toml)?;
+  Ok(toml_table(table))
+}
+
+/// Convert a TOML text to an atto document and the comments right before
+/// its keys and table headers as doc comments.
+///
+/// ```
+/// let text = "# The name\nname = \"atto\"\n\n# comment\n\nport = 80";
+/// let (value, docs) = atto::convert::from_toml_with_docs(text).unwrap();
+///
+/// assert_eq!(value.to_string(), "(name: atto port: 80)");
+/// assert_eq!(docs[&"name".parse().unwrap()], "The name");
+/// assert_eq!(docs.len(), 1);
+/// ```
+pub fn from_toml_with_docs(text: &str) -> Result<(Value, Docs), ConvertError> {
+  let value = from_toml(text)?;
+  let mut docs = Docs::new();
+  if let Ok(document) = text.parse::<toml_edit::DocumentMut>() {
+    toml_docs(document.as_table(), &mut Path::root(), &mut docs);
+  }
+  Ok((value, docs))
+}
+
+fn toml_table(table: toml::Table) -> Value {
+  let document = table.into_iter().map(|(k, v)| (k, toml_value(v)));
+  Value::Document(document.collect())
+}
+
+fn toml_value(value: toml::Value) -> Value {
+  use toml::Value::*;
+
+  match value {
+    String(s) => Value::Atom(s),
+    Integer(i) => Value::Atom(i.to_string()),
+    // With a fraction or exponent, as TOML writes infinity and NaN
+    Float(x) if x.is_nan() => Value::Atom("nan".to_owned()),
+    Float(x) => Value::Atom(format!("{x:?}")),
+    Boolean(b) => Value::Atom(b.to_string()),
+    Datetime(dt) => Value::Atom(dt.to_string()),
+    Array(vec) => Value::List(vec.into_iter().map(toml_value).collect()),
+    Table(table) => toml_table(table),
+  }
+}
+
+// Collect the docs of the entries of `table` at `path`. A table or array of
+// tables is documented by the comment before its first header.
+fn toml_docs(table: &toml_edit::Table, path: &mut Path, docs: &mut Docs) {
+  for (key, item) in table.iter() {
+    path.push(Segment::Key(key.to_owned()));
+    let mut doc = table.key(key).and_then(|key| toml_doc(key.leaf_decor()));
+    match item {
+      toml_edit::Item::Table(table) => {
+        doc = doc.or_else(|| toml_doc(table.decor()));
+        toml_docs(table, path, docs);
+      }
+      toml_edit::Item::ArrayOfTables(array) => {
+        doc = doc.or_else(|| toml_doc(array.get(0)?.decor()));
+        for (index, table) in array.iter().enumerate() {
+          path.push(Segment::Index(index));
+          toml_docs(table, path, docs);
+          path.pop();
+        }
+      }
+      _ => {}
+    }
+    if let Some(doc) = doc {
+      docs.insert(path.clone(), doc);
+    }
+    path.pop();
+  }
+}
+
+// The block of comment lines at the end of the prefix of `decor`, as for
+// doc comments in atto texts
+fn toml_doc(decor: &toml_edit::Decor) -> Option<String> {
+  let prefix = decor.prefix()?.as_str()?;
+  // without the indentation of the key
+  let prefix = &prefix[..prefix.rfind('\n').map_or(0, |at| at + 1)];
+  let mut lines = vec![];
+  for line in prefix.lines().rev() {
+    let Some(comment) = line.trim().strip_prefix('#') else { break };
+    lines.push(comment.strip_prefix(' ').unwrap_or(comment));
+  }
+  lines.reverse();
+  (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Convert a YAML text to an atto value.
+///
+/// A text with several YAML documents is converted to a list with one
+/// value per YAML document.
+///
+/// ```
+/// let value = atto::convert::from_yaml("a: [1, x]\n---\nb: ~\n").unwrap();
+///
+/// assert_eq!(value.to_string(), "((a: (1 x)) (b: #nil))");
+/// ```
+pub fn from_yaml(text: &str) -> Result<Value, ConvertError> {
+  let mut values = vec![];
+  for document in serde_yaml::Deserializer::from_str(text) {
+    let value = serde_yaml::Value::deserialize(document);
+    values.push(yaml_value(value.map_err(ConvertError::Yaml)?));
+  }
+
+  // A text without content is an empty document
+  let empty = text
+    .lines()
+    .map(str::trim)
+    .all(|line| line.is_empty() || line.starts_with('#') || line == "---");
+  Ok(match values.len() {
+    _ if empty => Value::Document(Document::new()),
+    1 => values.remove(0),
+    _ => Value::List(values),
+  })
+}
+
+fn yaml_value(value: serde_yaml::Value) -> Value {
+  use serde_yaml::Value::*;
+
+  match value {
+    Null => Value::Nil,
+    Bool(b) => Value::Atom(b.to_string()),
+    Number(n) => Value::Atom(n.to_string()),
+    String(s) => Value::Atom(s),
+    Sequence(vec) => Value::List(vec.into_iter().map(yaml_value).collect()),
+    Mapping(mapping) => {
+      let mut document = Document::new();
+      for (key, value) in mapping {
+        document.insert(yaml_key(key), yaml_value(value));
+      }
+      Value::Document(document)
+    }
+    Tagged(tagged) => yaml_value(tagged.value),
+  }
+}
+
+// Keys which are not scalars are written in their compact atto form
+fn yaml_key(key: serde_yaml::Value) -> String {
+  match yaml_value(key) {
+    Value::Atom(atom) => atom,
+    value => value.to_string(),
+  }
+}
+
+/// Convert an atto value to JSON.
+///
+/// ```
+/// let value = atto::parse("a: (1 x) b: #nil c: ()").unwrap();
+///
+/// assert_eq!(
+///   atto::convert::to_json(&value).to_string(),
+///   r#"{"a":["1","x"],"b":null,"c":[]}"#
+/// );
+/// ```
+pub fn to_json(value: &Value) -> Json {
+  match value {
+    Value::Nil => Json::Null,
+    Value::Atom(atom) => Json::String(atom.clone()),
+    Value::List(list) => Json::Array(list.iter().map(to_json).collect()),
+    Value::Document(document) => {
+      let object = document.iter().map(|(k, v)| (k.clone(), to_json(v)));
+      Json::Object(object.collect())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_toml() {
+    let text = r#"
+      title = "TOML example"
+      ports = [ 8000, 8001 ]
+      ratio = 0.5
+
+      [owner]
+      dob = 1979-05-27T07:32:00-08:00
+
+      [[products]]
+      name = "Hammer"
+
+      [[products]]
+      name = "Nail"
+      enabled = false
+    "#;
+    let value = from_toml(text).unwrap();
+    assert_eq!(
+      value.to_string(),
+      concat!(
+        r#"(title: "TOML example" ports: (8000 8001) ratio: 0.5 "#,
+        r#"owner: (dob: "1979-05-27T07:32:00-08:00") "#,
+        "products: ((name: Hammer) (name: Nail enabled: false)))",
+      )
+    );
+
+    let text = "a = 3.0\nb = 1e300\nc = -inf\nd = nan\ne = 0.1";
+    assert_eq!(
+      from_toml(text).unwrap().to_string(),
+      "(a: 3.0 b: 1e300 c: -inf d: nan e: 0.1)"
+    );
+  }
+
+  #[test]
+  fn test_toml_docs() {
+    let text = "\
+# The name
+#
+#  of it
+name = \"x\" # trailing
+
+# not a doc comment
+
+port = 80
+# The table
+[table]
+  # of a key
+  key = 1
+# The array
+[[array]]
+key = 1
+[[array]]
+# of a key in the second table
+key = 2
+";
+    let (value, docs) = from_toml_with_docs(text).unwrap();
+    let paths = docs.iter().map(|(path, doc)| (path.to_string(), doc.as_str()));
+    assert_eq!(paths.collect::<Vec<_>>(), [
+      ("array".to_owned(), "The array"),
+      ("array.1.key".into(), "of a key in the second table"),
+      ("name".into(), "The name\n\n of it"),
+      ("table".into(), "The table"),
+      ("table.key".into(), "of a key"),
+    ]);
+    let options = crate::format::Options::default();
+    let pretty = crate::format::pretty_with_docs(&value, &options, &docs);
+    let root = crate::parser::parse_tree(&pretty).root;
+    assert_eq!(root.to_value(), value);
+    assert_eq!(root.docs(), docs);
+  }
+
+  #[test]
+  fn test_yaml() {
+    let text = "name: x ray\n? [a, b]\n: !tag 3\nnested: {}\n";
+    let value = from_yaml(text).unwrap();
+    assert_eq!(value.to_string(), r#"(name: "x ray" "(a b)": 3 nested: ())"#);
+
+    let empty = Value::Document(Document::new());
+    assert_eq!(from_yaml("").unwrap(), empty);
+    assert_eq!(from_yaml("# x\n---\n").unwrap(), empty);
+    assert_eq!(from_yaml("~").unwrap(), Value::Nil);
+  }
+
+  #[test]
+  fn test_errors() {
+    assert!(matches!(from_toml("a ="), Err(ConvertError::Toml(_))));
+    assert!(matches!(from_yaml("a: [1"), Err(ConvertError::Yaml(_))));
+    assert!(matches!(
+      "json".parse::<Format>(),
+      Err(ConvertError::UnknownFormat(_))
+    ));
+  }
+}
@@ -0,0 +1,677 @@
+//! Deserialization of atto values with serde
+//!
+//! Atoms are parsed as numbers or booleans where those are expected, nil
+//! is a unit or `None`, and an enum variant is an atom for a unit variant
+//! or a document with a single entry otherwise. Unknown keys in structs are
+//! errors which suggest the most similar field, except for structs with
+//! flattened fields. Errors are [`DecodeError`]s with the path of the value
+//! and, from [`from_str()`], its location.
+//!
+//! ```
+//! # use serde::Deserialize;
+//! #[derive(Debug, Deserialize)]
+//! struct Hero {
+//!   name:   String,
+//!   powers: Vec<String>,
+//! }
+//!
+//! let text = "name: Storm\npowers: Sandstorm";
+//! let err = atto::de::from_str::<Hero>(text).unwrap_err();
+//! assert_eq!(
+//!   err.to_string(),
+//!   r#"powers: expected list, found atom "Sandstorm" at 2:9"#
+//! );
+//!
+//! let err = atto::de::from_str::<Hero>("name: Storm pwoers: ()").unwrap_err();
+//! assert_eq!(
+//!   err.to_string(),
+//!   "pwoers: unknown key `pwoers`, did you mean `powers`? at 1:13"
+//! );
+//! ```
+
+use crate::decode::{self, DecodeError, DecodeErrorKind};
+use crate::path::Segment;
+use crate::value::{Document, Value};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use std::fmt;
+
+impl de::Error for DecodeError {
+  fn custom<T: fmt::Display>(message: T) -> Self {
+    DecodeError::new(DecodeErrorKind::Custom(message.to_string()))
+  }
+
+  fn invalid_type(found: de::Unexpected, expected: &dyn de::Expected) -> Self {
+    let expected = expected.to_string();
+    let found = found.to_string();
+    DecodeError::new(DecodeErrorKind::Expected { expected, found })
+  }
+
+  fn missing_field(field: &'static str) -> Self {
+    DecodeError::new(DecodeErrorKind::MissingKey(field.to_owned()))
+  }
+
+  fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+    DecodeError::unknown("key", field, expected)
+  }
+
+  fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+    DecodeError::unknown("variant", variant, expected)
+  }
+}
+
+/// Deserialize `value` into `T`.
+pub fn from_value<'v, T: de::Deserialize<'v>>(
+  value: &'v Value,
+) -> Result<T, DecodeError> {
+  T::deserialize(Deserializer::new(value))
+}
+
+/// Parse `text` and deserialize its root document into `T`.
+pub fn from_str<T: DeserializeOwned>(text: &str) -> Result<T, DecodeError> {
+  let root = decode::parse_root(text)?;
+  let value = root.to_value();
+  from_value(&value).map_err(|err| err.locate(text, &root))
+}
+
+/// A serde deserializer reading from a [`Value`]
+pub struct Deserializer<'v> {
+  value: &'v Value,
+}
+
+impl<'v> Deserializer<'v> {
+  pub fn new(value: &'v Value) -> Deserializer<'v> { Deserializer { value } }
+
+  fn atom(&self) -> Result<&'v str, DecodeError> {
+    match self.value {
+      Value::Atom(atom) => Ok(atom),
+      value => Err(DecodeError::expected("atom", value)),
+    }
+  }
+}
+
+macro_rules! deserialize_atoms {
+  ($($method:ident => $visit:ident,)*) => {$(
+    fn $method<V: Visitor<'v>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+      visitor.$visit(decode::atom(self.value)?)
+    }
+  )*};
+}
+
+impl<'v> de::Deserializer<'v> for Deserializer<'v> {
+  type Error = DecodeError;
+
+  deserialize_atoms! {
+    deserialize_bool => visit_bool,
+    deserialize_i8 => visit_i8,
+    deserialize_i16 => visit_i16,
+    deserialize_i32 => visit_i32,
+    deserialize_i64 => visit_i64,
+    deserialize_i128 => visit_i128,
+    deserialize_u8 => visit_u8,
+    deserialize_u16 => visit_u16,
+    deserialize_u32 => visit_u32,
+    deserialize_u64 => visit_u64,
+    deserialize_u128 => visit_u128,
+    deserialize_f32 => visit_f32,
+    deserialize_f64 => visit_f64,
+  }
+
+  fn deserialize_any<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    match self.value {
+      Value::Nil => visitor.visit_unit(),
+      Value::Atom(atom) => visitor.visit_borrowed_str(atom),
+      Value::List(list) => visit_list(list, visitor),
+      Value::Document(document) => visit_document(document, None, visitor),
+    }
+  }
+
+  fn deserialize_char<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    let mut chars = self.atom()?.chars();
+    match (chars.next(), chars.next()) {
+      (Some(c), None) => visitor.visit_char(c),
+      _ => Err(DecodeError::expected("character", self.value)),
+    }
+  }
+
+  fn deserialize_str<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    visitor.visit_borrowed_str(self.atom()?)
+  }
+
+  fn deserialize_string<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    self.deserialize_str(visitor)
+  }
+
+  fn deserialize_identifier<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    self.deserialize_str(visitor)
+  }
+
+  fn deserialize_bytes<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    visitor.visit_borrowed_bytes(self.atom()?.as_bytes())
+  }
+
+  fn deserialize_byte_buf<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    self.deserialize_bytes(visitor)
+  }
+
+  fn deserialize_option<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    match self.value {
+      Value::Nil => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    }
+  }
+
+  fn deserialize_unit<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    match self.value {
+      Value::Nil => visitor.visit_unit(),
+      value => Err(DecodeError::expected("nil", value)),
+    }
+  }
+
+  fn deserialize_unit_struct<V: Visitor<'v>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    self.deserialize_unit(visitor)
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'v>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    visitor.visit_newtype_struct(self)
+  }
+
+  fn deserialize_seq<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    match self.value {
+      Value::List(list) => visit_list(list, visitor),
+      value => Err(DecodeError::expected("list", value)),
+    }
+  }
+
+  fn deserialize_tuple<V: Visitor<'v>>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    self.deserialize_seq(visitor)
+  }
+
+  fn deserialize_tuple_struct<V: Visitor<'v>>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    self.deserialize_seq(visitor)
+  }
+
+  fn deserialize_map<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    let document = decode::document(self.value)?;
+    visit_document(document, None, visitor)
+  }
+
+  fn deserialize_struct<V: Visitor<'v>>(
+    self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    let document = decode::document(self.value)?;
+    visit_document(document, Some(fields), visitor)
+  }
+
+  fn deserialize_enum<V: Visitor<'v>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    match self.value {
+      Value::Atom(atom) => {
+        visitor.visit_enum(atom.as_str().into_deserializer())
+      }
+      Value::Document(document) if document.len() == 1 => {
+        let (key, value) = document.first().unwrap();
+        visitor.visit_enum(Variant { key, value })
+      }
+      value => Err(DecodeError::expected("enum variant", value)),
+    }
+  }
+
+  fn deserialize_ignored_any<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    visitor.visit_unit()
+  }
+}
+
+fn visit_list<'v, V: Visitor<'v>>(
+  list: &'v [Value],
+  visitor: V,
+) -> Result<V::Value, DecodeError> {
+  let mut elements = Elements { list, index: 0 };
+  let value = visitor.visit_seq(&mut elements)?;
+  match elements.index == list.len() {
+    true => Ok(value),
+    false => Err(de::Error::invalid_length(list.len(), &"fewer elements")),
+  }
+}
+
+fn visit_document<'v, V: Visitor<'v>>(
+  document: &'v Document,
+  fields: Option<&'static [&'static str]>,
+  visitor: V,
+) -> Result<V::Value, DecodeError> {
+  let entries = document.iter();
+  visitor.visit_map(Entries { entries, value: None, fields })
+}
+
+struct Elements<'v> {
+  list:  &'v [Value],
+  index: usize,
+}
+
+impl<'v> de::SeqAccess<'v> for Elements<'v> {
+  type Error = DecodeError;
+
+  fn next_element_seed<T: de::DeserializeSeed<'v>>(
+    &mut self,
+    seed: T,
+  ) -> Result<Option<T::Value>, Self::Error> {
+    let Some(element) = self.list.get(self.index) else {
+      return Ok(None);
+    };
+    let index = self.index;
+    self.index += 1;
+    let element = seed.deserialize(Deserializer::new(element));
+    element.map(Some).map_err(|err| err.within(Segment::Index(index)))
+  }
+
+  fn size_hint(&self) -> Option<usize> { Some(self.list.len() - self.index) }
+}
+
+struct Entries<'v> {
+  entries: indexmap::map::Iter<'v, String, Value>,
+  value:   Option<(&'v String, &'v Value)>,
+  fields:  Option<&'static [&'static str]>,
+}
+
+impl<'v> de::MapAccess<'v> for Entries<'v> {
+  type Error = DecodeError;
+
+  fn next_key_seed<K: de::DeserializeSeed<'v>>(
+    &mut self,
+    seed: K,
+  ) -> Result<Option<K::Value>, Self::Error> {
+    let Some((key, value)) = self.entries.next() else {
+      return Ok(None);
+    };
+    let segment = || Segment::Key(key.clone());
+    if let Some(fields) = self.fields {
+      if !fields.contains(&key.as_str()) {
+        let err = DecodeError::unknown("key", key, fields);
+        return Err(err.within(segment()));
+      }
+    }
+    self.value = Some((key, value));
+    let key = seed.deserialize(Key(key));
+    key.map(Some).map_err(|err| err.within(segment()))
+  }
+
+  fn next_value_seed<V: de::DeserializeSeed<'v>>(
+    &mut self,
+    seed: V,
+  ) -> Result<V::Value, Self::Error> {
+    let (key, value) = self.value.take().expect("value after its key");
+    let value = seed.deserialize(Deserializer::new(value));
+    value.map_err(|err| err.within(Segment::Key(key.clone())))
+  }
+
+  fn size_hint(&self) -> Option<usize> { Some(self.entries.len()) }
+}
+
+// A key deserializes like an atom
+struct Key<'v>(&'v str);
+
+macro_rules! deserialize_keys {
+  ($($method:ident => $visit:ident($type:ty),)*) => {$(
+    fn $method<V: Visitor<'v>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+      let key = crate::atoms::FromAtom::from_atom(self.0);
+      visitor.$visit(key.map_err(|err| DecodeError::new(DecodeErrorKind::Atom(err)))?)
+    }
+  )*};
+}
+
+impl<'v> de::Deserializer<'v> for Key<'v> {
+  type Error = DecodeError;
+
+  deserialize_keys! {
+    deserialize_bool => visit_bool(bool),
+    deserialize_i8 => visit_i8(i8),
+    deserialize_i16 => visit_i16(i16),
+    deserialize_i32 => visit_i32(i32),
+    deserialize_i64 => visit_i64(i64),
+    deserialize_u8 => visit_u8(u8),
+    deserialize_u16 => visit_u16(u16),
+    deserialize_u32 => visit_u32(u32),
+    deserialize_u64 => visit_u64(u64),
+  }
+
+  serde::forward_to_deserialize_any! {
+    <W: Visitor<'v>>
+    i128 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+    seq tuple tuple_struct map struct enum identifier ignored_any
+  }
+
+  fn deserialize_any<V: Visitor<'v>>(
+    self,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    visitor.visit_borrowed_str(self.0)
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'v>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    visitor.visit_newtype_struct(self)
+  }
+}
+
+// A variant written as a document with one entry
+struct Variant<'v> {
+  key:   &'v str,
+  value: &'v Value,
+}
+
+impl<'v> de::EnumAccess<'v> for Variant<'v> {
+  type Error = DecodeError;
+  type Variant = Self;
+
+  fn variant_seed<V: de::DeserializeSeed<'v>>(
+    self,
+    seed: V,
+  ) -> Result<(V::Value, Self), Self::Error> {
+    let variant = seed.deserialize(Key(self.key))?;
+    Ok((variant, self))
+  }
+}
+
+impl<'v> de::VariantAccess<'v> for Variant<'v> {
+  type Error = DecodeError;
+
+  fn unit_variant(self) -> Result<(), Self::Error> {
+    de::Deserialize::deserialize(Deserializer::new(self.value))
+      .map_err(|err: DecodeError| err.within(self.key.into()))
+  }
+
+  fn newtype_variant_seed<T: de::DeserializeSeed<'v>>(
+    self,
+    seed: T,
+  ) -> Result<T::Value, Self::Error> {
+    let value = seed.deserialize(Deserializer::new(self.value));
+    value.map_err(|err| err.within(self.key.into()))
+  }
+
+  fn tuple_variant<V: Visitor<'v>>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    let value =
+      de::Deserializer::deserialize_seq(Deserializer::new(self.value), visitor);
+    value.map_err(|err| err.within(self.key.into()))
+  }
+
+  fn struct_variant<V: Visitor<'v>>(
+    self,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error> {
+    let value = de::Deserializer::deserialize_struct(
+      Deserializer::new(self.value),
+      "",
+      fields,
+      visitor,
+    );
+    value.map_err(|err| err.within(self.key.into()))
+  }
+}
+
+/// Any value, for parts of a type without a fixed shape. With other
+/// deserializers, numbers and booleans become atoms.
+impl<'de> de::Deserialize<'de> for Value {
+  fn deserialize<D: de::Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    deserializer.deserialize_any(ValueVisitor)
+  }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+  type Value = Value;
+
+  fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "an atto value")
+  }
+
+  fn visit_unit<E: de::Error>(self) -> Result<Value, E> { Ok(Value::Nil) }
+
+  fn visit_none<E: de::Error>(self) -> Result<Value, E> { Ok(Value::Nil) }
+
+  fn visit_some<D: de::Deserializer<'de>>(
+    self,
+    deserializer: D,
+  ) -> Result<Value, D::Error> {
+    de::Deserialize::deserialize(deserializer)
+  }
+
+  fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+    Ok(Value::Atom(v.to_string()))
+  }
+
+  fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+    Ok(Value::Atom(v.to_string()))
+  }
+
+  fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+    Ok(Value::Atom(v.to_string()))
+  }
+
+  fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+    Ok(Value::Atom(v.to_string()))
+  }
+
+  fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+    Ok(Value::Atom(v.to_owned()))
+  }
+
+  fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+    Ok(Value::Atom(v))
+  }
+
+  fn visit_seq<A: de::SeqAccess<'de>>(
+    self,
+    mut seq: A,
+  ) -> Result<Value, A::Error> {
+    let mut list = vec![];
+    while let Some(value) = seq.next_element()? {
+      list.push(value);
+    }
+    Ok(Value::List(list))
+  }
+
+  fn visit_map<A: de::MapAccess<'de>>(
+    self,
+    mut map: A,
+  ) -> Result<Value, A::Error> {
+    let mut document = Document::new();
+    while let Some((key, value)) = map.next_entry::<String, Value>()? {
+      document.insert(key, value);
+    }
+    Ok(Value::Document(document))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::atoms::{atom, ByteSize};
+  use serde::Deserialize;
+  use std::collections::BTreeMap;
+  use std::time::Duration;
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Team {
+    #[serde(rename = "squadName")]
+    name:    String,
+    active:  bool,
+    members: Vec<Member>,
+  }
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Member {
+    name:   String,
+    age:    u32,
+    powers: Vec<String>,
+  }
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  enum Color {
+    Red,
+    Gray(u8),
+    Rgb(u8, u8, u8),
+    Named { name: String },
+  }
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Settings {
+    #[serde(with = "atom")]
+    timeout: Duration,
+    cache:   Option<ByteSize>,
+    colors:  BTreeMap<String, Color>,
+    #[serde(default)]
+    ports:   BTreeMap<u16, String>,
+  }
+
+  const TEAM: &str = r#"
+squadName: "Super hero squad"
+active: true
+members: (
+  (name: "Molecule Man" age: 29 powers: (radiation "turning tiny"))
+  (name: "Madame Uppercut" age: 39 powers: ("million tonne punch"))
+  (
+    name: "Eternal Flame"
+    age: 1000000
+    powers: Sandstorm
+  )
+)
+"#;
+
+  #[test]
+  fn test_deserialize() {
+    let team = from_str::<Team>(&TEAM.replace("Sandstorm", "(immortality)"));
+    let team = team.unwrap();
+    assert_eq!(team.name, "Super hero squad");
+    assert_eq!(team.members[2].age, 1_000_000);
+
+    let text = "timeout: 1m30s cache: 10MiB colors: (a: Red b: (Gray: 128) \
+      c: (Rgb: (1 2 3)) d: (Named: (name: teal))) ports: (80: http)";
+    let settings = from_str::<Settings>(text).unwrap();
+    assert_eq!(settings, Settings {
+      timeout: Duration::from_secs(90),
+      cache:   Some(ByteSize(10 << 20)),
+      colors:  BTreeMap::from([
+        ("a".to_owned(), Color::Red),
+        ("b".to_owned(), Color::Gray(128)),
+        ("c".to_owned(), Color::Rgb(1, 2, 3)),
+        ("d".to_owned(), Color::Named { name: "teal".to_owned() }),
+      ]),
+      ports:   BTreeMap::from([(80, "http".to_owned())]),
+    });
+
+    let text = "timeout: 1s cache: #nil colors: ()";
+    assert_eq!(from_str::<Settings>(text).unwrap().cache, None);
+    let nils =
+      from_str::<BTreeMap<String, Vec<Option<String>>>>("a: (#nil \"#nil\")");
+    assert_eq!(nils.unwrap()["a"], [None, Some("#nil".to_owned())]);
+
+    let value = crate::parse("a: (x y)").unwrap();
+    let borrowed = from_value::<BTreeMap<&str, Vec<&str>>>(&value).unwrap();
+    assert_eq!(borrowed["a"], ["x", "y"]);
+
+    let value = crate::parse("a: (x #nil (b: ()))").unwrap();
+    assert_eq!(from_value::<Value>(&value).unwrap(), value);
+  }
+
+  #[test]
+  fn test_errors() {
+    let error =
+      |text: &str| from_str::<Settings>(text).unwrap_err().to_string();
+
+    assert_eq!(
+      from_str::<Team>(TEAM).unwrap_err().to_string(),
+      r#"members.2.powers: expected list, found atom "Sandstorm" at 10:13"#
+    );
+    assert_eq!(
+      error("timeout: 5 colors: ()"),
+      "timeout: invalid duration: expected unit at 1:10"
+    );
+    assert_eq!(error("colors: ()"), "missing key `timeout` at 1:1");
+    assert_eq!(
+      error("timeout: 1s colors: (a: Rde)"),
+      "colors.a: unknown variant `Rde`, did you mean `Red`? at 1:25"
+    );
+    assert_eq!(
+      error("timeout: 1s colors: (a: (Rgb: (1 2 3 4)))"),
+      "colors.a.Rgb: invalid length 4, expected fewer elements at 1:31"
+    );
+    assert_eq!(
+      error("timeout: 1s colors: ()\n  colour: ()"),
+      "colour: unknown key `colour`, did you mean `colors`? at 2:3"
+    );
+    assert_eq!(
+      error("timeout: 1s colors: () teal: 1"),
+      "teal: unknown key `teal` at 1:24"
+    );
+  }
+}
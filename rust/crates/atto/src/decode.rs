@@ -0,0 +1,429 @@
+//! Conversion of atto values into Rust types
+//!
+//! [`FromAtto`] is implemented for [`Value`], every [`FromAtom`] type,
+//! options, vectors and maps with string keys. Errors carry the path of
+//! the offending value, and [`from_str()`] adds its position in the text.
+
+use crate::atoms::{
+  AtomError, ByteSize, FromAtom, Percent, Timestamp, Version,
+};
+use crate::diagnostic::Diagnostic;
+use crate::parser::{parse_tree, ErrorKind, Parsed};
+use crate::path::{Path, Segment};
+use crate::syntax::{Node, NodeKind, Position, Span};
+use crate::value::{Document, Value};
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// A type which can be built from an atto value.
+///
+/// ```
+/// use atto::decode::{document, field, from_str, DecodeError, FromAtto};
+/// use atto::Value;
+/// use std::time::Duration;
+///
+/// struct Server {
+///   port:    u16,
+///   timeout: Option<Duration>,
+/// }
+///
+/// impl FromAtto for Server {
+///   fn from_atto(value: &Value) -> Result<Self, DecodeError> {
+///     let server = document(value)?;
+///     Ok(Server {
+///       port:    field(server, "port")?,
+///       timeout: field(server, "timeout")?,
+///     })
+///   }
+/// }
+///
+/// let server = from_str::<Server>("port: 8080 timeout: 1m30s").unwrap();
+/// assert_eq!(server.timeout, Some(Duration::from_secs(90)));
+///
+/// let Err(err) = from_str::<Server>("port: 8080\ntimeout: 30x") else {
+///   panic!("30x is no duration");
+/// };
+/// assert_eq!(
+///   err.to_string(),
+///   "timeout: invalid duration: unknown unit `x` at 2:12"
+/// );
+/// ```
+pub trait FromAtto: Sized {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError>;
+}
+
+/// A value which could not be converted, with its path and, if known, its
+/// location
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DecodeError {
+  pub path:     Path,
+  pub kind:     Box<DecodeErrorKind>,
+  pub span:     Option<Span>,
+  pub position: Option<Position>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeErrorKind {
+  /// The text is not valid atto
+  Parse(ErrorKind),
+  Expected {
+    expected: String,
+    found:    String,
+  },
+  Atom(AtomError),
+  MissingKey(String),
+  /// An unknown key or enum variant, with the most similar known one
+  Unknown {
+    what:       &'static str,
+    name:       String,
+    suggestion: Option<String>,
+  },
+  Custom(String),
+}
+
+impl fmt::Display for DecodeErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DecodeErrorKind::Parse(kind) => write!(f, "{kind}"),
+      DecodeErrorKind::Expected { expected, found } => {
+        write!(f, "expected {expected}, found {found}")
+      }
+      DecodeErrorKind::Atom(err) => write!(f, "{err}"),
+      DecodeErrorKind::MissingKey(key) => write!(f, "missing key `{key}`"),
+      DecodeErrorKind::Unknown { what, name, suggestion } => {
+        write!(f, "unknown {what} `{name}`")?;
+        match suggestion {
+          Some(suggestion) => write!(f, ", did you mean `{suggestion}`?"),
+          None => Ok(()),
+        }
+      }
+      DecodeErrorKind::Custom(message) => f.write_str(message),
+    }
+  }
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if !self.path.is_root() {
+      write!(f, "{}: ", self.path)?;
+    }
+    write!(f, "{}", self.kind)?;
+    if let Some(position) = self.position {
+      write!(f, " at {position}")?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for DecodeError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match &*self.kind {
+      DecodeErrorKind::Atom(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl DecodeError {
+  pub fn new(kind: DecodeErrorKind) -> DecodeError {
+    DecodeError {
+      path:     Path::root(),
+      kind:     Box::new(kind),
+      span:     None,
+      position: None,
+    }
+  }
+
+  /// An error for a value of the wrong type.
+  pub fn expected(expected: &str, found: &Value) -> DecodeError {
+    let found = match found {
+      Value::Atom(atom) => format!("atom {atom:?}"),
+      _ => found.type_name().to_owned(),
+    };
+    let expected = expected.to_owned();
+    DecodeError::new(DecodeErrorKind::Expected { expected, found })
+  }
+
+  /// An error for an unknown key or variant `name`, suggesting the most
+  /// similar of `known` if there is one close enough.
+  pub fn unknown(
+    what: &'static str,
+    name: &str,
+    known: &[&str],
+  ) -> DecodeError {
+    let suggestion = suggest(name, known).map(str::to_owned);
+    let name = name.to_owned();
+    DecodeError::new(DecodeErrorKind::Unknown { what, name, suggestion })
+  }
+
+  /// The error of a child value, with `segment` put in front of its path.
+  pub fn within(mut self, segment: Segment) -> DecodeError {
+    self.path.0.insert(0, segment);
+    self
+  }
+
+  /// Add the location of the error in `text`, which was parsed into `root`.
+  ///
+  /// The location is the value at the path or, if the path leads to a
+  /// missing key, the innermost value on the path. Errors in bare atoms
+  /// point into the atom and unknown keys to the key.
+  pub fn locate(mut self, text: &str, root: &Node) -> DecodeError {
+    let mut node = root;
+    for segment in self.path.segments() {
+      match node.get(segment) {
+        Some(child) => node = child,
+        None => break,
+      }
+    }
+
+    let mut span = node.span;
+    match (&*self.kind, &node.kind) {
+      (DecodeErrorKind::Atom(err), NodeKind::Atom(atom))
+        if text[span.start..span.end] == *atom =>
+      {
+        span.start += err.offset.min(span.len());
+      }
+      (DecodeErrorKind::Unknown { what: "key", .. }, _) => {
+        if let Some(entry) = root.entry_at_path(&self.path) {
+          span = entry.key_span;
+        }
+      }
+      _ => {}
+    }
+    self.span = Some(span);
+    self.position = Some(Position::of(text, span.start));
+    self
+  }
+
+  /// The error with the line of `text` it is in, as located by
+  /// [`locate`](Self::locate), and its span underlined.
+  ///
+  /// ```
+  /// # use std::collections::BTreeMap;
+  /// let text = "ports: (80 443)\nadmin: (8O8O)";
+  /// let ports = atto::decode::from_str::<BTreeMap<String, Vec<u16>>>(text);
+  /// let Err(err) = ports else { panic!("8O8O is no port") };
+  ///
+  /// assert_eq!(
+  ///   err.render(text),
+  ///   "\
+  /// error: invalid integer: expected digits
+  ///  --> 2:9
+  ///   |
+  /// 2 | admin: (8O8O)
+  ///   |         ^^^^ admin.0
+  /// "
+  /// );
+  /// ```
+  pub fn render(&self, text: &str) -> String {
+    Diagnostic::from(self).render(text)
+  }
+}
+
+// The most similar of `known` to `name` by edit distance, if it differs in
+// at most a third of its characters
+fn suggest<'k>(name: &str, known: &[&'k str]) -> Option<&'k str> {
+  let max = (name.chars().count() / 3).max(1);
+  let distances = known.iter().map(|k| (edit_distance(name, k), *k));
+  let (distance, nearest) = distances.min_by_key(|(distance, _)| *distance)?;
+  (distance <= max).then_some(nearest)
+}
+
+// Edits are insertions, deletions, substitutions and transpositions of
+// neighbouring characters
+fn edit_distance(a: &str, b: &str) -> usize {
+  let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+  let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+  for i in 1..=a.len() {
+    let mut row = vec![i; b.len() + 1];
+    for j in 1..=b.len() {
+      let above = &rows[i - 1];
+      let substitution = above[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+      row[j] = substitution.min(above[j] + 1).min(row[j - 1] + 1);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+      }
+    }
+    rows.push(row);
+  }
+  rows[a.len()][b.len()]
+}
+
+/// Convert `value` to `T`.
+pub fn from_value<T: FromAtto>(value: &Value) -> Result<T, DecodeError> {
+  T::from_atto(value)
+}
+
+/// Parse `text` and convert its root document to `T`.
+pub fn from_str<T: FromAtto>(text: &str) -> Result<T, DecodeError> {
+  let root = parse_root(text)?;
+  T::from_atto(&root.to_value()).map_err(|err| err.locate(text, &root))
+}
+
+// The syntax tree of `text`, or its first syntax error
+pub(crate) fn parse_root(text: &str) -> Result<Node, DecodeError> {
+  let Parsed { root, errors } = parse_tree(text);
+  match errors.into_iter().next() {
+    Some(err) => Err(DecodeError {
+      path:     Path::root(),
+      kind:     Box::new(DecodeErrorKind::Parse(err.kind)),
+      span:     Some(err.span),
+      position: Some(err.position),
+    }),
+    None => Ok(root),
+  }
+}
+
+/// The document of `value`, or an error if it is something else. An empty
+/// list is an empty document, as both are written `()`.
+pub fn document(value: &Value) -> Result<&Document, DecodeError> {
+  static EMPTY: LazyLock<Document> = LazyLock::new(Document::new);
+
+  match value {
+    Value::Document(document) => Ok(document),
+    Value::List(list) if list.is_empty() => Ok(&EMPTY),
+    _ => Err(DecodeError::expected("document", value)),
+  }
+}
+
+/// Convert the value of `key`. A missing key is converted as nil, which
+/// gives `None` for an `Option`.
+pub fn field<T: FromAtto>(
+  document: &Document,
+  key: &str,
+) -> Result<T, DecodeError> {
+  let value = document.get(key).unwrap_or(&Value::Nil);
+  T::from_atto(value).map_err(|err| err.within(Segment::Key(key.to_owned())))
+}
+
+/// Convert an atom with [`FromAtom`], for implementing [`FromAtto`] on
+/// types which have an atom form.
+pub fn atom<T: FromAtom>(value: &Value) -> Result<T, DecodeError> {
+  match value {
+    Value::Atom(atom) => T::from_atom(atom)
+      .map_err(|err| DecodeError::new(DecodeErrorKind::Atom(err))),
+    _ => Err(DecodeError::expected(T::EXPECTED, value)),
+  }
+}
+
+macro_rules! atom_types {
+  ($($type:ty),*) => {$(
+    impl FromAtto for $type {
+      fn from_atto(value: &Value) -> Result<Self, DecodeError> { atom(value) }
+    }
+  )*};
+}
+
+atom_types!(String, bool, f32, f64);
+atom_types!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+atom_types!(IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr);
+atom_types!(Duration, ByteSize, Percent, Timestamp, Version);
+
+impl FromAtto for Value {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> { Ok(value.clone()) }
+}
+
+impl<T: FromAtto> FromAtto for Option<T> {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> {
+    match value {
+      Value::Nil => Ok(None),
+      _ => T::from_atto(value).map(Some),
+    }
+  }
+}
+
+impl<T: FromAtto> FromAtto for Vec<T> {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> {
+    let Value::List(list) = value else {
+      return Err(DecodeError::expected("list", value));
+    };
+    let elements = list.iter().enumerate().map(|(index, element)| {
+      T::from_atto(element).map_err(|err| err.within(Segment::Index(index)))
+    });
+    elements.collect()
+  }
+}
+
+fn entries<T: FromAtto, M: FromIterator<(String, T)>>(
+  value: &Value,
+) -> Result<M, DecodeError> {
+  let entries = document(value)?.iter().map(|(key, value)| {
+    let value = T::from_atto(value);
+    Ok((key.clone(), value.map_err(|err| err.within(key.as_str().into()))?))
+  });
+  entries.collect()
+}
+
+impl<T: FromAtto> FromAtto for IndexMap<String, T> {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> { entries(value) }
+}
+
+impl<T: FromAtto> FromAtto for BTreeMap<String, T> {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> { entries(value) }
+}
+
+impl<T: FromAtto, S: BuildHasher + Default> FromAtto for HashMap<String, T, S> {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> { entries(value) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn error<T: FromAtto + fmt::Debug>(text: &str) -> String {
+    from_str::<T>(text).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn test_from_str() {
+    let text = "a: (10MiB 1.5GB) b: ()";
+    let sizes = from_str::<BTreeMap<String, Vec<ByteSize>>>(text).unwrap();
+    assert_eq!(sizes["a"], [ByteSize(10 << 20), ByteSize(1_500_000_000)]);
+    assert!(sizes["b"].is_empty());
+
+    let text = "a: \"2023-10-14T15:06:05Z\"";
+    let times = from_str::<IndexMap<String, Timestamp>>(text).unwrap();
+    assert_eq!(times["a"].unix_seconds(), 1_697_295_965);
+    assert_eq!(from_value::<Option<Percent>>(&Value::Nil), Ok(None));
+  }
+
+  #[test]
+  fn test_errors() {
+    type Sizes = HashMap<String, Vec<ByteSize>>;
+    assert_eq!(
+      error::<Sizes>("a: (1kB\n 2XB)"),
+      "a.1: invalid byte size: unknown unit `XB` at 2:3"
+    );
+    assert_eq!(
+      error::<Sizes>("a: (1kB \"2 XB\")"),
+      "a.1: invalid byte size: expected end of atom at 1:9"
+    );
+    assert_eq!(
+      error::<Sizes>("a: (b: 1)"),
+      "a: expected list, found document at 1:4"
+    );
+    assert_eq!(error::<Sizes>("a: (1kB"), "unclosed parenthesis at 1:4");
+    assert_eq!(
+      error::<Vec<u8>>("a: 1"),
+      "expected list, found document at 1:1"
+    );
+
+    let err = from_value::<Version>(&Value::Atom("1.x".into())).unwrap_err();
+    assert_eq!(err.to_string(), "invalid semantic version: expected digits");
+    assert_eq!(err.position, None);
+  }
+
+  #[test]
+  fn test_suggest() {
+    let known = ["color", "colors", "size"];
+    assert_eq!(suggest("colour", &known), Some("color"));
+    assert_eq!(suggest("szie", &known), Some("size"));
+    assert_eq!(suggest("weight", &known), None);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+  }
+}
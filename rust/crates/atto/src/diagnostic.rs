@@ -0,0 +1,366 @@
+//! Rendering of errors with the source line they are in, like rustc
+//!
+//! A [`Diagnostic`] is a message with an optional span in the source text,
+//! a label for the span, notes and a help text. The [`Renderer`] prints it
+//! with the line of the span and a caret underline, optionally with ANSI
+//! colors. Parse and decode errors convert into diagnostics, and so do
+//! the unmatched-input tokens of an axlex lexer with
+//! [`Diagnostic::unmatched`].
+
+use crate::decode::{DecodeError, DecodeErrorKind};
+use crate::lex::R_ID_UNEXPECTED_END;
+use crate::parser::ParseError;
+use crate::syntax::{Position, Span};
+use axlex::Token;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+  Error,
+  Warning,
+  Note,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Severity::Error => write!(f, "error"),
+      Severity::Warning => write!(f, "warning"),
+      Severity::Note => write!(f, "note"),
+    }
+  }
+}
+
+/// A message about a span of a source text
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub message:  String,
+  pub span:     Option<Span>,
+  /// Written after the underline of the span
+  pub label:    Option<String>,
+  pub notes:    Vec<String>,
+  pub help:     Option<String>,
+}
+
+impl Diagnostic {
+  pub fn new(severity: Severity, message: impl Into<String>) -> Diagnostic {
+    Diagnostic {
+      severity,
+      message: message.into(),
+      span: None,
+      label: None,
+      notes: vec![],
+      help: None,
+    }
+  }
+
+  pub fn error(message: impl Into<String>) -> Diagnostic {
+    Diagnostic::new(Severity::Error, message)
+  }
+
+  pub fn warning(message: impl Into<String>) -> Diagnostic {
+    Diagnostic::new(Severity::Warning, message)
+  }
+
+  pub fn with_span(mut self, span: Span) -> Diagnostic {
+    self.span = Some(span);
+    self
+  }
+
+  pub fn with_label(mut self, label: impl Into<String>) -> Diagnostic {
+    self.label = Some(label.into());
+    self
+  }
+
+  pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+    self.notes.push(note.into());
+    self
+  }
+
+  pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+    self.help = Some(help.into());
+    self
+  }
+
+  /// A diagnostic for a token an axlex lexer could not match with a rule
+  /// of the current group, or for the end of the input in a group which
+  /// needs more.
+  ///
+  /// ```
+  /// # use atto::diagnostic::{Diagnostic, Renderer};
+  /// let text = "a: \"b";
+  /// let tokens = atto::lex::tokens(text.as_bytes()).collect::<Vec<_>>();
+  /// let end = Diagnostic::unmatched(tokens.last().unwrap());
+  ///
+  /// assert_eq!(
+  ///   Renderer::new().render(&end, text),
+  ///   "\
+  /// error: unexpected end of input
+  ///  --> 1:6
+  ///   |
+  /// 1 | a: \"b
+  ///   |      ^
+  /// "
+  /// );
+  /// ```
+  pub fn unmatched(token: &Token) -> Diagnostic {
+    let span = Span::new(token.index - token.data.len(), token.index);
+    let message = match token.rule_id {
+      R_ID_UNEXPECTED_END => "unexpected end of input".to_owned(),
+      _ => {
+        format!("unmatched input `{}`", String::from_utf8_lossy(&token.data))
+      }
+    };
+    Diagnostic::error(message).with_span(span)
+  }
+
+  /// The diagnostic rendered without colors.
+  pub fn render(&self, text: &str) -> String {
+    Renderer::new().render(self, text)
+  }
+}
+
+impl From<&ParseError> for Diagnostic {
+  fn from(err: &ParseError) -> Self {
+    Diagnostic::error(err.kind.to_string()).with_span(err.span)
+  }
+}
+
+impl From<&DecodeError> for Diagnostic {
+  /// The path of the value becomes the label, and a suggested key or
+  /// variant the help.
+  fn from(err: &DecodeError) -> Self {
+    let mut diagnostic = match &*err.kind {
+      DecodeErrorKind::Unknown { what, name, suggestion } => {
+        let diagnostic = Diagnostic::error(format!("unknown {what} `{name}`"));
+        match suggestion {
+          Some(suggestion) => {
+            diagnostic.with_help(format!("did you mean `{suggestion}`?"))
+          }
+          None => diagnostic,
+        }
+      }
+      kind => Diagnostic::error(kind.to_string()),
+    };
+    diagnostic.span = err.span;
+    match err.path.is_root() {
+      true => diagnostic,
+      false => diagnostic.with_label(err.path.to_string()),
+    }
+  }
+}
+
+/// Renders diagnostics, by default without colors or file name.
+///
+/// ```
+/// # use atto::diagnostic::{Diagnostic, Renderer};
+/// # use atto::syntax::Span;
+/// let text = "name: atto\nversion: 1.0\n";
+/// let diagnostic = Diagnostic::error("invalid semantic version")
+///   .with_span(Span::new(20, 23))
+///   .with_label("expected three numbers")
+///   .with_note("versions are written as in semver 2.0")
+///   .with_help("add the patch number: `1.0.0`");
+///
+/// assert_eq!(
+///   Renderer::new().origin("Cargo.atto").render(&diagnostic, text),
+///   "\
+/// error: invalid semantic version
+///  --> Cargo.atto:2:10
+///   |
+/// 2 | version: 1.0
+///   |          ^^^ expected three numbers
+///   |
+///   = note: versions are written as in semver 2.0
+///   = help: add the patch number: `1.0.0`
+/// "
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Renderer {
+  color:  bool,
+  origin: Option<String>,
+}
+
+// ANSI escape sequences
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+impl Renderer {
+  pub fn new() -> Renderer { Renderer::default() }
+
+  /// Use ANSI colors.
+  pub fn color(mut self, color: bool) -> Renderer {
+    self.color = color;
+    self
+  }
+
+  /// The name of the source, usually a file name, written before the
+  /// position.
+  pub fn origin(mut self, origin: impl Into<String>) -> Renderer {
+    self.origin = Some(origin.into());
+    self
+  }
+
+  fn paint(&self, style: &str, text: &str) -> String {
+    match self.color {
+      true => format!("{style}{text}{RESET}"),
+      false => text.to_owned(),
+    }
+  }
+
+  fn severity_style(severity: Severity) -> &'static str {
+    match severity {
+      Severity::Error => "\x1b[1;31m",
+      Severity::Warning => "\x1b[1;33m",
+      Severity::Note => "\x1b[1;32m",
+    }
+  }
+
+  pub fn render(&self, diagnostic: &Diagnostic, text: &str) -> String {
+    let style = Renderer::severity_style(diagnostic.severity);
+    let severity = self.paint(style, &diagnostic.severity.to_string());
+    let message = self.paint(BOLD, &diagnostic.message);
+    let colon = self.paint(BOLD, ":");
+    let mut rendered = format!("{severity}{colon} {message}\n");
+
+    let mut gutter = String::from("  ");
+    if let Some(span) = diagnostic.span {
+      let start = span.start.min(text.len());
+      let position = Position::of(text, start);
+      let number = position.line.to_string();
+      gutter = " ".repeat(number.len() + 1);
+
+      let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+      let line_end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+      let line = &text[line_start..line_end];
+      // Tabs are kept so that the underline lines up with the text
+      let indent = text[line_start..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+      let end = span.end.clamp(start, line_end);
+      let carets = "^".repeat(text[start..end].chars().count().max(1));
+
+      let origin = match &self.origin {
+        Some(origin) => format!("{origin}:{position}"),
+        None => position.to_string(),
+      };
+      let bar = self.paint(BLUE, "|");
+      let arrow = self.paint(BLUE, "-->");
+      rendered.push_str(&format!("{}{arrow} {origin}\n", &gutter[1..]));
+      rendered.push_str(&format!("{gutter}{bar}\n"));
+      rendered
+        .push_str(&format!("{} {bar} {line}\n", self.paint(BLUE, &number)));
+      rendered.push_str(&format!(
+        "{gutter}{bar} {indent}{}",
+        self.paint(style, &carets)
+      ));
+      if let Some(label) = &diagnostic.label {
+        rendered.push_str(&format!(" {}", self.paint(style, label)));
+      }
+      rendered.push('\n');
+    } else if let Some(label) = &diagnostic.label {
+      rendered.push_str(&format!("  {} {label}\n", self.paint(BLUE, "-->")));
+    }
+
+    let help = diagnostic.help.iter().map(|help| ("help", help));
+    let notes = diagnostic.notes.iter().map(|note| ("note", note)).chain(help);
+    for (i, (kind, note)) in notes.enumerate() {
+      let bar = self.paint(BLUE, "|");
+      if i == 0 && diagnostic.span.is_some() {
+        rendered.push_str(&format!("{gutter}{bar}\n"));
+      }
+      let equals = self.paint(BLUE, "=");
+      let kind = self.paint(BOLD, &format!("{kind}:"));
+      rendered.push_str(&format!("{gutter}{equals} {kind} {note}\n"));
+    }
+    rendered
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::lex::R_ID_CATCH_ALL;
+
+  #[test]
+  fn test_render() {
+    let text = "a: (b\n\tc: (x y";
+    let err = crate::parse(text).unwrap_err();
+    assert_eq!(
+      Diagnostic::from(&err).render(text),
+      "\
+error: unclosed parenthesis
+ --> 1:4
+  |
+1 | a: (b
+  |    ^
+"
+    );
+
+    let diagnostic = Diagnostic::warning("two values")
+      .with_span(Span::new(10, 14))
+      .with_label("list");
+    assert_eq!(
+      diagnostic.render(text),
+      "\
+warning: two values
+ --> 2:5
+  |
+2 | \tc: (x y
+  | \t   ^^^^ list
+"
+    );
+    let colored = Renderer::new().color(true).render(&diagnostic, text);
+    assert!(colored.starts_with(
+      "\x1b[1;33mwarning\x1b[0m\x1b[1m:\x1b[0m \x1b[1mtwo values"
+    ));
+
+    let diagnostic =
+      Diagnostic::error("no span").with_label("a.b").with_help("look");
+    assert_eq!(
+      diagnostic.render(""),
+      "error: no span\n  --> a.b\n  = help: look\n"
+    );
+  }
+
+  #[test]
+  fn test_conversions() {
+    let text = "name: Storm\npwoers: ()";
+    let err = crate::de::from_str::<Hero>(text).unwrap_err();
+    assert_eq!(
+      Diagnostic::from(&err).render(text),
+      "\
+error: unknown key `pwoers`
+ --> 2:1
+  |
+2 | pwoers: ()
+  | ^^^^^^ pwoers
+  |
+  = help: did you mean `powers`?
+"
+    );
+
+    let token = Token {
+      rule_id:  R_ID_CATCH_ALL,
+      group_id: 0,
+      data:     b"$$".to_vec(),
+      index:    5,
+    };
+    let diagnostic = Diagnostic::unmatched(&token);
+    assert_eq!(
+      (diagnostic.message.as_str(), diagnostic.span),
+      ("unmatched input `$$`", Some(Span::new(3, 5)))
+    );
+  }
+
+  #[derive(Debug, serde::Deserialize)]
+  #[allow(dead_code)]
+  struct Hero {
+    name:   String,
+    powers: Vec<String>,
+  }
+}
@@ -0,0 +1,487 @@
+//! Structural diff between two atto values
+//!
+//! Documents are compared by key, so reordered entries are no change, and
+//! lists are aligned by their longest common subsequence. Paths of removed
+//! list elements refer to the old list, all other paths to the new one.
+
+use crate::patch::{apply_operation, Operation, Patch};
+use crate::path::{Path, Segment};
+use crate::value::{Document, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A single difference between two values
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+  Added { path: Path, value: Value },
+  Removed { path: Path, value: Value },
+  Changed { path: Path, old: Value, new: Value },
+}
+
+impl Change {
+  pub fn path(&self) -> &Path {
+    match self {
+      Change::Added { path, .. } => path,
+      Change::Removed { path, .. } => path,
+      Change::Changed { path, .. } => path,
+    }
+  }
+}
+
+/// The differences between two values in document and list order
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Diff {
+  pub changes: Vec<Change>,
+}
+
+/// Compute the structural diff from `old` to `new`.
+///
+/// ```
+/// # use atto::{diff::diff, value::Document, Value};
+/// let atom = |s: &str| Value::Atom(s.to_owned());
+/// let mut old = Document::new();
+/// old.insert("name".to_owned(), atom("John"));
+/// old.insert("age".to_owned(), atom("42"));
+/// let mut new = Document::new();
+/// new.insert("name".to_owned(), atom("Jane"));
+/// new.insert("city".to_owned(), atom("Bern"));
+///
+/// let diff = diff(&Value::Document(old), &Value::Document(new));
+/// assert_eq!(
+///   diff.to_string(),
+///   "~ name: John -> Jane\n- age: 42\n+ city: Bern\n"
+/// );
+/// ```
+pub fn diff(old: &Value, new: &Value) -> Diff {
+  let mut changes = vec![];
+  diff_value(&mut changes, &mut Path::root(), old, new);
+  Diff { changes }
+}
+
+impl Diff {
+  pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+
+  pub fn len(&self) -> usize { self.changes.len() }
+
+  /// The diff as machine-readable atto value.
+  ///
+  /// It is a list with one document per change with the keys `op` (`add`,
+  /// `remove` or `replace`), `path` and `value`, and `old` for replacements
+  /// and removals.
+  ///
+  /// ```
+  /// # use atto::{diff::diff, Value};
+  /// let old = Value::List(vec![Value::Atom("a".to_owned())]);
+  /// let new = Value::List(vec![Value::Atom("b".to_owned())]);
+  ///
+  /// let value = diff(&old, &new).to_value();
+  /// assert_eq!(value.to_string(), "((op: replace path: 0 old: a value: b))");
+  /// ```
+  pub fn to_value(&self) -> Value {
+    Value::List(self.changes.iter().map(change_value).collect())
+  }
+
+  /// The patch which makes `new` of `old`, the value the diff is from.
+  ///
+  /// Removals and replacements are preceded by a test of the old value, and
+  /// removed list elements are addressed by their index in the list as
+  /// patched so far.
+  ///
+  /// ```
+  /// # use atto::{diff::diff, patch::apply};
+  /// let old = atto::parse("a: (x y) b: 1").unwrap();
+  /// let new = atto::parse("a: (z x) b: 2").unwrap();
+  ///
+  /// let mut value = old.clone();
+  /// apply(&mut value, &diff(&old, &new).to_patch(&old)).unwrap();
+  /// assert_eq!(value, new);
+  /// ```
+  pub fn to_patch(&self, old: &Value) -> Patch {
+    // The value patched so far tells which parents are lists, and the
+    // shift of a list is the number of elements added minus removed so far
+    let mut value = old.clone();
+    let mut shifts = HashMap::<Path, isize>::new();
+    let mut operations = vec![];
+    for change in &self.changes {
+      let parent = change.path().parent().unwrap_or_default();
+      let list = matches!(value.get_path(&parent), Some(Value::List(_)));
+      let shift = shifts.entry(parent.clone()).or_insert(0);
+      let ops = match change {
+        Change::Added { path, value } => {
+          *shift += isize::from(list);
+          let value = value.clone();
+          vec![Operation::Add { path: path.clone(), value }]
+        }
+        Change::Removed { path, value } => {
+          let path = match path.last() {
+            Some(&Segment::Index(index)) if list => {
+              let index = index.wrapping_add_signed(*shift);
+              *shift -= 1;
+              parent.join(Segment::Index(index))
+            }
+            _ => path.clone(),
+          };
+          let test =
+            Operation::Test { path: path.clone(), value: value.clone() };
+          vec![test, Operation::Remove { path }]
+        }
+        Change::Changed { path, old, new } => {
+          let test =
+            Operation::Test { path: path.clone(), value: old.clone() };
+          vec![test, Operation::Replace {
+            path:  path.clone(),
+            value: new.clone(),
+          }]
+        }
+      };
+      for operation in &ops {
+        let _ = apply_operation(&mut value, operation);
+      }
+      operations.extend(ops);
+    }
+    Patch { operations }
+  }
+}
+
+fn change_value(change: &Change) -> Value {
+  let atom = |s: &str| Value::Atom(s.to_owned());
+  let mut document = Document::new();
+  let mut insert = |key: &str, value: Value| {
+    document.insert(key.to_owned(), value);
+  };
+
+  match change {
+    Change::Added { path, value } => {
+      insert("op", atom("add"));
+      insert("path", atom(&path.to_string()));
+      insert("value", value.clone());
+    }
+    Change::Removed { path, value } => {
+      insert("op", atom("remove"));
+      insert("path", atom(&path.to_string()));
+      insert("old", value.clone());
+    }
+    Change::Changed { path, old, new } => {
+      insert("op", atom("replace"));
+      insert("path", atom(&path.to_string()));
+      insert("old", old.clone());
+      insert("value", new.clone());
+    }
+  }
+
+  Value::Document(document)
+}
+
+impl fmt::Display for Diff {
+  /// Human-readable rendering with one line per change, prefixed with `+`
+  /// for added, `-` for removed and `~` for changed values.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for change in &self.changes {
+      match change {
+        Change::Added { path, value } => writeln!(f, "+ {path}: {value}")?,
+        Change::Removed { path, value } => writeln!(f, "- {path}: {value}")?,
+        Change::Changed { path, old, new } => {
+          writeln!(f, "~ {path}: {old} -> {new}")?
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn diff_value(
+  changes: &mut Vec<Change>,
+  path: &mut Path,
+  old: &Value,
+  new: &Value,
+) {
+  match (old, new) {
+    (Value::Document(old), Value::Document(new)) => {
+      diff_document(changes, path, old, new)
+    }
+    (Value::List(old), Value::List(new)) => diff_list(changes, path, old, new),
+    (old, new) if old == new => {}
+    (old, new) => changes.push(Change::Changed {
+      path: path.clone(),
+      old:  old.clone(),
+      new:  new.clone(),
+    }),
+  }
+}
+
+fn diff_document(
+  changes: &mut Vec<Change>,
+  path: &mut Path,
+  old: &Document,
+  new: &Document,
+) {
+  for (key, old_value) in old {
+    path.push(Segment::Key(key.clone()));
+    match new.get(key) {
+      Some(new_value) => diff_value(changes, path, old_value, new_value),
+      None => {
+        let value = old_value.clone();
+        changes.push(Change::Removed { path: path.clone(), value });
+      }
+    }
+    path.pop();
+  }
+
+  for (key, new_value) in new {
+    if !old.contains_key(key) {
+      let path = path.join(Segment::Key(key.clone()));
+      changes.push(Change::Added { path, value: new_value.clone() });
+    }
+  }
+}
+
+fn diff_list(
+  changes: &mut Vec<Change>,
+  path: &mut Path,
+  old: &[Value],
+  new: &[Value],
+) {
+  let (mut i, mut j) = (0, 0);
+  for (anchor_i, anchor_j) in
+    lcs(old, new).into_iter().chain([(old.len(), new.len())])
+  {
+    // Pair up unmatched elements between anchors, the rest is removed or added
+    while i < anchor_i && j < anchor_j {
+      path.push(Segment::Index(j));
+      diff_value(changes, path, &old[i], &new[j]);
+      path.pop();
+      (i, j) = (i + 1, j + 1);
+    }
+    for (i, value) in old.iter().enumerate().take(anchor_i).skip(i) {
+      let path = path.join(Segment::Index(i));
+      changes.push(Change::Removed { path, value: value.clone() });
+    }
+    for (j, value) in new.iter().enumerate().take(anchor_j).skip(j) {
+      let path = path.join(Segment::Index(j));
+      changes.push(Change::Added { path, value: value.clone() });
+    }
+    (i, j) = (anchor_i + 1, anchor_j + 1);
+  }
+}
+
+// Index pairs of a longest common subsequence. The common prefix and
+// suffix are matched directly, the rest with Hirschberg's algorithm in
+// linear space.
+fn lcs(old: &[Value], new: &[Value]) -> Vec<(usize, usize)> {
+  let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+  let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+  let suffix = old_rest
+    .iter()
+    .rev()
+    .zip(new_rest.iter().rev())
+    .take_while(|(a, b)| a == b)
+    .count();
+
+  let mut pairs = (0..prefix).map(|i| (i, i)).collect();
+  let old_mid = &old_rest[..old_rest.len() - suffix];
+  let new_mid = &new_rest[..new_rest.len() - suffix];
+  hirschberg(old_mid, new_mid, (prefix, prefix), &mut pairs);
+  let (n, m) = (old.len() - suffix, new.len() - suffix);
+  pairs.extend((0..suffix).map(|k| (n + k, m + k)));
+  pairs
+}
+
+// Appends the pairs of a longest common subsequence of `old` and `new`,
+// whose first elements are at `offset`, by splitting `old` in half at the
+// point of `new` where a longest subsequence passes.
+fn hirschberg(
+  old: &[Value],
+  new: &[Value],
+  offset: (usize, usize),
+  pairs: &mut Vec<(usize, usize)>,
+) {
+  match old {
+    [] => return,
+    [value] => {
+      if let Some(j) = new.iter().position(|v| v == value) {
+        pairs.push((offset.0, offset.1 + j));
+      }
+      return;
+    }
+    _ if new.is_empty() => return,
+    _ => {}
+  }
+  let mid = old.len() / 2;
+  let before = lcs_lengths(old[..mid].iter(), new.iter());
+  let after = lcs_lengths(old[mid..].iter().rev(), new.iter().rev());
+  let split = (0..=new.len())
+    .max_by_key(|&j| (before[j] + after[new.len() - j], usize::MAX - j))
+    .unwrap_or(0);
+  hirschberg(&old[..mid], &new[..split], offset, pairs);
+  let offset = (offset.0 + mid, offset.1 + split);
+  hirschberg(&old[mid..], &new[split..], offset, pairs);
+}
+
+// The lengths of the longest common subsequences of `old` and each prefix
+// of `new`
+fn lcs_lengths<'v>(
+  old: impl Iterator<Item = &'v Value>,
+  new: impl Iterator<Item = &'v Value> + Clone,
+) -> Vec<usize> {
+  let mut row = vec![0; new.clone().count() + 1];
+  for a in old {
+    let mut diagonal = 0;
+    for (j, b) in new.clone().enumerate() {
+      let above = row[j + 1];
+      row[j + 1] = match a == b {
+        true => diagonal + 1,
+        false => above.max(row[j]),
+      };
+      diagonal = above;
+    }
+  }
+  row
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn atom(s: &str) -> Value { Value::Atom(s.to_owned()) }
+
+  fn list(atoms: &[&str]) -> Value {
+    Value::List(atoms.iter().map(|s| atom(s)).collect())
+  }
+
+  #[test]
+  fn test_diff_list_alignment() {
+    let old = list(&["a", "b", "c", "d"]);
+    let new = list(&["a", "c", "x", "d", "e"]);
+    let diff = diff(&old, &new);
+    assert_eq!(diff.to_string(), "- 1: b\n+ 2: x\n+ 4: e\n");
+  }
+
+  #[test]
+  fn test_diff_nested() {
+    let mut member = Document::new();
+    member.insert("powers".to_owned(), list(&["fly"]));
+    let mut old = Document::new();
+    old.insert(
+      "members".to_owned(),
+      Value::List(vec![Value::Document(member.clone())]),
+    );
+    member.insert("powers".to_owned(), list(&["fly", "x ray"]));
+    let mut new = Document::new();
+    new
+      .insert("members".to_owned(), Value::List(vec![Value::Document(member)]));
+
+    let diff = diff(&Value::Document(old), &Value::Document(new));
+    assert_eq!(diff.to_string(), "+ members.0.powers.1: \"x ray\"\n");
+    assert_eq!(
+      diff.to_value().to_string(),
+      r#"((op: add path: members.0.powers.1 value: "x ray"))"#
+    );
+  }
+
+  #[test]
+  fn test_diff_ignores_key_order() {
+    let mut old = Document::new();
+    old.insert("a".to_owned(), atom("1"));
+    old.insert("b".to_owned(), atom("2"));
+    let mut new = Document::new();
+    new.insert("b".to_owned(), atom("2"));
+    new.insert("a".to_owned(), atom("1"));
+
+    assert!(diff(&Value::Document(old), &Value::Document(new)).is_empty());
+  }
+
+  #[test]
+  fn test_diff_kind_change() {
+    let diff = diff(&atom("a"), &list(&["a"]));
+    assert_eq!(diff.to_string(), "~ .: a -> (a)\n");
+  }
+
+  #[test]
+  fn test_lcs() {
+    // The length by the quadratic table
+    fn length(old: &[Value], new: &[Value]) -> usize {
+      let mut table = vec![vec![0; new.len() + 1]; old.len() + 1];
+      for (i, a) in old.iter().enumerate() {
+        for (j, b) in new.iter().enumerate() {
+          table[i + 1][j + 1] = match a == b {
+            true => table[i][j] + 1,
+            false => table[i][j + 1].max(table[i + 1][j]),
+          };
+        }
+      }
+      table[old.len()][new.len()]
+    }
+
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut random = |n: u64| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      seed % n
+    };
+    for _ in 0..500 {
+      let mut values = || {
+        let len = random(12);
+        (0..len).map(|_| atom(&random(4).to_string())).collect::<Vec<_>>()
+      };
+      let (old, new) = (values(), values());
+      let pairs = lcs(&old, &new);
+      assert_eq!(pairs.len(), length(&old, &new), "{old:?} {new:?}");
+      assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+      assert!(pairs.iter().all(|&(i, j)| old[i] == new[j]));
+    }
+
+    let old = (0..100_000).map(|i| atom(&i.to_string())).collect::<Vec<_>>();
+    let mut new = old.clone();
+    new[50_000] = atom("x");
+    new.insert(50_010, atom("y"));
+    let diff = diff(&Value::List(old), &Value::List(new));
+    assert_eq!(diff.to_string(), "~ 50000: 50000 -> x\n+ 50010: y\n");
+  }
+
+  #[test]
+  fn test_patch_round_trip() {
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut random = move |n: u64| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      seed % n
+    };
+    fn value(random: &mut impl FnMut(u64) -> u64, depth: u32) -> Value {
+      match random(if depth == 0 { 2 } else { 4 }) {
+        0 => Value::Nil,
+        1 => atom(&random(3).to_string()),
+        2 => Value::List(
+          (0..random(6)).map(|_| value(random, depth - 1)).collect(),
+        ),
+        _ => Value::Document(
+          (0..random(4))
+            .map(|_| (random(4).to_string(), value(random, depth - 1)))
+            .collect(),
+        ),
+      }
+    }
+
+    let old = list(&["a", "b"]);
+    let new = list(&["x", "a"]);
+    let patch = diff(&old, &new).to_patch(&old);
+    assert_eq!(
+      patch.to_value().to_string(),
+      "((op: add path: 0 value: x) (op: test path: 2 value: b) \
+       (op: remove path: 2))"
+    );
+
+    for _ in 0..2000 {
+      let (old, new) = (value(&mut random, 3), value(&mut random, 3));
+      let mut patched = old.clone();
+      let patch = diff(&old, &new).to_patch(&old);
+      crate::patch::apply(&mut patched, &patch).unwrap();
+      assert_eq!(patched, new, "{old} {new}");
+      let patch = Patch::try_from(&patch.to_value()).unwrap();
+      let mut patched = old.clone();
+      crate::patch::apply(&mut patched, &patch).unwrap();
+      assert_eq!(patched, new, "{old} {new}");
+    }
+  }
+}
@@ -0,0 +1,94 @@
+//! Conversion of Rust values into atto values
+//!
+//! [`ToAtto`] is the counterpart of [`FromAtto`](crate::decode::FromAtto).
+//! It is implemented for [`Value`], strings, every [`ToAtom`] type,
+//! options, sequences and maps with string keys, and it is what the
+//! [`atto!`](crate::atto!) macro uses for interpolated expressions.
+
+use crate::atoms::{ByteSize, Percent, Timestamp, ToAtom, Version};
+use crate::value::Value;
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+/// A type which can be converted to an atto value.
+///
+/// ```
+/// use atto::encode::ToAtto;
+/// use std::time::Duration;
+///
+/// let timeouts = vec![Some(Duration::from_secs(90)), None];
+/// assert_eq!(timeouts.to_atto().to_string(), "(1m30s #nil)");
+/// ```
+pub trait ToAtto {
+  fn to_atto(&self) -> Value;
+}
+
+impl<T: ToAtto + ?Sized> ToAtto for &T {
+  fn to_atto(&self) -> Value { (**self).to_atto() }
+}
+
+impl ToAtto for Value {
+  fn to_atto(&self) -> Value { self.clone() }
+}
+
+impl ToAtto for str {
+  fn to_atto(&self) -> Value { Value::Atom(self.to_owned()) }
+}
+
+macro_rules! atom_types {
+  ($($type:ty),*) => {$(
+    impl ToAtto for $type {
+      fn to_atto(&self) -> Value { Value::Atom(self.to_atom()) }
+    }
+  )*};
+}
+
+atom_types!(String, bool, f32, f64);
+atom_types!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+atom_types!(IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr);
+atom_types!(Duration, ByteSize, Percent, Timestamp, Version);
+
+impl<T: ToAtto> ToAtto for Option<T> {
+  fn to_atto(&self) -> Value {
+    match self {
+      Some(value) => value.to_atto(),
+      None => Value::Nil,
+    }
+  }
+}
+
+impl<T: ToAtto> ToAtto for [T] {
+  fn to_atto(&self) -> Value {
+    Value::List(self.iter().map(ToAtto::to_atto).collect())
+  }
+}
+
+impl<T: ToAtto, const N: usize> ToAtto for [T; N] {
+  fn to_atto(&self) -> Value { self.as_slice().to_atto() }
+}
+
+impl<T: ToAtto> ToAtto for Vec<T> {
+  fn to_atto(&self) -> Value { self.as_slice().to_atto() }
+}
+
+fn entries<'e, K: AsRef<str> + 'e, T: ToAtto + 'e>(
+  entries: impl Iterator<Item = (&'e K, &'e T)>,
+) -> Value {
+  let entries = entries.map(|(k, v)| (k.as_ref().to_owned(), v.to_atto()));
+  Value::Document(entries.collect())
+}
+
+impl<K: AsRef<str>, T: ToAtto> ToAtto for IndexMap<K, T> {
+  fn to_atto(&self) -> Value { entries(self.iter()) }
+}
+
+impl<K: AsRef<str>, T: ToAtto> ToAtto for BTreeMap<K, T> {
+  fn to_atto(&self) -> Value { entries(self.iter()) }
+}
+
+/// The entries are in the iteration order of the map, which is arbitrary.
+impl<K: AsRef<str>, T: ToAtto, S> ToAtto for HashMap<K, T, S> {
+  fn to_atto(&self) -> Value { entries(self.iter()) }
+}
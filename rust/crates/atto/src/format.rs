@@ -0,0 +1,238 @@
+use crate::path::{Path, Segment};
+use crate::value::{format_atom, Value};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Options for the pretty printer
+#[derive(Clone, Debug)]
+pub struct Options {
+  /// Number of spaces per nesting level
+  pub indent: usize,
+
+  /// Lists and documents are broken into lines if wider than this
+  pub width: usize,
+}
+
+impl Default for Options {
+  fn default() -> Self { Options { indent: 2, width: 80 } }
+}
+
+/// Pretty print a value as an atto text with default options.
+///
+/// A document is printed as root document: one entry per line and without
+/// enclosing parentheses.
+///
+/// ```
+/// # use atto::{format::pretty, Value};
+/// let mut doc = atto::value::Document::new();
+/// doc.insert("name".to_owned(), Value::Atom("John Doe".to_owned()));
+/// doc.insert("age".to_owned(), Value::Atom("42".to_owned()));
+///
+/// assert_eq!(pretty(&Value::Document(doc)), "name: \"John Doe\"\nage: 42\n");
+/// ```
+pub fn pretty(value: &Value) -> String {
+  pretty_with(value, &Options::default())
+}
+
+/// Doc comments by the path of the entry they document
+pub type Docs = BTreeMap<Path, String>;
+
+/// Pretty print a value as an atto text.
+pub fn pretty_with(value: &Value, options: &Options) -> String {
+  pretty_with_docs(value, options, &Docs::new())
+}
+
+/// Pretty print a value as an atto text with doc comments.
+///
+/// A doc comment is written as `#` lines before the key of its entry. A
+/// list or document containing doc comments is always broken into lines.
+///
+/// ```
+/// # use atto::format::{pretty_with_docs, Docs, Options};
+/// let value = atto::parse("port: 8080 tls: (cert: a.pem)").unwrap();
+/// let docs = Docs::from([
+///   ("port".parse().unwrap(), "The port to listen on".to_owned()),
+///   ("tls.cert".parse().unwrap(), "PEM file".to_owned()),
+/// ]);
+///
+/// assert_eq!(
+///   pretty_with_docs(&value, &Options::default(), &docs),
+///   "# The port to listen on\nport: 8080\ntls: (\n  # PEM file\n  cert: a.pem\n)\n"
+/// );
+/// ```
+pub fn pretty_with_docs(
+  value: &Value,
+  options: &Options,
+  docs: &Docs,
+) -> String {
+  let mut out = String::new();
+  let mut path = Path::root();
+  match value {
+    Value::Document(document) => {
+      for (key, value) in document {
+        path.push(Segment::Key(key.clone()));
+        write_entry(&mut out, key, value, 0, options, docs, &mut path);
+        path.pop();
+        out.push('\n');
+      }
+    }
+    value => {
+      write_value(&mut out, value, 0, 0, options, docs, &mut path);
+      out.push('\n');
+    }
+  }
+  out
+}
+
+/// Write `doc` as comment lines at the indentation of `level`.
+pub(crate) fn write_doc(
+  out: &mut String,
+  doc: &str,
+  level: usize,
+  options: &Options,
+) {
+  for line in doc.lines() {
+    write_indent(out, level, options);
+    out.push('#');
+    if !line.is_empty() {
+      out.push(' ');
+      out.push_str(line);
+    }
+    out.push('\n');
+  }
+}
+
+// Whether a doc comment belongs to an entry below `path`
+fn has_docs_below(docs: &Docs, path: &Path) -> bool {
+  let below = (Bound::Excluded(path), Bound::Unbounded);
+  docs.range::<Path, _>(below).next().is_some_and(|(p, _)| p.starts_with(path))
+}
+
+fn write_indent(out: &mut String, level: usize, options: &Options) {
+  out.push_str(&" ".repeat(level * options.indent));
+}
+
+fn write_entry(
+  out: &mut String,
+  key: &str,
+  value: &Value,
+  level: usize,
+  options: &Options,
+  docs: &Docs,
+  path: &mut Path,
+) {
+  if let Some(doc) = docs.get(path) {
+    write_doc(out, doc, level, options);
+  }
+  write_indent(out, level, options);
+  let key = format_atom(key);
+  out.push_str(&key);
+  out.push_str(": ");
+  let prefix = key.chars().count() + 2;
+  write_value(out, value, level, prefix, options, docs, path);
+}
+
+// `prefix` is the width already used on the current line after the indent
+fn write_value(
+  out: &mut String,
+  value: &Value,
+  level: usize,
+  prefix: usize,
+  options: &Options,
+  docs: &Docs,
+  path: &mut Path,
+) {
+  let inline = value.to_string();
+  let used = level * options.indent + prefix;
+  let fits = used + inline.chars().count() <= options.width;
+  if fits && !has_docs_below(docs, path) {
+    out.push_str(&inline);
+    return;
+  }
+
+  match value {
+    Value::List(list) if !list.is_empty() => {
+      out.push_str("(\n");
+      for (index, value) in list.iter().enumerate() {
+        path.push(Segment::Index(index));
+        write_indent(out, level + 1, options);
+        write_value(out, value, level + 1, 0, options, docs, path);
+        path.pop();
+        out.push('\n');
+      }
+      write_indent(out, level, options);
+      out.push(')');
+    }
+    Value::Document(document) if !document.is_empty() => {
+      out.push_str("(\n");
+      for (key, value) in document {
+        path.push(Segment::Key(key.clone()));
+        write_entry(out, key, value, level + 1, options, docs, path);
+        path.pop();
+        out.push('\n');
+      }
+      write_indent(out, level, options);
+      out.push(')');
+    }
+    _ => out.push_str(&inline),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::value::Document;
+
+  fn atom(s: &str) -> Value { Value::Atom(s.to_owned()) }
+
+  #[test]
+  fn test_pretty_breaks_wide_values() {
+    let list = Value::List((0..30).map(|i| atom(&i.to_string())).collect());
+    let mut inner = Document::new();
+    inner.insert("list".to_owned(), list);
+    inner.insert("empty".to_owned(), Value::List(vec![]));
+    let mut doc = Document::new();
+    doc.insert("inner".to_owned(), Value::Document(inner));
+
+    let options = Options { indent: 2, width: 40 };
+    let text = pretty_with(&Value::Document(doc), &options);
+    let expected = "inner: (\n  list: (\n    0\n    1\n";
+    assert!(text.starts_with(expected), "{text}");
+    assert!(text.ends_with("    29\n  )\n  empty: ()\n)\n"), "{text}");
+  }
+
+  #[test]
+  fn test_pretty_docs() {
+    let text = "a: (b: 1 c: (2 (d: 3))) e: 4";
+    let value = crate::parse(text).unwrap();
+    let docs = Docs::from([
+      ("a".parse().unwrap(), "A\n\nlong".to_owned()),
+      ("a.c.1.d".parse().unwrap(), "D".to_owned()),
+      ("x".parse().unwrap(), "unused".to_owned()),
+    ]);
+    assert_eq!(
+      pretty_with_docs(&value, &Options::default(), &docs),
+      "\
+# A
+#
+# long
+a: (
+  b: 1
+  c: (
+    2
+    (
+      # D
+      d: 3
+    )
+  )
+)
+e: 4
+"
+    );
+  }
+
+  #[test]
+  fn test_pretty_root_atom() {
+    assert_eq!(pretty(&atom("a b")), "\"a b\"\n");
+  }
+}
@@ -0,0 +1,468 @@
+//! Incremental reparsing of edited texts
+//!
+//! After an edit only the lexemes around it are lexed again: lexing restarts
+//! at the last lexeme ending before the line of the edit, which starts in
+//! the `init` group, and stops as soon as a lexeme after the edit starts
+//! where one started in the old text. The parser then moves subtrees in
+//! parentheses from the old tree if all of their lexemes are unchanged and they
+//! had no errors, instead of parsing them again.
+
+use crate::parser::{
+  ErrorKind, Kind, Lexeme, Lexemes, ParseError, ParseLimits, Parsed, Parser,
+};
+use crate::syntax::{LineIndex, Node, NodeKind, Span};
+use std::mem;
+
+/// A text edit: the bytes of `span` are replaced by `text`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Edit {
+  pub span: Span,
+  pub text: String,
+}
+
+impl Edit {
+  pub fn new(span: Span, text: impl Into<String>) -> Edit {
+    Edit { span, text: text.into() }
+  }
+}
+
+/// What an edit cost
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Reparsed {
+  /// The part of the new text which was lexed again
+  pub relexed: Span,
+
+  /// The number of subtrees moved from the old tree
+  pub reused: usize,
+}
+
+/// A text with its syntax tree, kept up to date through edits.
+///
+/// ```
+/// # use atto::incremental::{Edit, Incremental};
+/// # use atto::syntax::Span;
+/// let mut text = Incremental::new("a: (b: 1)\nc: (d: 2)\n");
+/// let reparsed = text.edit(&Edit::new(Span::new(7, 8), "10"));
+///
+/// assert_eq!(text.text(), "a: (b: 10)\nc: (d: 2)\n");
+/// assert_eq!(
+///   text.parsed().root.to_value().to_string(),
+///   "(a: (b: 10) c: (d: 2))"
+/// );
+/// assert_eq!(reparsed.reused, 1);
+/// ```
+pub struct Incremental {
+  text:       String,
+  lexemes:    Vec<Lexeme>,
+  lex_errors: Vec<ParseError>,
+  parsed:     Parsed,
+  limits:     ParseLimits,
+}
+
+impl Incremental {
+  pub fn new(text: impl Into<String>) -> Incremental {
+    Incremental::with_limits(text, ParseLimits::default())
+  }
+
+  pub fn with_limits(
+    text: impl Into<String>,
+    limits: ParseLimits,
+  ) -> Incremental {
+    let root = nil(Span::default());
+    let mut incremental = Incremental {
+      text: text.into(),
+      lexemes: vec![],
+      lex_errors: vec![],
+      parsed: Parsed { root, errors: vec![] },
+      limits,
+    };
+    incremental.reparse();
+    incremental
+  }
+
+  // Lex and parse the whole text. There are no lexemes if it is too long
+  // or exceeds another limit, which stops lexing.
+  fn reparse(&mut self) {
+    self.lexemes.clear();
+    self.lex_errors.clear();
+    if let Some(parsed) = Parsed::too_long(&self.text, &self.limits) {
+      self.parsed = parsed;
+      return;
+    }
+    let mut lexer = Lexemes::new(&self.text, 0, true);
+    let lexemes = lexer.by_ref().inspect(|l| self.lexemes.push(l.clone()));
+    let parser = Parser::new(&self.text, lexemes, &self.limits, None);
+    let (root, errors) = parser.root();
+    self.lex_errors = lexer.errors;
+    let limit =
+      |err: &ParseError| matches!(err.kind, ErrorKind::LimitExceeded { .. });
+    if errors.iter().any(limit) {
+      self.lexemes.clear();
+    }
+    self.parsed =
+      Parsed::new(root, self.lex_errors.clone(), errors, &self.limits);
+  }
+
+  pub fn text(&self) -> &str { &self.text }
+
+  pub fn parsed(&self) -> &Parsed { &self.parsed }
+
+  /// Apply `edit` and update the syntax tree.
+  ///
+  /// Panics if the span of the edit is not inside the text or not on
+  /// character boundaries.
+  pub fn edit(&mut self, edit: &Edit) -> Reparsed {
+    let Edit { span, text: insert } = edit;
+    let delta = insert.len() as isize - span.len() as isize;
+    let shift = |offset: usize| offset.wrapping_add_signed(delta);
+    self.text.replace_range(span.start..span.end, insert);
+    let new_end = span.start + insert.len();
+    if self.lexemes.is_empty() || self.text.len() > self.limits.max_input_len {
+      self.reparse();
+      let lexed = !self.lexemes.is_empty();
+      let relexed = Span::new(0, if lexed { self.text.len() } else { 0 });
+      return Reparsed { relexed, reused: 0 };
+    }
+
+    // Comments look ahead to the end of the line, so an edit can change
+    // every token on its line. The lexeme before the line must also end
+    // before it to stay the same.
+    let line_start =
+      self.text[..span.start].rfind(['\n', '\r']).map_or(0, |at| at + 1);
+    let before = self.lexemes.partition_point(|l| l.span.end < line_start);
+    let (keep, restart, spaced) = match before.checked_sub(1) {
+      Some(i) => (i, self.lexemes[i].span.start, self.lexemes[i].spaced),
+      None => (0, 0, true),
+    };
+    let mut lexemes = mem::take(&mut self.lexemes);
+    let old = lexemes.split_off(keep);
+
+    // Lex until a lexeme after the edit starts like one in the old text,
+    // with the same comments before it. The restart lexeme keeps its doc
+    // comment, which is before the restart.
+    let mut lexer = Lexemes::new(&self.text, restart, spaced);
+    if before > 0 {
+      lexer.doc = old[0].doc.clone();
+    }
+    let mut resync = None;
+    for lexeme in lexer.by_ref() {
+      if lexeme.kind != Kind::End && lexeme.span.start >= new_end {
+        let old_start = lexeme.span.start.wrapping_add_signed(-delta);
+        let found = old.binary_search_by_key(&old_start, |l| l.span.start);
+        let same = |&i: &usize| {
+          old[i].spaced == lexeme.spaced && old[i].doc == lexeme.doc
+        };
+        if let Some(i) = found.ok().filter(same) {
+          resync = Some(i);
+          break;
+        }
+      }
+      lexemes.push(lexeme);
+    }
+    let relexed_end =
+      resync.map_or(self.text.len(), |i| shift(old[i].span.start));
+    let old_after = resync.map_or(usize::MAX, |i| old[i].span.start);
+    if let Some(i) = resync {
+      lexemes.extend(old.into_iter().skip(i).map(|mut lexeme| {
+        lexeme.span =
+          Span::new(shift(lexeme.span.start), shift(lexeme.span.end));
+        lexeme
+      }));
+    }
+
+    let mut lex_errors = vec![];
+    let mut lines = None;
+    for err in mem::take(&mut self.lex_errors) {
+      if err.span.start < restart {
+        lex_errors.push(err);
+      } else if err.span.start >= old_after {
+        let span = Span::new(shift(err.span.start), shift(err.span.end));
+        let lines = lines.get_or_insert_with(|| LineIndex::new(&self.text));
+        let position = lines.position(span.start);
+        lex_errors.push(ParseError { span, position, ..err });
+      }
+    }
+    let relexed = Span::new(restart, relexed_end);
+    lex_errors.extend(
+      lexer.errors.into_iter().filter(|err| err.span.start < relexed_end),
+    );
+    lex_errors.sort_by_key(|err| err.span.start);
+
+    let old_root = mem::replace(&mut self.parsed.root, nil(Span::default()));
+    let mut reuse = Reuse {
+      old: old_root,
+      old_errors: self.parsed.errors.iter().map(|err| err.span).collect(),
+      before: restart,
+      after: old_after,
+      delta,
+      reused: 0,
+    };
+    let parser =
+      Parser::new(&self.text, lexemes.iter(), &self.limits, Some(&mut reuse));
+    let (root, errors) = parser.root();
+    self.parsed = Parsed::new(root, lex_errors.clone(), errors, &self.limits);
+    self.lexemes = lexemes;
+    self.lex_errors = lex_errors;
+    Reparsed { relexed, reused: reuse.reused }
+  }
+}
+
+// The old tree, from which the parser takes unchanged subtrees
+pub(crate) struct Reuse {
+  old:        Node,
+  // sorted by start
+  old_errors: Vec<Span>,
+  // Lexemes before this offset are unchanged
+  before:     usize,
+  // Lexemes from this old offset on are unchanged but moved by `delta`
+  after:      usize,
+  delta:      isize,
+  reused:     usize,
+}
+
+impl Reuse {
+  // The old node in parentheses starting at `start` in the new text
+  pub(crate) fn take(&mut self, start: usize) -> Option<Node> {
+    let old_start = if start < self.before {
+      start
+    } else {
+      let old_start = start.wrapping_add_signed(-self.delta);
+      (old_start >= self.after).then_some(old_start)?
+    };
+
+    let node = find(&mut self.old, old_start)?;
+    if old_start < self.before && node.span.end > self.before {
+      return None;
+    }
+    let i = self.old_errors.partition_point(|err| err.start < old_start);
+    if self.old_errors.get(i).is_some_and(|err| err.start < node.span.end) {
+      return None;
+    }
+
+    let mut node = mem::replace(node, nil(node.span));
+    if old_start >= self.after {
+      move_by(&mut node, self.delta);
+    }
+    self.reused += 1;
+    Some(node)
+  }
+}
+
+fn nil(span: Span) -> Node { Node { span, kind: NodeKind::Nil } }
+
+// The descendant list or document starting at `start`
+fn find(node: &mut Node, start: usize) -> Option<&mut Node> {
+  let child = match &mut node.kind {
+    NodeKind::List(list) => {
+      let i = list.partition_point(|node| node.span.end <= start);
+      list.get_mut(i)?
+    }
+    NodeKind::Document(entries) => {
+      let i = entries.partition_point(|entry| entry.value.span.end <= start);
+      &mut entries.get_mut(i)?.value
+    }
+    _ => return None,
+  };
+  if child.span.start < start {
+    find(child, start)
+  } else {
+    let nested =
+      matches!(child.kind, NodeKind::List(_) | NodeKind::Document(_));
+    (child.span.start == start && nested).then_some(child)
+  }
+}
+
+fn move_by(node: &mut Node, delta: isize) {
+  let shift = |span: &mut Span| {
+    *span = Span::new(
+      span.start.wrapping_add_signed(delta),
+      span.end.wrapping_add_signed(delta),
+    )
+  };
+  shift(&mut node.span);
+  match &mut node.kind {
+    NodeKind::List(list) => {
+      list.iter_mut().for_each(|node| move_by(node, delta))
+    }
+    NodeKind::Document(entries) => {
+      for entry in entries {
+        shift(&mut entry.key_span);
+        move_by(&mut entry.value, delta);
+      }
+    }
+    _ => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::{parse_tree, parse_tree_with};
+
+  // Apply edits one by one and compare with parsing from scratch
+  fn check(text: &str, edits: &[(usize, usize, &str)]) -> Vec<Reparsed> {
+    let mut incremental = Incremental::new(text);
+    let mut reparsed = vec![];
+    for &(start, end, insert) in edits {
+      reparsed
+        .push(incremental.edit(&Edit::new(Span::new(start, end), insert)));
+      let full = parse_tree(incremental.text());
+      assert_eq!(
+        incremental.parsed().root,
+        full.root,
+        "{:?}",
+        incremental.text()
+      );
+      assert_eq!(
+        incremental.parsed().errors,
+        full.errors,
+        "{:?}",
+        incremental.text()
+      );
+    }
+    reparsed
+  }
+
+  #[test]
+  fn test_reuse() {
+    let text = "a: (x y)\nb: (c: 1 d: (e f))\ng: (h)\n";
+    let reparsed = check(text, &[(16, 17, "100")]);
+    assert_eq!(reparsed[0].relexed, Span::new(7, 20));
+    // (e f) and (h), the end of (x y) is relexed
+    assert_eq!(reparsed[0].reused, 2);
+
+    let text =
+      (0..10_000).map(|i| format!("k{i}: (v{i})\n")).collect::<String>();
+    let start = text.find("(v5000)").unwrap();
+    let reparsed = check(&text, &[(start + 1, start + 2, "w")]);
+    assert_eq!(reparsed[0].relexed, Span::new(start - 9, start + 6));
+    // all but (v4999) and (w5000)
+    assert_eq!(reparsed[0].reused, 9_998);
+  }
+
+  #[test]
+  fn test_strings() {
+    let text = "a: \"x\"\nb: (c)\nd: (e)";
+    // an unterminated string ends at the end of the line
+    let reparsed = check(text, &[(3, 4, ""), (3, 3, "\"")]);
+    assert_eq!(reparsed[0].relexed, Span::new(0, 6));
+    assert_eq!(reparsed[1].relexed, Span::new(0, 7));
+
+    // a guarded string does not, so the rest of the text is relexed
+    let reparsed = check(text, &[(3, 3, "#\"")]);
+    assert_eq!(reparsed[0].relexed, Span::new(0, text.len() + 2));
+
+    check("a: #\"x\"# b: y", &[(5, 5, "\"# c: \"#"), (4, 5, "")]);
+    check("a: \"x\\u{41}\" b: y", &[(8, 9, "z"), (9, 10, "")]);
+  }
+
+  #[test]
+  fn test_structure() {
+    let text = "a: (b: (c)) d: (e) f: g";
+    check(text, &[(10, 11, ""), (10, 10, ")"), (15, 16, ""), (0, 0, "x: ")]);
+    check(text, &[(0, text.len(), "")]);
+    check("", &[(0, 0, "a: (b)"), (6, 6, " c: (d)"), (2, 3, "")]);
+    check("a: (b: 1 b: 2) c: (d)", &[(19, 20, "(d)")]);
+    check("a: b # x\nc: (d)", &[(5, 6, ""), (5, 5, "# ")]);
+  }
+
+  #[test]
+  fn test_comments() {
+    // `###(` is four tokens, `###` one comment to the end of the line
+    for insert in ["", " ", "#", "\n"] {
+      check("###(", &[(3, 4, insert)]);
+    }
+    check("###)", &[(3, 4, "")]);
+    check("a: b\n###a", &[(8, 9, "")]);
+    check("a: b\n#x\r\nc: d", &[(8, 8, "y")]);
+  }
+
+  // Random edits of texts with many special characters
+  #[test]
+  fn test_random_edits() {
+    const CHARS: [&str; 14] = [
+      "a", "b", " ", "\n", "\r", "#", "\"", "\\", "(", ")", ":", "x", "_",
+      "\u{e9}",
+    ];
+    let mut seed = 0x2545f4914f6cdd1d_u64;
+    let mut random = |n: usize| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      (seed % n as u64) as usize
+    };
+    for _ in 0..1000 {
+      let mut text = String::new();
+      for _ in 0..random(30) {
+        text.push_str(CHARS[random(CHARS.len())]);
+      }
+      let mut incremental = Incremental::new(text);
+      for _ in 0..20 {
+        let text = incremental.text();
+        let boundaries = (0..=text.len())
+          .filter(|&at| text.is_char_boundary(at))
+          .collect::<Vec<_>>();
+        let start = boundaries[random(boundaries.len())];
+        let ends = boundaries.iter().filter(|&&at| at >= start);
+        let end = *ends.clone().nth(random(ends.count().min(4))).unwrap();
+        let mut insert = String::new();
+        for _ in 0..random(4) {
+          insert.push_str(CHARS[random(CHARS.len())]);
+        }
+        incremental.edit(&Edit::new(Span::new(start, end), insert));
+        let full = parse_tree(incremental.text());
+        assert_eq!(
+          incremental.parsed().root,
+          full.root,
+          "{:?}",
+          incremental.text()
+        );
+        assert_eq!(
+          incremental.parsed().errors,
+          full.errors,
+          "{:?}",
+          incremental.text()
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_docs() {
+    let text = "a: 1\n# doc\nb: (\n  c: 2\n)\n";
+    check(text, &[(7, 10, "note"), (5, 5, "\n"), (5, 6, "")]);
+    check(text, &[(15, 15, "# c\n  "), (5, 12, ""), (4, 4, " # a")]);
+  }
+
+  #[test]
+  fn test_limits() {
+    let limits =
+      ParseLimits { max_depth: 2, max_input_len: 16, ..ParseLimits::default() };
+    let mut incremental =
+      Incremental::with_limits("a: x\nb: ((c))", limits.clone());
+    let same = |incremental: &Incremental| {
+      let full = parse_tree_with(incremental.text(), &limits);
+      assert_eq!(incremental.parsed().root, full.root);
+      assert_eq!(incremental.parsed().errors, full.errors);
+    };
+
+    // ((c)) is nested deeper now and exceeds the limit
+    incremental.edit(&Edit::new(Span::new(3, 4), "("));
+    same(&incremental);
+    assert_eq!(
+      incremental.parsed().errors[0].to_string(),
+      "nesting depth exceeds 2 at 2:5"
+    );
+
+    // a full parse stops lexing at the limit and keeps no lexemes
+    let over = Incremental::with_limits("a: (((b)))", limits.clone());
+    assert!(over.lexemes.is_empty());
+
+    let reparsed = incremental.edit(&Edit::new(Span::new(0, 0), "long: text "));
+    same(&incremental);
+    assert_eq!(reparsed.relexed, Span::default());
+    let reparsed = incremental.edit(&Edit::new(Span::new(0, 15), ""));
+    same(&incremental);
+    assert_eq!(reparsed.relexed, Span::new(0, 9));
+  }
+}
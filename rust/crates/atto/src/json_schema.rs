@@ -0,0 +1,242 @@
+//! Export of schemas to JSON Schema
+//!
+//! [`to_json_schema()`] translates a [`Schema`] to JSON Schema draft
+//! 2020-12 for atto values mapped to JSON: nil is `null`, atoms are
+//! strings, lists are arrays and documents are objects. So integers,
+//! numbers and booleans are strings with a pattern or enum, and a document
+//! or map which may be empty also allows an empty array, as `()` is both
+//! an empty list and an empty document.
+
+use crate::diagnostic::Diagnostic;
+use crate::path::{Path, Segment};
+use crate::schema::Schema;
+use serde_json::{json, Map, Value as Json};
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+const INTEGER: &str = "^[+-]?[0-9]+$";
+const NUMBER: &str = r"^[+-]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][+-]?[0-9]+)?$";
+
+/// A JSON Schema, with warnings about parts of the atto schema it does not
+/// express exactly
+#[derive(Clone, Debug)]
+pub struct JsonSchema {
+  pub schema:   Json,
+  /// Labelled with the path of the value in the atto documents
+  pub warnings: Vec<Diagnostic>,
+}
+
+/// Translate `schema` to JSON Schema.
+///
+/// ```
+/// # use atto::{decode::from_str, json_schema::to_json_schema, schema::Schema};
+/// let schema = from_str::<Schema>("document: (port: integer)").unwrap();
+///
+/// assert_eq!(
+///   to_json_schema(&schema).schema.to_string(),
+///   r#"{"$schema":"https://json-schema.org/draft/2020-12/schema","type":"object","properties":{"port":{"type":"string","pattern":"^[+-]?[0-9]+$"}},"required":["port"],"additionalProperties":false}"#
+/// );
+/// ```
+pub fn to_json_schema(schema: &Schema) -> JsonSchema {
+  let mut warnings = vec![];
+  let mut json = translate(schema, &mut Path::root(), &mut warnings);
+  let json = match json {
+    Json::Object(ref mut object) => {
+      let mut root = Map::from_iter([("$schema".to_owned(), json!(DRAFT))]);
+      root.append(object);
+      Json::Object(root)
+    }
+    _ => json!({ "$schema": DRAFT, "allOf": [json] }),
+  };
+  JsonSchema { schema: json, warnings }
+}
+
+fn translate(
+  schema: &Schema,
+  path: &mut Path,
+  warnings: &mut Vec<Diagnostic>,
+) -> Json {
+  match schema {
+    Schema::Any => json!(true),
+    Schema::Nil => json!({ "type": "null" }),
+    Schema::String => json!({ "type": "string" }),
+    Schema::Integer => json!({ "type": "string", "pattern": INTEGER }),
+    Schema::Number => {
+      let warning =
+        Diagnostic::warning("infinite and NaN numbers are not matched")
+          .with_label(path.to_string());
+      warnings.push(warning);
+      json!({ "type": "string", "pattern": NUMBER })
+    }
+    Schema::Boolean => json!({ "enum": ["true", "false"] }),
+    Schema::Enum(atoms) => json!({ "enum": atoms }),
+    Schema::List(element) => {
+      path.push(Segment::Index(0));
+      let items = translate(element, path, warnings);
+      path.pop();
+      json!({ "type": "array", "items": items })
+    }
+    Schema::Map(value) => {
+      path.push(Segment::Key("*".to_owned()));
+      let values = translate(value, path, warnings);
+      path.pop();
+      or_empty(json!({ "type": "object", "additionalProperties": values }))
+    }
+    Schema::Document(fields) => {
+      let mut properties = Map::new();
+      let mut required = vec![];
+      for (key, field) in fields {
+        path.push(Segment::Key(key.clone()));
+        properties
+          .insert(key.clone(), translate(&field.schema, path, warnings));
+        path.pop();
+        if !field.optional {
+          required.push(key.clone());
+        }
+      }
+      let object = json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+      });
+      match required.is_empty() {
+        true => or_empty(object),
+        false => object,
+      }
+    }
+    Schema::OneOf(schemas) => {
+      let schemas =
+        schemas.iter().map(|schema| translate(schema, path, warnings));
+      json!({ "anyOf": schemas.collect::<Vec<_>>() })
+    }
+  }
+}
+
+// `object`, or an empty array for an empty document written `()`
+fn or_empty(object: Json) -> Json {
+  json!({ "anyOf": [object, { "type": "array", "maxItems": 0 }] })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::convert::to_json;
+  use crate::decode::from_str;
+
+  #[test]
+  fn test_to_json_schema() {
+    let text = "\
+document: (
+  level: (enum: (debug info))
+  debug: boolean
+  hosts: (list: (one-of: (nil string)))
+  labels: (map: (document: (ratio: number)))
+  extra: any
+)
+optional: (debug extra)
+";
+    let schema = from_str::<Schema>(text).unwrap();
+    let JsonSchema { schema, warnings } = to_json_schema(&schema);
+    assert_eq!(
+      schema,
+      json!({
+        "$schema": DRAFT,
+        "type": "object",
+        "properties": {
+          "level": { "enum": ["debug", "info"] },
+          "debug": { "enum": ["true", "false"] },
+          "hosts": {
+            "type": "array",
+            "items": { "anyOf": [{ "type": "null" }, { "type": "string" }] },
+          },
+          "labels": {
+            "anyOf": [
+              {
+                "type": "object",
+                "additionalProperties": {
+                  "type": "object",
+                  "properties": {
+                    "ratio": { "type": "string", "pattern": NUMBER },
+                  },
+                  "required": ["ratio"],
+                  "additionalProperties": false,
+                },
+              },
+              { "type": "array", "maxItems": 0 },
+            ],
+          },
+          "extra": true,
+        },
+        "required": ["level", "hosts", "labels"],
+        "additionalProperties": false,
+      })
+    );
+    let warnings = warnings.iter().map(|w| w.render("")).collect::<Vec<_>>();
+    assert_eq!(
+      warnings,
+      ["warning: infinite and NaN numbers are not matched\n  --> labels.*.ratio\n"]
+    );
+
+    let schema = to_json_schema(&Schema::Any).schema;
+    assert_eq!(schema, json!({ "$schema": DRAFT, "allOf": [true] }));
+  }
+
+  #[test]
+  fn test_validate() {
+    let text = "\
+document: (
+  level: (enum: (debug info))
+  debug: boolean
+  hosts: (list: (one-of: (nil string)))
+  labels: (map: (document: (ratio: number)))
+  port: integer
+)
+optional: (debug)
+";
+    let schema = from_str::<Schema>(text).unwrap();
+    let validator =
+      jsonschema::validator_for(&to_json_schema(&schema).schema).unwrap();
+    let is_valid =
+      |text: &str| validator.is_valid(&to_json(&crate::parse(text).unwrap()));
+    assert!(is_valid("level: info hosts: (a #nil) labels: () port: 80"));
+    assert!(is_valid(
+      "level: debug debug: true hosts: () port: -1 \
+       labels: (x: (ratio: 1.5e3) y: (ratio: .5))"
+    ));
+    assert!(!is_valid("level: warn hosts: () labels: () port: 80"));
+    assert!(!is_valid("level: info hosts: () labels: () port: 8.5"));
+    assert!(!is_valid("level: info hosts: (()) labels: () port: 80"));
+    assert!(!is_valid("level: info hosts: () labels: () port: 80 x: y"));
+    assert!(!is_valid("level: info labels: () port: 80"));
+
+    let samples = [
+      "name: a tags: (x y) size: 1 owner: (id: 7)",
+      "name: b tags: () size: 2.5 owner: #nil",
+      "name: \"c d\" tags: (z) size: -3 extra: true owner: (id: 8)",
+    ];
+    let samples = samples.map(|text| crate::parse(text).unwrap());
+    let schema = crate::schema::infer(&samples);
+    let validator =
+      jsonschema::validator_for(&to_json_schema(&schema).schema).unwrap();
+    for sample in &samples {
+      assert!(validator.is_valid(&to_json(sample)), "{sample}");
+    }
+  }
+
+  #[test]
+  fn test_patterns() {
+    let integer = regex::Regex::new(INTEGER).unwrap();
+    let number = regex::Regex::new(NUMBER).unwrap();
+    for atom in ["0", "-12", "+3"] {
+      assert!(integer.is_match(atom), "{atom}");
+    }
+    for atom in ["1.5", ".5", "5.", "-1e3", "2E-2", "42"] {
+      assert!(number.is_match(atom), "{atom}");
+      assert!(atom.parse::<f64>().is_ok(), "{atom}");
+    }
+    for atom in ["", "1.2.3", "e5", "1e", "0x1", "- 1"] {
+      assert!(!number.is_match(atom), "{atom}");
+      assert!(!integer.is_match(atom), "{atom}");
+    }
+  }
+}
@@ -0,0 +1,531 @@
+//! Lazy access to large atto texts
+//!
+//! [`Index::new()`] lexes a text once and records the span of every value
+//! and key, without allocating atoms. Looking up a path then only walks
+//! the index, and [`LazyValue::to_value()`] parses just the span of the
+//! value. The text is a byte slice, so it can be memory-mapped.
+//!
+//! The scan checks the structure of the text. If it finds an error, the
+//! whole text is parsed to report the same error as [`parse`]. Errors
+//! within a value, like invalid escapes or duplicate keys, are reported
+//! when the value is materialized. A lookup finds the first entry with
+//! the key.
+//!
+//! [`parse`]: crate::parse
+
+use crate::lex::{self, *};
+use crate::parser::{
+  parse_tree_with, ErrorKind, Lexemes, Limit, ParseError, ParseLimits, Parsed,
+  Parser,
+};
+use crate::path::{Path, Segment};
+use crate::syntax::{Node, NodeKind, Position, Span};
+use crate::value::Value;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+  Nil,
+  Atom,
+  List,
+  Document,
+}
+
+// A value in the index. The values below it follow it, so the next
+// sibling is `descendants + 1` slots further.
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+  kind:        Kind,
+  span:        Span,
+  key:         Option<Span>,
+  descendants: usize,
+}
+
+/// The index of the values of an atto text.
+///
+/// ```
+/// # use atto::lazy::Index;
+/// let text = b"name: app\nhosts: ((name: a port: 80) (name: b port: 81))\n";
+/// let index = Index::new(text).unwrap();
+///
+/// let host = index.get_path(&"hosts.1".parse().unwrap()).unwrap();
+/// assert_eq!(host.len(), 2);
+/// assert_eq!(host.to_value().unwrap().to_string(), "(name: b port: 81)");
+/// ```
+///
+/// For a memory-mapped file, with the `memmap2` crate:
+///
+/// ```ignore
+/// let file = std::fs::File::open("large.atto")?;
+/// let map = unsafe { memmap2::Mmap::map(&file)? };
+/// let index = Index::with_limits(&map, &ParseLimits::trusted())?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Index<'t> {
+  text:   &'t str,
+  slots:  Vec<Slot>,
+  limits: ParseLimits,
+}
+
+impl<'t> Index<'t> {
+  /// Index `bytes` within the default limits.
+  pub fn new(bytes: &'t [u8]) -> Result<Index<'t>, ParseError> {
+    Index::with_limits(bytes, &ParseLimits::default())
+  }
+
+  /// Index `bytes` within `limits`, which also apply to materialized
+  /// values.
+  pub fn with_limits(
+    bytes: &'t [u8],
+    limits: &ParseLimits,
+  ) -> Result<Index<'t>, ParseError> {
+    let text = std::str::from_utf8(bytes).map_err(|err| {
+      let start = err.valid_up_to();
+      let end = start + err.error_len().unwrap_or(bytes.len() - start);
+      let token = String::from_utf8_lossy(&bytes[start..end]).into_owned();
+      let valid = std::str::from_utf8(&bytes[..start]).unwrap_or_default();
+      let position = Position::of(valid, start);
+      let kind = ErrorKind::InvalidToken(token);
+      ParseError { kind, span: Span::new(start, end), position }
+    })?;
+    if let Some(Parsed { mut errors, .. }) = Parsed::too_long(text, limits) {
+      return Err(errors.remove(0));
+    }
+    let mut scan = Scan::new(text, limits);
+    match scan.root() {
+      Ok(()) => Ok(Index { text, slots: scan.slots, limits: limits.clone() }),
+      // The parser reports the first error, which may be before the one
+      // the scan found
+      Err(err) => {
+        let parsed = parse_tree_with(text, limits);
+        Err(parsed.errors.into_iter().next().unwrap_or(err))
+      }
+    }
+  }
+
+  /// The root document.
+  pub fn root(&self) -> LazyValue<'_, 't> { LazyValue { index: self, slot: 0 } }
+
+  /// The value at `path`, if there is one.
+  pub fn get_path(&self, path: &Path) -> Option<LazyValue<'_, 't>> {
+    self.root().get_path(path)
+  }
+
+  // A key as written, or unescaped if it is a string with escapes
+  fn key(&self, span: Span) -> Cow<'t, str> {
+    let key = &self.text[span.start..span.end];
+    let unquoted = match key.strip_prefix('"') {
+      Some(key) if !key.contains('\\') => key.strip_suffix('"'),
+      Some(_) => None,
+      // A guarded string has no escapes
+      None if key.starts_with('#') => {
+        let start = key.find('"').map_or(0, |at| at + 1);
+        let end = key.rfind('"').unwrap_or(key.len()).max(start);
+        Some(&key[start..end])
+      }
+      None => Some(key),
+    };
+    if let Some(unquoted) = unquoted {
+      return Cow::Borrowed(unquoted);
+    }
+    let text = &self.text[..span.end];
+    match Lexemes::new(text, span.start, true).next().map(|l| l.kind) {
+      Some(crate::parser::Kind::Atom(atom)) => Cow::Owned(atom),
+      _ => Cow::Borrowed(key),
+    }
+  }
+}
+
+/// A value in an [`Index`], parsed on demand
+#[derive(Clone, Copy, Debug)]
+pub struct LazyValue<'i, 't> {
+  index: &'i Index<'t>,
+  slot:  usize,
+}
+
+impl<'i, 't> LazyValue<'i, 't> {
+  fn slot(&self) -> &'i Slot { &self.index.slots[self.slot] }
+
+  /// The name of the type for messages, like
+  /// [`Value::type_name()`](crate::Value::type_name).
+  pub fn type_name(&self) -> &'static str {
+    match self.slot().kind {
+      Kind::Nil => "nil",
+      Kind::Atom => "atom",
+      Kind::List => "list",
+      Kind::Document => "document",
+    }
+  }
+
+  /// The span of the value in the text.
+  pub fn span(&self) -> Span { self.slot().span }
+
+  /// The source text of the value.
+  pub fn text(&self) -> &'t str {
+    &self.index.text[self.span().start..self.span().end]
+  }
+
+  /// The key of the entry, if the value is one.
+  pub fn key(&self) -> Option<Cow<'t, str>> {
+    self.slot().key.map(|span| self.index.key(span))
+  }
+
+  /// The elements of a list or the values of a document.
+  pub fn children(&self) -> impl Iterator<Item = LazyValue<'i, 't>> + 'i {
+    let index = self.index;
+    let end = self.slot + self.slot().descendants + 1;
+    let mut slot = self.slot + 1;
+    std::iter::from_fn(move || {
+      let child = (slot < end).then_some(LazyValue { index, slot })?;
+      slot += index.slots[slot].descendants + 1;
+      Some(child)
+    })
+  }
+
+  /// The number of elements or entries, 0 for atoms.
+  pub fn len(&self) -> usize { self.children().count() }
+
+  pub fn is_empty(&self) -> bool { self.slot().descendants == 0 }
+
+  /// The child value addressed by one segment, like
+  /// [`Value::get()`](crate::Value::get).
+  pub fn get(&self, segment: &Segment) -> Option<LazyValue<'i, 't>> {
+    match (self.slot().kind, segment) {
+      (Kind::List, Segment::Index(index)) => self.children().nth(*index),
+      (Kind::Document, Segment::Key(key)) => self.entry(key),
+      (Kind::Document, Segment::Index(index)) => self.entry(&index.to_string()),
+      _ => None,
+    }
+  }
+
+  fn entry(&self, key: &str) -> Option<LazyValue<'i, 't>> {
+    self.children().find(|child| child.key().as_deref() == Some(key))
+  }
+
+  /// The value at `path` below this one, if there is one.
+  pub fn get_path(&self, path: &Path) -> Option<LazyValue<'i, 't>> {
+    path.0.iter().try_fold(*self, |value, segment| value.get(segment))
+  }
+
+  /// Parse the value.
+  pub fn to_value(&self) -> Result<Value, ParseError> {
+    let Index { text, limits, .. } = self.index;
+    if self.slot == 0 {
+      let Parsed { root, mut errors } = parse_tree_with(text, limits);
+      return match errors.is_empty() {
+        true => Ok(root.to_value()),
+        false => Err(errors.remove(0)),
+      };
+    }
+    // Positions of errors stay those in the whole text
+    let span = self.span();
+    let text = &text[..span.end];
+    let mut lexer = Lexemes::new(text, span.start, true);
+    lexer.max_errors = limits.max_errors;
+    let parser = Parser::new(text, lexer.by_ref(), limits, None);
+    let (node, errors) = parser.value_only();
+    let node = node.unwrap_or(Node { span, kind: NodeKind::Nil });
+    let Parsed { root, mut errors } =
+      Parsed::new(node, lexer.errors, errors, limits);
+    match errors.is_empty() {
+      true => Ok(root.to_value()),
+      false => Err(errors.remove(0)),
+    }
+  }
+}
+
+// The kind of a lexeme of the scan, atoms are not allocated
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Lexeme {
+  Atom,
+  Nil,
+  Colon,
+  Open,
+  Close,
+  End,
+}
+
+// A lexeme with its span and whether whitespace is before it
+type Spanned = (Lexeme, Span, bool);
+
+// A list or document being scanned, with its slot and number of children
+#[derive(Clone, Copy)]
+struct Frame {
+  slot: usize,
+  len:  usize,
+}
+
+struct Scan<'t> {
+  text:   &'t str,
+  tokens: axlex::TokenIterator<'t, State>,
+  peeked: VecDeque<Spanned>,
+  string: Option<usize>,
+  spaced: bool,
+  limits: &'t ParseLimits,
+  slots:  Vec<Slot>,
+  stack:  Vec<Frame>,
+}
+
+impl<'t> Scan<'t> {
+  fn new(text: &'t str, limits: &'t ParseLimits) -> Scan<'t> {
+    Scan {
+      text,
+      tokens: lex::tokens(text.as_bytes()),
+      peeked: VecDeque::new(),
+      string: None,
+      spaced: true,
+      limits,
+      slots: vec![],
+      stack: vec![],
+    }
+  }
+
+  fn error(&self, kind: ErrorKind, span: Span) -> ParseError {
+    ParseError { kind, span, position: span.position(self.text) }
+  }
+
+  // The next lexeme, with whether whitespace is before it. The tokens are
+  // spans of the text, only the guards of strings are copied.
+  #[allow(non_upper_case_globals)]
+  fn lex(&mut self) -> Result<Spanned, ParseError> {
+    while let Some(token) = self.tokens.next_span() {
+      let span = Span::new(token.start, token.index);
+      let lexeme = match token.rule_id {
+        R_ID_ws | R_ID_comment => {
+          self.spaced = true;
+          continue;
+        }
+        R_ID_bare => Lexeme::Atom,
+        R_ID_special_atom if token.data(self.text.as_bytes()) == b"#nil" => {
+          Lexeme::Nil
+        }
+        R_ID_colon => Lexeme::Colon,
+        R_ID_open_paren => Lexeme::Open,
+        R_ID_close_paren => Lexeme::Close,
+        R_ID_start_string | R_ID_start_gd_string => {
+          self.string = Some(span.start);
+          continue;
+        }
+        R_ID_string | R_ID_gd_string | R_ID_gd_quote | R_ID_start_esc
+        | R_ID_simple_esc | R_ID_x_esc | R_ID_u_esc | R_ID_invalid_x_esc
+        | R_ID_invalid_u_esc | R_ID_invalid_esc => continue,
+        R_ID_end_string | R_ID_end_gd_string => {
+          let start = self.string.take().unwrap_or(span.start);
+          let spaced = std::mem::replace(&mut self.spaced, false);
+          return Ok((Lexeme::Atom, Span::new(start, span.end), spaced));
+        }
+        R_ID_UNEXPECTED_END if self.string.is_none() => break,
+        R_ID_unterminated_string | R_ID_UNEXPECTED_END => {
+          let start = self.string.unwrap_or(span.start);
+          let span = Span::new(start, span.start);
+          return Err(self.error(ErrorKind::UnterminatedString, span));
+        }
+        _ => {
+          let data = token.data(self.text.as_bytes());
+          let data = String::from_utf8_lossy(data).into_owned();
+          return Err(self.error(ErrorKind::InvalidToken(data), span));
+        }
+      };
+      let spaced = std::mem::replace(&mut self.spaced, false);
+      return Ok((lexeme, span, spaced));
+    }
+    let end = Span::new(self.text.len(), self.text.len());
+    Ok((Lexeme::End, end, true))
+  }
+
+  fn peek(&mut self, ahead: usize) -> Result<Spanned, ParseError> {
+    while self.peeked.len() <= ahead {
+      let lexeme = self.lex()?;
+      self.peeked.push_back(lexeme);
+    }
+    Ok(self.peeked[ahead])
+  }
+
+  fn bump(&mut self) -> Result<Spanned, ParseError> {
+    self.peek(0)?;
+    Ok(self.peeked.pop_front().unwrap())
+  }
+
+  fn push(&mut self, kind: Kind, span: Span, key: Option<Span>) {
+    self.slots.push(Slot { kind, span, key, descendants: 0 });
+  }
+
+  // The entries of the root document and all values below them
+  fn root(&mut self) -> Result<(), ParseError> {
+    let span = Span::new(0, self.text.len());
+    self.push(Kind::Document, span, None);
+    self.stack.push(Frame { slot: 0, len: 0 });
+    while let Some(&frame) = self.stack.last() {
+      let (lexeme, span, spaced) = self.peek(0)?;
+      let document = self.slots[frame.slot].kind == Kind::Document;
+      let root = self.stack.len() == 1;
+      match lexeme {
+        Lexeme::End if root => {
+          self.close(span.end);
+          continue;
+        }
+        Lexeme::End => {
+          let open = self.slots[frame.slot].span;
+          return Err(self.error(ErrorKind::UnclosedParen, open));
+        }
+        Lexeme::Close if root => {
+          return Err(self.error(ErrorKind::UnmatchedParen, span));
+        }
+        Lexeme::Close => {
+          self.bump()?;
+          self.close(span.end);
+          continue;
+        }
+        _ => {}
+      }
+      if frame.len > 0 && !spaced {
+        return Err(self.error(ErrorKind::MissingWhitespace, span));
+      }
+      if frame.len >= self.limits.max_entries {
+        let max = self.limits.max_entries;
+        let kind = ErrorKind::LimitExceeded { limit: Limit::Entries, max };
+        return Err(self.error(kind, span));
+      }
+      let key = match (document, lexeme) {
+        (true, Lexeme::Atom) => {
+          self.bump()?;
+          match self.bump()? {
+            (Lexeme::Colon, ..) => Some(span),
+            (Lexeme::End, span, _) => {
+              return Err(self.error(ErrorKind::UnexpectedEnd, span));
+            }
+            (_, span, _) => {
+              return Err(self.error(ErrorKind::ExpectedColon, span));
+            }
+          }
+        }
+        (true, _) => return Err(self.error(ErrorKind::ExpectedKey, span)),
+        (false, _) => None,
+      };
+      self.stack.last_mut().unwrap().len += 1;
+      self.value(key)?;
+    }
+    Ok(())
+  }
+
+  fn value(&mut self, key: Option<Span>) -> Result<(), ParseError> {
+    let (lexeme, span, _) = self.bump()?;
+    if self.slots.len() > self.limits.max_nodes {
+      let max = self.limits.max_nodes;
+      let kind = ErrorKind::LimitExceeded { limit: Limit::Nodes, max };
+      return Err(self.error(kind, span));
+    }
+    match lexeme {
+      Lexeme::Atom => self.push(Kind::Atom, span, key),
+      Lexeme::Nil => self.push(Kind::Nil, span, key),
+      Lexeme::Open => {
+        if self.stack.len() > self.limits.max_depth {
+          let max = self.limits.max_depth;
+          let kind = ErrorKind::LimitExceeded { limit: Limit::Depth, max };
+          return Err(self.error(kind, span));
+        }
+        let document =
+          self.peek(0)?.0 == Lexeme::Atom && self.peek(1)?.0 == Lexeme::Colon;
+        let kind = if document { Kind::Document } else { Kind::List };
+        let slot = self.slots.len();
+        self.push(kind, span, key);
+        self.stack.push(Frame { slot, len: 0 });
+      }
+      Lexeme::End => return Err(self.error(ErrorKind::UnexpectedEnd, span)),
+      Lexeme::Colon | Lexeme::Close => {
+        return Err(self.error(ErrorKind::ExpectedValue, span));
+      }
+    }
+    Ok(())
+  }
+
+  fn close(&mut self, end: usize) {
+    let frame = self.stack.pop().unwrap();
+    let descendants = self.slots.len() - frame.slot - 1;
+    let slot = &mut self.slots[frame.slot];
+    slot.span.end = end;
+    slot.descendants = descendants;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TEXT: &str = r##"# hosts
+name: app
+hosts: (
+  (name: a port: 80 tags: (x y))
+  (name: "b\n" port: #nil tags: ())
+)
+"a b": (0: zero "c\"d": 1 #"e"f"#: 2)
+"##;
+
+  fn get(index: &Index, path: &str) -> Option<String> {
+    let value = index.get_path(&path.parse().unwrap())?;
+    Some(value.to_value().unwrap().to_string())
+  }
+
+  #[test]
+  fn test_lookup() {
+    let index = Index::new(TEXT.as_bytes()).unwrap();
+    assert_eq!(index.slots.len(), 17);
+    assert_eq!(get(&index, "name").as_deref(), Some("app"));
+    assert_eq!(get(&index, "hosts.0.tags").as_deref(), Some("(x y)"));
+    assert_eq!(get(&index, "hosts.1.name").as_deref(), Some("\"b\\n\""));
+    assert_eq!(get(&index, "hosts.1.port").as_deref(), Some("#nil"));
+    assert_eq!(get(&index, "hosts.1.tags").as_deref(), Some("()"));
+    assert_eq!(get(&index, r#""a b".0"#).as_deref(), Some("zero"));
+    assert_eq!(get(&index, r#""a b"."c\"d""#).as_deref(), Some("1"));
+    assert_eq!(get(&index, r#""a b"."e\"f""#).as_deref(), Some("2"));
+    assert_eq!(get(&index, "hosts.2"), None);
+    assert_eq!(get(&index, "name.x"), None);
+    assert_eq!(get(&index, "."), Some(crate::parse(TEXT).unwrap().to_string()));
+
+    let hosts = index.get_path(&"hosts".parse().unwrap()).unwrap();
+    assert_eq!(hosts.type_name(), "list");
+    assert_eq!(hosts.len(), 2);
+    assert_eq!(hosts.text().lines().next(), Some("("));
+    let keys = index.root().children().map(|v| v.key().unwrap().into_owned());
+    assert_eq!(keys.collect::<Vec<_>>(), ["name", "hosts", "a b"]);
+  }
+
+  #[test]
+  fn test_errors() {
+    let error = |text: &str| Index::new(text.as_bytes()).unwrap_err();
+    assert_eq!(error("a: (b").to_string(), "unclosed parenthesis at 1:4");
+    assert_eq!(
+      error("a: b)").to_string(),
+      "unmatched closing parenthesis at 1:5"
+    );
+    assert_eq!(error("a b").to_string(), "expected colon after key at 1:3");
+    assert_eq!(error("a: (b)(c)").to_string(), "missing whitespace at 1:7");
+    assert_eq!(error("a: \"b").to_string(), "unterminated string at 1:4");
+    assert_eq!(error("a: #x").to_string(), "unknown special atom `#x` at 1:4");
+    // The parser finds the invalid escape before the scan's error
+    assert_eq!(
+      error("a: \"\\q\" b").to_string(),
+      "invalid escape `\\q` at 1:5"
+    );
+    let err = Index::new(b"a: \xff").unwrap_err();
+    assert_eq!(err.to_string(), "invalid token `\u{fffd}` at 1:4");
+
+    let limits = ParseLimits { max_depth: 1, ..ParseLimits::default() };
+    let err = Index::with_limits(b"a: ((b))", &limits).unwrap_err();
+    assert_eq!(err.to_string(), "nesting depth exceeds 1 at 1:5");
+
+    // Errors within a value are found when it is materialized
+    let index = Index::new(b"a: (b: 1 b: 2) c: \"\\q\"").unwrap();
+    let a = index.get_path(&"a".parse().unwrap()).unwrap();
+    assert_eq!(
+      a.to_value().unwrap_err().to_string(),
+      "duplicate key `b` at 1:10"
+    );
+    let c = index.get_path(&"c".parse().unwrap()).unwrap();
+    assert_eq!(
+      c.to_value().unwrap_err().to_string(),
+      "invalid escape `\\q` at 1:20"
+    );
+  }
+}
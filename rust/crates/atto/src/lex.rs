@@ -0,0 +1,251 @@
+//! The atto lexer, generated by `axlex`
+//!
+//! The rule groups are `init` for everything outside of strings, `str` and
+//! `esc` for strings and their escapes, and `gd_str` for guarded strings.
+//! Only between tokens of `init` the lexer can be restarted without state.
+//!
+//! A `#` followed by a letter starts a special atom like `#nil`. The lexer
+//! accepts any name, the parser knows which ones are defined.
+
+use axlex::{Token, TokenIterator};
+use const_format::concatcp;
+
+// regex parts
+const SPC: &str = r" ";
+const TAB: &str = r"\t";
+const LF_NL: &str = r"\n\r";
+const DQU: &str = r#"""#;
+const BSL: &str = r"\\";
+const HASH: &str = r"#";
+const INVALID_BARE: &str = concatcp!(r"\(\):", DQU, BSL, HASH);
+const INVALID_CATS: &str = r"\p{Cc}\p{Cn}\p{Co}\pZ";
+const GD_ID: &str = "[_0-9]{0,9}";
+
+// character classes
+const CC_WS: &str = concatcp!("[", SPC, TAB, LF_NL, "]");
+const CC_SPC_TAB: &str = concatcp!("[", SPC, TAB, "]");
+const CC_DQU_BSL: &str = concatcp!("[", DQU, BSL, "]");
+const CC_BSL_HASH: &str = concatcp!("[", BSL, HASH, "]");
+const CC_INVALID_BARE: &str = concatcp!("[", INVALID_BARE, "]");
+const CC_HEX: &str = "[a-fA-F0-9]";
+const CC_INVALID: &str = concatcp!("[[", INVALID_CATS, "]--", CC_WS, "]");
+const CC_VALID_NOT_WS: &str = concatcp!("[^", INVALID_CATS, "]");
+const CC_VALID: &str = concatcp!("[", CC_VALID_NOT_WS, CC_WS, "]");
+const CC_VALID_SPC_TAB: &str = concatcp!("[", CC_VALID_NOT_WS, CC_SPC_TAB, "]");
+const CC_BARE: &str =
+  concatcp!("[", CC_VALID_NOT_WS, "--", CC_INVALID_BARE, "]");
+const CC_STRING: &str = concatcp!("[", CC_VALID_SPC_TAB, "--", CC_DQU_BSL, "]");
+const CC_GD_STRING: &str = concatcp!("[", CC_VALID, "--", DQU, "]");
+
+// token regexen
+const WS: &str = concatcp!(CC_WS, "+");
+const COMMENT: &str =
+  concatcp!("#+(?:", CC_SPC_TAB, CC_VALID_SPC_TAB, r"*)?(?:\r?\n|\r|\z)");
+const BARE: &str = concatcp!(CC_BARE, "+");
+const GD_START: &str = concatcp!(HASH, GD_ID, DQU);
+const GD_END: &str = concatcp!(DQU, GD_ID, HASH);
+const SPECIAL_ATOM: &str = concatcp!(HASH, "[a-zA-Z]", CC_BARE, "*");
+const INVALID_INIT: &str = concatcp!(CC_BSL_HASH, CC_BARE, "{0,20}");
+const INVALID_CHARS: &str = concatcp!(CC_INVALID, "+");
+const STRING: &str = concatcp!(CC_STRING, "+");
+const GD_STRING: &str = concatcp!(CC_GD_STRING, "+");
+const ESC: &str = concatcp!("[", DQU, BSL, "enrt0", "]");
+const X_ESC: &str = concatcp!("x", CC_HEX, "{2}");
+const INVALID_X_ESC: &str = concatcp!("x", CC_HEX, "?");
+const U_ESC: &str = concatcp!("u", r"\{", CC_HEX, "{3,6}", r"\}");
+const INVALID_U_ESC: &str = concatcp!("u(?:", r"\{", CC_HEX, r"*\}?)?");
+const INVALID_ESC: &str = concatcp!(CC_VALID, "?");
+
+axlex::lexer! {
+  atto<State> {
+    ALL: [
+      invalid_chars(INVALID_CHARS),
+    ],
+    init: [
+      ws(WS),
+      comment(COMMENT),
+      bare(BARE),
+      start_gd_string(GD_START, action=save_guard, to=gd_str),
+      start_string(DQU, to=str),
+      colon(":"),
+      open_paren(r"\("),
+      close_paren(r"\)"),
+      special_atom(SPECIAL_ATOM),
+      invalid_init(INVALID_INIT),
+    ],
+    str: [
+      string(STRING),
+      start_esc(BSL, to=esc),
+      end_string(DQU, to=init),
+      unterminated_string("[\n\r]", to=init),
+    ],
+    esc: [
+      simple_esc(ESC, to=str),
+      x_esc(X_ESC, to=str),
+      invalid_x_esc(INVALID_X_ESC, to=str),
+      u_esc(U_ESC, to=str),
+      invalid_u_esc(INVALID_U_ESC, to=str),
+      invalid_esc(INVALID_ESC, to=str),
+    ],
+    gd_str: [
+      gd_string(GD_STRING),
+      end_gd_string(GD_END, action=check_guard, to=init),
+      gd_quote(DQU),
+    ],
+  };
+}
+
+/// Lexer state: the guard of the current guarded string
+#[derive(Clone, Debug, Default)]
+pub struct State {
+  guard: Vec<u8>,
+}
+
+// The guard is the part between `#` and `"` of the start token
+fn action_save_guard(token: Token, state: &mut State) -> Option<Token> {
+  state.guard = token.data[1..token.data.len() - 1].to_vec();
+  Some(token)
+}
+
+// Reject end tokens with another guard, then `gd_quote` matches the quote
+fn action_check_guard(token: Token, state: &mut State) -> Option<Token> {
+  let guard = &token.data[1..token.data.len() - 1];
+  (guard == state.guard).then_some(token)
+}
+
+/// Start the atto lexer on `input`.
+///
+/// ```
+/// # use atto::lex::{tokens, R_ID_bare, R_ID_colon, R_ID_ws};
+/// let rule_ids = tokens(b"a: b").map(|t| t.rule_id).collect::<Vec<_>>();
+///
+/// assert_eq!(rule_ids, [R_ID_bare, R_ID_colon, R_ID_ws, R_ID_bare]);
+/// ```
+pub fn tokens(input: &[u8]) -> TokenIterator<'_, State> {
+  TokenIterator::start(input, &LEXER, State::default())
+}
+
+/// The name of the rule with `rule_id`.
+pub fn rule_name(rule_id: u16) -> &'static str {
+  axlex::rule_of(&LEXER, rule_id).rule_name
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn lex(input: &str) -> Vec<(&'static str, String)> {
+    let tokens = tokens(input.as_bytes());
+    let data = |t: Token| String::from_utf8_lossy(&t.data).into_owned();
+    tokens.map(|t| (rule_name(t.rule_id), data(t))).collect()
+  }
+
+  #[test]
+  fn test_entries() {
+    assert_eq!(lex(""), []);
+    assert_eq!(lex("a:x"), [
+      ("bare", "a".to_owned()),
+      ("colon", ":".into()),
+      ("bare", "x".into()),
+    ]);
+    let tokens = lex(r#" "key" : "value" "#);
+    let names = tokens.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    assert_eq!(names, [
+      "ws",
+      "start_string",
+      "string",
+      "end_string",
+      "ws",
+      "colon",
+      "ws",
+      "start_string",
+      "string",
+      "end_string",
+      "ws",
+    ]);
+  }
+
+  #[test]
+  fn test_comment() {
+    assert_eq!(lex("# one\n#\na"), [
+      ("comment", "# one\n".to_owned()),
+      ("comment", "#\n".into()),
+      ("bare", "a".into())
+    ]);
+    assert_eq!(lex("#1 y"), [
+      ("invalid_init", "#1".to_owned()),
+      ("ws", " ".into()),
+      ("bare", "y".into())
+    ]);
+  }
+
+  #[test]
+  fn test_special_atom() {
+    assert_eq!(lex("(#nil #future-1)"), [
+      ("open_paren", "(".to_owned()),
+      ("special_atom", "#nil".into()),
+      ("ws", " ".into()),
+      ("special_atom", "#future-1".into()),
+      ("close_paren", ")".into()),
+    ]);
+    assert_eq!(lex("#\"x\"#")[0], ("start_gd_string", "#\"".to_owned()));
+  }
+
+  #[test]
+  fn test_guarded_string() {
+    let tokens = lex(r###"#1"a "b"# "#1"1#"###);
+    let names = tokens.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    assert_eq!(names, [
+      "start_gd_string",
+      "gd_string",
+      "gd_quote",
+      "gd_string",
+      "gd_quote",
+      "gd_string",
+      "gd_quote",
+      "gd_string",
+      "end_gd_string",
+    ]);
+  }
+
+  #[test]
+  fn test_escapes() {
+    let tokens = lex(r#""\x4a\u{1f4a9}\xz\q""#);
+    let names = tokens.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    assert_eq!(names, [
+      "start_string",
+      "start_esc",
+      "x_esc",
+      "start_esc",
+      "u_esc",
+      "start_esc",
+      "invalid_x_esc",
+      "string",
+      "start_esc",
+      "invalid_esc",
+      "end_string",
+    ]);
+
+    let tokens = lex(r#""x\e-\n.\r:\t,\0;\\'\"_""#);
+    let escapes = tokens.iter().filter(|(name, _)| *name == "simple_esc");
+    let escapes = escapes.map(|(_, data)| data.as_str()).collect::<String>();
+    assert_eq!(escapes, "enrt0\\\"");
+    assert_eq!(tokens.len(), 2 + 7 * 3 + 1);
+  }
+
+  #[test]
+  fn test_unterminated_string() {
+    assert_eq!(lex("\"a\nb"), [
+      ("start_string", "\"".to_owned()),
+      ("string", "a".into()),
+      ("unterminated_string", "\n".into()),
+      ("bare", "b".into()),
+    ]);
+    assert_eq!(lex("\"a")[2], ("UNEXPECTED_END", "".to_owned()));
+  }
+
+  #[test]
+  fn test_invalid_chars() {
+    assert_eq!(lex("a\u{0}b")[1], ("invalid_chars", "\u{0}".to_owned()));
+  }
+}
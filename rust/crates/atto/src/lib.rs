@@ -1,6 +1,33 @@
+pub mod atoms;
+pub mod binary;
+pub mod canonical;
+pub mod codegen;
+pub mod config;
+pub mod convert;
+pub mod de;
+pub mod decode;
+pub mod diagnostic;
+pub mod diff;
+pub mod encode;
+pub mod format;
+pub mod incremental;
+pub mod json_schema;
+pub mod lazy;
+pub mod lex;
+pub mod lines;
+pub mod macros;
 pub mod parser;
+pub mod patch;
+pub mod path;
+pub mod query;
+pub mod schema;
+pub mod syntax;
 pub mod value;
+pub mod visit;
+pub mod watch;
+pub mod writer;
 
+pub use parser::parse;
 pub use value::Value;
 
 /// Dummy test to exercise test infrastructure
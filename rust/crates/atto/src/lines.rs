@@ -0,0 +1,388 @@
+//! atto-lines: streams of root documents, one per record
+//!
+//! A record is either one line with the entries of a document, or a
+//! document in parentheses, which may span lines and may be followed by
+//! more records on its last line. Whitespace and comments between records
+//! are skipped. The [`Writer`] writes one document in parentheses per line.
+//!
+//! A record with errors is reported, and reading goes on with the next one.
+//! A record in parentheses which is still open when a line starts with `(`
+//! ends before that line, so that a missing `)` does not swallow the rest
+//! of the stream.
+
+use crate::lex::{self, *};
+use crate::parser::{parse_with, ErrorKind, Limit, ParseError, ParseLimits};
+use crate::syntax::{Position, Span};
+use crate::value::{format_document, Document, Value};
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+#[derive(Debug)]
+pub enum LinesError {
+  Io(io::Error),
+  /// An invalid record, the error has its position in the stream
+  Record {
+    record: usize,
+    error:  ParseError,
+  },
+}
+
+impl fmt::Display for LinesError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LinesError::Io(err) => write!(f, "{err}"),
+      LinesError::Record { record, error } => {
+        write!(f, "record {record}: {error}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for LinesError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      LinesError::Io(err) => Some(err),
+      LinesError::Record { error, .. } => Some(error),
+    }
+  }
+}
+
+/// Reads the documents of an atto-lines stream.
+///
+/// ```
+/// # use atto::lines::Reader;
+/// let input = "a: 1 b: 2\n# comment\n(a: 3\n b: 4) (a: 5)\nc 6\na: 7\n";
+/// let records = Reader::new(input.as_bytes())
+///   .map(|record| match record {
+///     Ok(document) => atto::Value::Document(document).to_string(),
+///     Err(err) => err.to_string(),
+///   })
+///   .collect::<Vec<_>>();
+///
+/// assert_eq!(records, [
+///   "(a: 1 b: 2)",
+///   "(a: 3 b: 4)",
+///   "(a: 5)",
+///   "record 4: expected colon after key at 5:3",
+///   "(a: 7)",
+/// ]);
+/// ```
+pub struct Reader<R> {
+  inner:   R,
+  limits:  ParseLimits,
+  buf:     String,
+  // stream position of the start of `buf`
+  offset:  usize,
+  line:    usize,
+  column:  usize,
+  // where lexing an open record in parentheses goes on, with its depth
+  scan:    (usize, usize),
+  records: usize,
+  done:    bool,
+}
+
+// The extent of a record at the start of the buffer
+enum Record {
+  Line(usize),
+  Parens(usize),
+  Unclosed(usize),
+  TooLong(usize),
+}
+
+impl<R: BufRead> Reader<R> {
+  pub fn new(inner: R) -> Reader<R> {
+    Reader::with_limits(inner, ParseLimits::default())
+  }
+
+  /// A reader which parses each record within `limits`. A record in
+  /// parentheses may not be longer than `max_input_len` either.
+  pub fn with_limits(inner: R, limits: ParseLimits) -> Reader<R> {
+    Reader {
+      inner,
+      limits,
+      buf: String::new(),
+      offset: 0,
+      line: 1,
+      column: 1,
+      scan: (0, 0),
+      records: 0,
+      done: false,
+    }
+  }
+
+  pub fn into_inner(self) -> R { self.inner }
+
+  // Append the next line to the buffer, false at the end of the input.
+  // The rest of a line longer than the limit is skipped.
+  fn read_line(&mut self) -> io::Result<bool> {
+    let limit = self.limits.max_input_len.saturating_add(1);
+    let mut line = self.inner.by_ref().take(limit as u64);
+    let len = line.read_line(&mut self.buf)?;
+    if len == limit && !self.buf.ends_with('\n') {
+      self.inner.skip_until(b'\n')?;
+    }
+    Ok(len > 0)
+  }
+
+  // Drop `len` bytes from the front of the buffer, moving the position
+  fn consume(&mut self, len: usize) {
+    let end = Position::of(&self.buf[..len], len);
+    if end.line > 1 {
+      self.line += end.line - 1;
+      self.column = end.column;
+    } else {
+      self.column += end.column - 1;
+    }
+    self.offset += len;
+    self.buf.drain(..len);
+    self.scan = (0, 0);
+  }
+
+  fn record(&mut self) -> io::Result<Record> {
+    if !self.buf.starts_with('(') {
+      let end = self.buf.find('\n').map_or(self.buf.len(), |i| i + 1);
+      return Ok(Record::Line(end));
+    }
+
+    loop {
+      let (from, depth) = self.scan;
+      match scan_parens(&self.buf, from, depth) {
+        Ok(end) => return Ok(Record::Parens(end)),
+        Err(scan) => self.scan = scan,
+      }
+      let len = self.buf.len();
+      if len > self.limits.max_input_len {
+        return Ok(Record::TooLong(len));
+      }
+      // A line starting with `(` outside of a string starts a new record
+      let resync = self.scan.0 == len;
+      if !self.read_line()? || resync && self.buf[len..].starts_with('(') {
+        return Ok(Record::Unclosed(len));
+      }
+    }
+  }
+
+  fn parse(&self, record: &Record) -> Result<Document, ParseError> {
+    let (text, max) = match *record {
+      Record::Line(len) => (self.buf[..len].to_owned(), 0),
+      // Without its parentheses the record is a root document
+      Record::Parens(len) => {
+        let inner = &self.buf[1..len - 1];
+        (format!(" {inner} "), 0)
+      }
+      Record::Unclosed(_) => {
+        let kind = ErrorKind::UnclosedParen;
+        return Err(self.error(kind, Span::new(0, 1)));
+      }
+      Record::TooLong(len) => (String::new(), len),
+    };
+    if max > 0 {
+      let max = self.limits.max_input_len;
+      let kind = ErrorKind::LimitExceeded { limit: Limit::InputLength, max };
+      return Err(self.error(kind, Span::new(max, max)));
+    }
+
+    match parse_with(&text, &self.limits) {
+      Ok(Value::Document(document)) => Ok(document),
+      Ok(_) => unreachable!("the root is a document"),
+      Err(err) => Err(self.error(err.kind, err.span)),
+    }
+  }
+
+  // An error at `span` of the record at the start of the buffer
+  fn error(&self, kind: ErrorKind, span: Span) -> ParseError {
+    let relative = Position::of(&self.buf, span.start);
+    let position = Position {
+      line:   self.line + relative.line - 1,
+      column: match relative.line {
+        1 => self.column + relative.column - 1,
+        _ => relative.column,
+      },
+    };
+    let span = Span::new(self.offset + span.start, self.offset + span.end);
+    ParseError { kind, span, position }
+  }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+  type Item = Result<Document, LinesError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      self.consume(leading_space(&self.buf));
+      if !self.buf.is_empty() {
+        break;
+      }
+      if self.done {
+        return None;
+      }
+      match self.read_line() {
+        Ok(true) => {}
+        Ok(false) => self.done = true,
+        // A line which is not UTF-8 is skipped, other errors end reading
+        Err(err) => {
+          self.done = err.kind() != io::ErrorKind::InvalidData;
+          return Some(Err(LinesError::Io(err)));
+        }
+      }
+    }
+
+    let record = match self.record() {
+      Ok(record) => record,
+      Err(err) => {
+        self.done = err.kind() != io::ErrorKind::InvalidData;
+        return Some(Err(LinesError::Io(err)));
+      }
+    };
+    self.records += 1;
+    let result = self
+      .parse(&record)
+      .map_err(|error| LinesError::Record { record: self.records, error });
+    let (Record::Line(len)
+    | Record::Parens(len)
+    | Record::Unclosed(len)
+    | Record::TooLong(len)) = record;
+    self.consume(len);
+    Some(result)
+  }
+}
+
+// The length of the whitespace and comments at the start of `text`
+#[allow(non_upper_case_globals)]
+fn leading_space(text: &str) -> usize {
+  let space =
+    |token: &axlex::Token| matches!(token.rule_id, R_ID_ws | R_ID_comment);
+  let tokens = lex::tokens(text.as_bytes()).take_while(space);
+  tokens.last().map_or(0, |token| token.index)
+}
+
+// Lex from `from` at `depth` to the end of the record in parentheses, or
+// the last position in the `init` group with its depth if it is still open
+#[allow(non_upper_case_globals)]
+fn scan_parens(
+  text: &str,
+  from: usize,
+  mut depth: usize,
+) -> Result<usize, (usize, usize)> {
+  let mut open = (from, depth);
+  for token in lex::tokens(&text.as_bytes()[from..]) {
+    let end = from + token.index;
+    match token.rule_id {
+      R_ID_open_paren => depth += 1,
+      R_ID_close_paren if depth == 1 => return Ok(end),
+      R_ID_close_paren => depth -= 1,
+      R_ID_UNEXPECTED_END => break,
+      _ => {}
+    }
+    if token.group_id == G_ID_init {
+      open = (end, depth);
+    }
+  }
+  Err(open)
+}
+
+/// Writes documents as an atto-lines stream, one per line.
+///
+/// ```
+/// # use atto::{lines::Writer, value::Document, Value};
+/// let mut document = Document::new();
+/// document.insert("msg".to_owned(), Value::Atom("a\nb".to_owned()));
+///
+/// let mut writer = Writer::new(vec![]);
+/// writer.write(&document).unwrap();
+/// writer.write(&Document::new()).unwrap();
+///
+/// let output = String::from_utf8(writer.into_inner()).unwrap();
+/// assert_eq!(output, "(msg: \"a\\nb\")\n()\n");
+/// ```
+pub struct Writer<W> {
+  inner: W,
+}
+
+impl<W: Write> Writer<W> {
+  pub fn new(inner: W) -> Writer<W> { Writer { inner } }
+
+  /// Write a document in its compact form on one line.
+  pub fn write(&mut self, document: &Document) -> io::Result<()> {
+    writeln!(self.inner, "{}", format_document(document))
+  }
+
+  pub fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+
+  pub fn into_inner(self) -> W { self.inner }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn read(input: &[u8]) -> Vec<String> {
+    let records = Reader::new(input).map(|record| match record {
+      Ok(document) => Value::Document(document).to_string(),
+      Err(err) => err.to_string(),
+    });
+    records.collect()
+  }
+
+  #[test]
+  fn test_multi_line_records() {
+    let input = "(a: \")\" # )\n  b: (1\n2)\n  c: #\"\n(\"#) d: 3";
+    assert_eq!(read(input.as_bytes()), [
+      "(a: \")\" b: (1 2) c: \"\\n(\")",
+      "(d: 3)"
+    ]);
+    assert_eq!(read(b"  # only a comment"), Vec::<String>::new());
+  }
+
+  #[test]
+  fn test_resync() {
+    let input = "(a: 1\n(b: 2)\na: (\nc: \"x\n(d: (1 2)\n";
+    assert_eq!(read(input.as_bytes()), [
+      "record 1: unclosed parenthesis at 1:1",
+      "(b: 2)",
+      "record 3: unclosed parenthesis at 3:4",
+      "record 4: unterminated string at 4:4",
+      "record 5: unclosed parenthesis at 5:1",
+    ]);
+
+    let input = b"a: 1\nb: \xff\nc: 3\n";
+    assert_eq!(read(input), [
+      "(a: 1)",
+      "stream did not contain valid UTF-8",
+      "(c: 3)"
+    ]);
+  }
+
+  #[test]
+  fn test_limits() {
+    let limits = ParseLimits { max_input_len: 8, ..ParseLimits::default() };
+    let input = "a: 1\nb: 123456789\n(c:\n1234 5678)\nd: 4\n";
+    let records = Reader::with_limits(input.as_bytes(), limits);
+    let records = records.map(|record| match record {
+      Ok(document) => Value::Document(document).to_string(),
+      Err(err) => err.to_string(),
+    });
+    assert_eq!(records.collect::<Vec<_>>(), [
+      "(a: 1)",
+      "record 2: input length exceeds 8 at 2:9",
+      "record 3: input length exceeds 8 at 3:5",
+      "(d: 4)",
+    ]);
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let input = "a: (b: \"x y\" c: ()) d: \"\\\"\"\n()\n(e: (1 (2)))";
+    let documents = Reader::new(input.as_bytes()).map(Result::unwrap);
+    let mut writer = Writer::new(vec![]);
+    for document in documents {
+      writer.write(&document).unwrap();
+    }
+    let output = String::from_utf8(writer.into_inner()).unwrap();
+    assert_eq!(
+      output,
+      "(a: (b: \"x y\" c: ()) d: \"\\\"\")\n()\n(e: (1 (2)))\n"
+    );
+    assert_eq!(read(output.as_bytes()), output.lines().collect::<Vec<_>>());
+  }
+}
@@ -0,0 +1,184 @@
+//! The [`atto!`](crate::atto!) macro for writing values in Rust code
+
+/// Build a [`Value::Document`](crate::Value::Document) from atto syntax.
+///
+/// Atoms are identifiers, literals and negative numbers, written as Rust
+/// tokens, so atoms like `2023-10-14` or `x-ray` need quotes. Literals
+/// with unit suffixes like `10MiB` or `30s` are fine. An expression in
+/// braces is interpolated with [`ToAtto`](crate::encode::ToAtto), as a
+/// value or as a key. `#nil` is [`Value::Nil`](crate::Value::Nil).
+///
+/// ```
+/// use atto::atto;
+///
+/// let power = "x ray";
+/// let hero = atto! {
+///   name: John
+///   age: 42
+///   powers: (fly {power})
+///   stats: (speed: 10MiB "max height": -3.5)
+///   sidekick: #nil
+/// };
+///
+/// assert_eq!(
+///   hero.to_string(),
+///   r#"(name: John age: 42 powers: (fly "x ray") stats: (speed: 10MiB "max height": -3.5) sidekick: #nil)"#
+/// );
+/// ```
+#[macro_export]
+macro_rules! atto {
+  // Entries of a document, one at a time
+  (@document $document:ident) => {};
+  (@document $document:ident $key:tt : - $value:tt $($rest:tt)*) => {
+    $document.insert($crate::atto!(@key $key), $crate::atto!(@value - $value));
+    $crate::atto!(@document $document $($rest)*);
+  };
+  (@document $document:ident $key:tt : # $value:tt $($rest:tt)*) => {
+    $document.insert($crate::atto!(@key $key), $crate::atto!(@value # $value));
+    $crate::atto!(@document $document $($rest)*);
+  };
+  (@document $document:ident $key:tt : $value:tt $($rest:tt)*) => {
+    $document.insert($crate::atto!(@key $key), $crate::atto!(@value $value));
+    $crate::atto!(@document $document $($rest)*);
+  };
+
+  // Elements of a list, collected until the last one
+  (@list [$($elements:expr,)*]) => {
+    $crate::Value::List(::std::vec![$($elements,)*])
+  };
+  (@list [$($elements:expr,)*] - $value:tt $($rest:tt)*) => {
+    $crate::atto!(@list [$($elements,)* $crate::atto!(@value - $value),] $($rest)*)
+  };
+  (@list [$($elements:expr,)*] # $value:tt $($rest:tt)*) => {
+    $crate::atto!(@list [$($elements,)* $crate::atto!(@value # $value),] $($rest)*)
+  };
+  (@list [$($elements:expr,)*] $value:tt $($rest:tt)*) => {
+    $crate::atto!(@list [$($elements,)* $crate::atto!(@value $value),] $($rest)*)
+  };
+
+  (@key {$key:expr}) => { ::std::string::ToString::to_string(&$key) };
+  (@key $key:tt) => { $crate::macros::literal(stringify!($key)) };
+
+  (@value {$value:expr}) => { $crate::encode::ToAtto::to_atto(&$value) };
+  (@value # nil) => { $crate::Value::Nil };
+  (@value - $atom:tt) => {
+    $crate::Value::Atom(::std::format!("-{}", stringify!($atom)))
+  };
+  (@value ($key:tt : $($entries:tt)*)) => {{
+    let mut document = $crate::value::Document::new();
+    $crate::atto!(@document document $key : $($entries)*);
+    $crate::Value::Document(document)
+  }};
+  (@value ($($elements:tt)*)) => { $crate::atto!(@list [] $($elements)*) };
+  (@value $atom:tt) => {
+    $crate::Value::Atom($crate::macros::literal(stringify!($atom)))
+  };
+
+  ($($entries:tt)*) => {{
+    #[allow(unused_mut)]
+    let mut document = $crate::value::Document::new();
+    $crate::atto!(@document document $($entries)*);
+    $crate::Value::Document(document)
+  }};
+}
+
+/// The atom of a Rust token: the contents of string and character
+/// literals and the text of anything else
+#[doc(hidden)]
+pub fn literal(token: &str) -> String {
+  if let Some(raw) = token.strip_prefix('r') {
+    let hashes = raw.len() - raw.trim_start_matches('#').len();
+    if raw[hashes..].starts_with('"') {
+      return raw[hashes + 1..raw.len() - hashes - 1].to_owned();
+    }
+  }
+  let quoted = token.starts_with('"') || token.starts_with('\'');
+  if !quoted {
+    return token.to_owned();
+  }
+
+  let mut atom = String::new();
+  let mut chars = token[1..token.len() - 1].chars().peekable();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      atom.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('n') => atom.push('\n'),
+      Some('r') => atom.push('\r'),
+      Some('t') => atom.push('\t'),
+      Some('0') => atom.push('\0'),
+      Some('x') => {
+        let hex = chars.by_ref().take(2).collect::<String>();
+        atom.push(u8::from_str_radix(&hex, 16).unwrap() as char);
+      }
+      Some('u') => {
+        let hex = chars.by_ref().skip(1).take_while(|&c| c != '}');
+        let hex = hex.collect::<String>();
+        let code = u32::from_str_radix(&hex, 16).unwrap();
+        atom.push(char::from_u32(code).unwrap());
+      }
+      // A line continuation skips the line break and the indentation
+      Some('\n') => while chars.next_if(|c| c.is_whitespace()).is_some() {},
+      Some(c) => atom.push(c),
+      None => {}
+    }
+  }
+  atom
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{parse, Value};
+
+  #[test]
+  fn test_literal() {
+    assert_eq!(literal("fly"), "fly");
+    assert_eq!(literal("10MiB"), "10MiB");
+    assert_eq!(literal(r#""a \"b\"\n\x41\u{1F600}""#), "a \"b\"\nA😀");
+    assert_eq!(literal("\"a\\\n    b\""), "ab");
+    assert_eq!(literal(r###"r#"a "b""#"###), r#"a "b""#);
+    assert_eq!(literal("'x'"), "x");
+  }
+
+  #[test]
+  fn test_atto() {
+    assert_eq!(atto! {}, Value::Document(Default::default()));
+
+    let squad = atto! {
+      squadName: "Super hero squad"
+      "home town": "Metro City"
+      formed: 2016
+      members: (
+        (name: "Molecule Man" age: 29 powers: (radiation "turning tiny"))
+        (name: "Madame Uppercut" powers: ())
+      )
+      offsets: (-1 0 -2.5)
+    };
+    let text = r#"
+      squadName: "Super hero squad"
+      "home town": "Metro City"
+      formed: 2016
+      members: (
+        (name: "Molecule Man" age: 29 powers: (radiation "turning tiny"))
+        (name: "Madame Uppercut" powers: ())
+      )
+      offsets: (-1 0 -2.5)
+    "#;
+    assert_eq!(squad, parse(text).unwrap());
+
+    let key = "dynamic";
+    let ages = vec![29, 39];
+    let interpolated = atto! {
+      {key}: {ages}
+      nested: ({format!("{key}-{}", 2)}: {Some(true)} none: {None::<u8>})
+      list: (#nil {1 + 1} #nil)
+    };
+    assert_eq!(
+      interpolated.to_string(),
+      "(dynamic: (29 39) nested: (dynamic-2: true none: #nil) list: (#nil 2 #nil))"
+    );
+  }
+}
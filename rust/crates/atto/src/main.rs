@@ -0,0 +1,136 @@
+use atto::codegen::RustOptions;
+use atto::convert::{convert_with_docs, Format};
+use atto::encode::ToAtto;
+use atto::query::Query;
+use atto::schema::Schema;
+use std::io::{self, Read};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: atto convert --from toml|yaml [FILE]
+       atto schema infer FILE...
+       atto schema json FILE
+       atto codegen rust [--schema] FILE...
+       atto query QUERY [FILE]
+
+convert: converts FILE (or standard input) to atto and writes it to standard
+output.
+schema infer: writes a schema matching the example documents in FILEs.
+schema json: writes the schema in FILE as a JSON Schema, and warnings about
+what it cannot express to standard error.
+codegen rust: writes Rust types for the schema in FILE, with --schema, or
+for the example documents in FILEs.
+query: runs QUERY on FILE (or standard input) and writes each result on a
+line.";
+
+fn main() -> ExitCode {
+  let args = std::env::args().skip(1).collect::<Vec<_>>();
+  let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+  let result = match args.as_slice() {
+    ["convert", rest @ ..] => cmd_convert(rest),
+    ["schema", "infer", files @ ..] if !files.is_empty() => cmd_infer(files),
+    ["schema", "json", file] => cmd_json_schema(file),
+    ["codegen", "rust", "--schema", file] => cmd_codegen_schema(file),
+    ["codegen", "rust", files @ ..] if !files.is_empty() => {
+      cmd_codegen_examples(files)
+    }
+    ["query", query] => cmd_query(query, None),
+    ["query", query, path] => cmd_query(query, Some(path)),
+    ["-h" | "--help"] => {
+      println!("{USAGE}");
+      return ExitCode::SUCCESS;
+    }
+    _ => Err(USAGE.to_owned()),
+  };
+
+  match result {
+    Ok(output) => {
+      print!("{output}");
+      ExitCode::SUCCESS
+    }
+    Err(err) => {
+      eprintln!("{err}");
+      ExitCode::FAILURE
+    }
+  }
+}
+
+fn cmd_convert(args: &[&str]) -> Result<String, String> {
+  let (format, path) = match args {
+    ["--from", format] => (format, None),
+    ["--from", format, path] => (format, Some(path)),
+    _ => return Err(USAGE.to_owned()),
+  };
+  let format = format.parse::<Format>().map_err(|err| err.to_string())?;
+  let text = read_input(path.copied())?;
+  let (value, docs) =
+    convert_with_docs(&text, format).map_err(|err| err.to_string())?;
+  let options = atto::format::Options::default();
+
+  Ok(atto::format::pretty_with_docs(&value, &options, &docs))
+}
+
+fn cmd_infer(files: &[&str]) -> Result<String, String> {
+  let schema = infer(files)?;
+  Ok(atto::format::pretty(&schema.to_atto()))
+}
+
+fn cmd_json_schema(path: &str) -> Result<String, String> {
+  let schema = read_schema(path)?;
+  let json = atto::json_schema::to_json_schema(&schema);
+  for warning in &json.warnings {
+    eprint!("{path}: {}", warning.render(""));
+  }
+  let output = serde_json::to_string_pretty(&json.schema).unwrap();
+  Ok(output + "\n")
+}
+
+fn cmd_codegen_schema(path: &str) -> Result<String, String> {
+  let schema = read_schema(path)?;
+  Ok(atto::codegen::rust(&schema, &RustOptions::default()))
+}
+
+fn cmd_codegen_examples(files: &[&str]) -> Result<String, String> {
+  let schema = infer(files)?;
+  Ok(atto::codegen::rust(&schema, &RustOptions::default()))
+}
+
+fn cmd_query(query: &str, path: Option<&str>) -> Result<String, String> {
+  let query = query.parse::<Query>().map_err(|err| err.to_string())?;
+  let text = read_input(path)?;
+  let value = atto::parse(&text).map_err(|err| err.to_string())?;
+  let results = query.run(&value).map_err(|err| err.to_string())?;
+
+  Ok(results.iter().map(|result| format!("{result}\n")).collect())
+}
+
+fn read_schema(path: &str) -> Result<Schema, String> {
+  let text = read_input(Some(path))?;
+  atto::decode::from_str::<Schema>(&text)
+    .map_err(|err| format!("{path}: {err}"))
+}
+
+// The schema inferred from the example documents in `files`
+fn infer(files: &[&str]) -> Result<Schema, String> {
+  let mut samples = vec![];
+  for path in files {
+    let text = read_input(Some(path))?;
+    let value = atto::parse(&text).map_err(|err| format!("{path}: {err}"))?;
+    samples.push(value);
+  }
+  Ok(atto::schema::infer(&samples))
+}
+
+fn read_input(path: Option<&str>) -> Result<String, String> {
+  match path {
+    Some(path) => {
+      std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))
+    }
+    None => {
+      let mut text = String::new();
+      io::stdin().read_to_string(&mut text).map_err(|err| err.to_string())?;
+      Ok(text)
+    }
+  }
+}
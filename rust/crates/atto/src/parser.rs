@@ -1 +1,1034 @@
+//! Parser for atto texts
+//!
+//! [`parse()`] returns the root document or the first error. [`parse_tree()`]
+//! recovers from errors: it reports all of them and returns a syntax tree
+//! of everything it could make sense of, which is what editors need.
+//!
+//! Both apply the default [`ParseLimits`], which are safe for untrusted
+//! input. Exceeding a limit stops parsing with an error.
+//!
+//! Grammar, with whitespace or comments required between entries and
+//! between list elements:
+//!
+//! ```text
+//! Root     := ( Entry )*
+//! Entry    := Atom ":" Value
+//! Value    := Atom | "(" ")" | "(" Value+ ")" | "(" Entry+ ")"
+//! ```
 
+use crate::incremental::Reuse;
+use crate::lex::{self, *};
+use crate::syntax::{Entry, LineIndex, Node, NodeKind, Position, Span};
+use crate::value::{Atom, Value};
+use axlex::{Token, TokenIterator};
+use std::borrow::Borrow;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+  /// Characters which are not valid here
+  InvalidToken(String),
+  InvalidEscape(String),
+  UnterminatedString,
+  UnexpectedEnd,
+  ExpectedKey,
+  ExpectedColon,
+  ExpectedValue,
+  MissingWhitespace,
+  UnclosedParen,
+  UnmatchedParen,
+  DuplicateKey(Atom),
+  /// A special atom with a name which is not defined
+  UnknownSpecialAtom(String),
+  LimitExceeded {
+    limit: Limit,
+    max:   usize,
+  },
+}
+
+impl fmt::Display for ErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use ErrorKind::*;
+
+    match self {
+      InvalidToken(token) => write!(f, "invalid token `{token}`"),
+      InvalidEscape(esc) => write!(f, "invalid escape `\\{esc}`"),
+      UnterminatedString => write!(f, "unterminated string"),
+      UnexpectedEnd => write!(f, "unexpected end of text"),
+      ExpectedKey => write!(f, "expected key"),
+      ExpectedColon => write!(f, "expected colon after key"),
+      ExpectedValue => write!(f, "expected value"),
+      MissingWhitespace => write!(f, "missing whitespace"),
+      UnclosedParen => write!(f, "unclosed parenthesis"),
+      UnmatchedParen => write!(f, "unmatched closing parenthesis"),
+      DuplicateKey(key) => write!(f, "duplicate key `{key}`"),
+      UnknownSpecialAtom(atom) => write!(f, "unknown special atom `{atom}`"),
+      LimitExceeded { limit, max } => write!(f, "{limit} exceeds {max}"),
+    }
+  }
+}
+
+/// The limit of a [`ErrorKind::LimitExceeded`] error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Limit {
+  Depth,
+  InputLength,
+  AtomLength,
+  Entries,
+  Nodes,
+}
+
+impl fmt::Display for Limit {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Limit::Depth => "nesting depth",
+      Limit::InputLength => "input length",
+      Limit::AtomLength => "atom length",
+      Limit::Entries => "number of entries",
+      Limit::Nodes => "number of values",
+    })
+  }
+}
+
+/// Limits for parsing untrusted input
+///
+/// The defaults allow texts of up to 16 MiB with up to a million values.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseLimits {
+  /// Maximum nesting depth of parentheses
+  pub max_depth:     usize,
+  /// Maximum length of the text in bytes
+  pub max_input_len: usize,
+  /// Maximum length of an atom in bytes, after unescaping
+  pub max_atom_len:  usize,
+  /// Maximum number of elements of a list or entries of a document
+  pub max_entries:   usize,
+  /// Maximum number of values in the tree
+  pub max_nodes:     usize,
+  /// Maximum number of errors reported, later ones are dropped
+  pub max_errors:    usize,
+}
+
+impl Default for ParseLimits {
+  fn default() -> Self {
+    ParseLimits {
+      max_depth:     128,
+      max_input_len: 16 << 20,
+      max_atom_len:  1 << 20,
+      max_entries:   1 << 16,
+      max_nodes:     1 << 20,
+      max_errors:    100,
+    }
+  }
+}
+
+impl ParseLimits {
+  /// No limits except for the nesting depth, for trusted input only.
+  ///
+  /// The depth limit stays, because the parser and most functions on
+  /// values recurse.
+  pub fn trusted() -> ParseLimits {
+    ParseLimits {
+      max_input_len: usize::MAX,
+      max_atom_len: usize::MAX,
+      max_entries: usize::MAX,
+      max_nodes: usize::MAX,
+      max_errors: usize::MAX,
+      ..ParseLimits::default()
+    }
+  }
+}
+
+/// A syntax error with its location
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+  pub kind:     ErrorKind,
+  pub span:     Span,
+  pub position: Position,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at {}", self.kind, self.position)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The result of [`parse_tree()`]
+#[derive(Clone, Debug)]
+pub struct Parsed {
+  /// The root document, spanning the whole text
+  pub root:   Node,
+  pub errors: Vec<ParseError>,
+}
+
+/// Parse an atto text into its root document.
+///
+/// ```
+/// let value = atto::parse("name: John powers: (fly \"x ray\")").unwrap();
+/// assert_eq!(value.to_string(), r#"(name: John powers: (fly "x ray"))"#);
+///
+/// let err = atto::parse("a: (b").unwrap_err();
+/// assert_eq!(err.to_string(), "unclosed parenthesis at 1:4");
+/// ```
+pub fn parse(text: &str) -> Result<Value, ParseError> {
+  parse_with(text, &ParseLimits::default())
+}
+
+/// Parse an atto text into its root document within `limits`.
+///
+/// ```
+/// # use atto::parser::{parse_with, ParseLimits};
+/// let limits = ParseLimits { max_depth: 2, ..ParseLimits::default() };
+///
+/// assert!(parse_with("a: ((b))", &limits).is_ok());
+/// let err = parse_with("a: (((b)))", &limits).unwrap_err();
+/// assert_eq!(err.to_string(), "nesting depth exceeds 2 at 1:6");
+/// ```
+pub fn parse_with(
+  text: &str,
+  limits: &ParseLimits,
+) -> Result<Value, ParseError> {
+  let Parsed { root, mut errors } = parse_tree_with(text, limits);
+  if errors.is_empty() {
+    Ok(root.to_value())
+  } else {
+    Err(errors.remove(0))
+  }
+}
+
+/// Parse an atto text into a syntax tree, recovering from errors.
+pub fn parse_tree(text: &str) -> Parsed {
+  parse_tree_with(text, &ParseLimits::default())
+}
+
+/// Parse an atto text into a syntax tree within `limits`, recovering from
+/// errors other than exceeded limits.
+pub fn parse_tree_with(text: &str, limits: &ParseLimits) -> Parsed {
+  if let Some(parsed) = Parsed::too_long(text, limits) {
+    return parsed;
+  }
+  let mut lexer = Lexemes::new(text, 0, true);
+  lexer.max_errors = limits.max_errors;
+  let (root, errors) = Parser::new(text, lexer.by_ref(), limits, None).root();
+  Parsed::new(root, lexer.errors, errors, limits)
+}
+
+impl Parsed {
+  // Parse errors after an exceeded limit are not reported, so neither are
+  // lexer errors
+  pub(crate) fn new(
+    root: Node,
+    mut lex_errors: Vec<ParseError>,
+    errors: Vec<ParseError>,
+    limits: &ParseLimits,
+  ) -> Parsed {
+    let is_limit =
+      |err: &&ParseError| matches!(err.kind, ErrorKind::LimitExceeded { .. });
+    if let Some(limit) = errors.iter().find(is_limit) {
+      lex_errors.retain(|err| err.span.start <= limit.span.start);
+    }
+    let mut errors = [lex_errors, errors].concat();
+    errors.sort_by_key(|err| err.span.start);
+    errors.truncate(limits.max_errors);
+    Parsed { root, errors }
+  }
+
+  // An empty tree with an error if the text is too long to be parsed
+  pub(crate) fn too_long(text: &str, limits: &ParseLimits) -> Option<Parsed> {
+    let max = limits.max_input_len;
+    if text.len() <= max {
+      return None;
+    }
+    let span = Span::new(max, text.len());
+    let root = Node { span: Span::new(0, text.len()), kind: NodeKind::Nil };
+    let kind = ErrorKind::LimitExceeded { limit: Limit::InputLength, max };
+    let position = span.position(text);
+    Some(Parsed { root, errors: vec![ParseError { kind, span, position }] })
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Kind {
+  Atom(Atom),
+  /// A special atom with its `#`
+  Special(String),
+  Colon,
+  Open,
+  Close,
+  End,
+}
+
+// A token of the parser: strings are already joined and unescaped, and
+// whitespace is only remembered in `spaced`, and a comment block on the
+// lines right before a lexeme in `doc`. Lexemes start with a token of the
+// `init` group, so lexing can restart at their start.
+#[derive(Clone, Debug)]
+pub(crate) struct Lexeme {
+  pub(crate) kind:   Kind,
+  pub(crate) span:   Span,
+  pub(crate) spaced: bool,
+  pub(crate) doc:    Option<String>,
+}
+
+// The lexemes of a text, the last one is `Kind::End`
+pub(crate) struct Lexemes<'t> {
+  text: &'t str,
+  offset: usize,
+  tokens: TokenIterator<'t, State>,
+  spaced: bool,
+  string: Option<(usize, String)>,
+  escape_start: usize,
+  done: bool,
+  pub(crate) errors: Vec<ParseError>,
+  // Further errors are dropped
+  pub(crate) max_errors: usize,
+  lines: Option<LineIndex<'t>>,
+  // The comment block so far, and the line breaks since its last line
+  pub(crate) doc: Option<String>,
+  breaks: usize,
+}
+
+impl<'t> Lexemes<'t> {
+  // Start at `offset`, which must be the start of a lexeme or the text
+  pub(crate) fn new(text: &'t str, offset: usize, spaced: bool) -> Self {
+    Lexemes {
+      text,
+      offset,
+      tokens: lex::tokens(&text.as_bytes()[offset..]),
+      spaced,
+      string: None,
+      escape_start: 0,
+      done: false,
+      errors: vec![],
+      max_errors: usize::MAX,
+      lines: None,
+      doc: None,
+      breaks: 1,
+    }
+  }
+
+  fn error(&mut self, kind: ErrorKind, span: Span) {
+    if self.errors.len() >= self.max_errors {
+      return;
+    }
+    let lines = self.lines.get_or_insert_with(|| LineIndex::new(self.text));
+    let position = lines.position(span.start);
+    self.errors.push(ParseError { kind, span, position });
+  }
+
+  fn lexeme(&mut self, kind: Kind, span: Span) -> Option<Lexeme> {
+    let spaced = std::mem::replace(&mut self.spaced, false);
+    // A blank line separates a comment block from the lexeme
+    let doc = self.doc.take().filter(|_| self.breaks < 2);
+    self.breaks = 0;
+    Some(Lexeme { kind, span, spaced, doc })
+  }
+
+  // Only comments on their own lines form a block, a blank line starts a
+  // new one
+  fn comment(&mut self, comment: &str) {
+    if self.breaks == 0 {
+      self.doc = None;
+    } else {
+      if self.breaks > 1 {
+        self.doc = None;
+      }
+      let line = comment.trim_end_matches(['\n', '\r']);
+      let line = line.strip_prefix('#').unwrap_or(line);
+      let line = line.strip_prefix(' ').unwrap_or(line);
+      match &mut self.doc {
+        Some(doc) => {
+          doc.push('\n');
+          doc.push_str(line);
+        }
+        None => self.doc = Some(line.to_owned()),
+      }
+    }
+    self.breaks = 1;
+  }
+
+  // the rule ids generated by `axlex` are lowercase
+  #[allow(non_upper_case_globals)]
+  fn token(&mut self, token: Token) -> Option<Lexeme> {
+    let end = self.offset + token.index;
+    let span = Span::new(end - token.data.len(), end);
+    let data = String::from_utf8_lossy(&token.data);
+
+    match token.rule_id {
+      R_ID_ws => {
+        self.spaced = true;
+        self.breaks += data.matches('\n').count();
+      }
+      R_ID_comment => {
+        self.spaced = true;
+        self.comment(&data);
+      }
+      R_ID_bare => return self.lexeme(Kind::Atom(data.into_owned()), span),
+      R_ID_special_atom => {
+        return self.lexeme(Kind::Special(data.into_owned()), span)
+      }
+      R_ID_colon => return self.lexeme(Kind::Colon, span),
+      R_ID_open_paren => return self.lexeme(Kind::Open, span),
+      R_ID_close_paren => return self.lexeme(Kind::Close, span),
+      R_ID_start_string | R_ID_start_gd_string => {
+        self.string = Some((span.start, String::new()))
+      }
+      R_ID_string | R_ID_gd_string | R_ID_gd_quote => {
+        if let Some((_, s)) = &mut self.string {
+          s.push_str(&data);
+        }
+      }
+      R_ID_start_esc => self.escape_start = span.start,
+      R_ID_simple_esc | R_ID_x_esc | R_ID_u_esc => match unescape(&data) {
+        Some(c) => {
+          if let Some((_, s)) = &mut self.string {
+            s.push(c);
+          }
+        }
+        None => {
+          let span = Span::new(self.escape_start, span.end);
+          self.error(ErrorKind::InvalidEscape(data.into_owned()), span);
+        }
+      },
+      R_ID_invalid_x_esc | R_ID_invalid_u_esc | R_ID_invalid_esc => {
+        let span = Span::new(self.escape_start, span.end);
+        self.error(ErrorKind::InvalidEscape(data.into_owned()), span);
+      }
+      R_ID_end_string | R_ID_end_gd_string => {
+        if let Some((start, s)) = self.string.take() {
+          return self.lexeme(Kind::Atom(s), Span::new(start, span.end));
+        }
+      }
+      R_ID_unterminated_string | R_ID_UNEXPECTED_END => {
+        let lexeme = self.string.take().and_then(|(start, s)| {
+          let span = Span::new(start, span.start);
+          self.error(ErrorKind::UnterminatedString, span);
+          self.lexeme(Kind::Atom(s), span)
+        });
+        self.spaced = true;
+        return lexeme;
+      }
+      _ => {
+        // Invalid tokens count as atoms so that the structure survives
+        let kind = ErrorKind::InvalidToken(data.clone().into_owned());
+        self.error(kind, span);
+        if self.string.is_none() {
+          return self.lexeme(Kind::Atom(data.into_owned()), span);
+        }
+      }
+    }
+    None
+  }
+}
+
+impl Iterator for Lexemes<'_> {
+  type Item = Lexeme;
+
+  fn next(&mut self) -> Option<Lexeme> {
+    if self.done {
+      return None;
+    }
+    while let Some(token) = self.tokens.next() {
+      if let Some(lexeme) = self.token(token) {
+        return Some(lexeme);
+      }
+    }
+    self.done = true;
+    let end = Span::new(self.text.len(), self.text.len());
+    self.lexeme(Kind::End, end)
+  }
+}
+
+// The parser pulls lexemes as it goes, so that it stops lexing at an
+// exceeded limit. `I` yields lexemes or references to them.
+pub(crate) struct Parser<'p, I: Iterator> {
+  text:    &'p str,
+  lexemes: I,
+  // The lexemes peeked at, and the end after the last one
+  ahead:   VecDeque<I::Item>,
+  end:     Lexeme,
+  // The number of lexemes consumed, and the end of the last one
+  pos:     usize,
+  last:    usize,
+  errors:  Vec<ParseError>,
+  lines:   Option<LineIndex<'p>>,
+  reuse:   Option<&'p mut Reuse>,
+  limits:  &'p ParseLimits,
+  depth:   usize,
+  nodes:   usize,
+  limited: bool,
+}
+
+impl<'p, I> Parser<'p, I>
+where
+  I: Iterator,
+  I::Item: Borrow<Lexeme>,
+{
+  // Parse `lexemes`, taking unchanged subtrees from `reuse`
+  pub(crate) fn new(
+    text: &'p str,
+    lexemes: I,
+    limits: &'p ParseLimits,
+    reuse: Option<&'p mut Reuse>,
+  ) -> Parser<'p, I> {
+    let end = Lexeme {
+      kind:   Kind::End,
+      span:   Span::new(text.len(), text.len()),
+      spaced: true,
+      doc:    None,
+    };
+    Parser {
+      text,
+      lexemes,
+      ahead: VecDeque::new(),
+      end,
+      pos: 0,
+      last: 0,
+      errors: vec![],
+      lines: None,
+      reuse,
+      limits,
+      depth: 0,
+      nodes: 0,
+      limited: false,
+    }
+  }
+
+  pub(crate) fn root(mut self) -> (Node, Vec<ParseError>) {
+    let entries = self.entries(true);
+    let span = Span::new(0, self.text.len());
+    (Node { span, kind: NodeKind::Document(entries) }, self.errors)
+  }
+
+  // A single value, for a text which ends with the value
+  pub(crate) fn value_only(mut self) -> (Option<Node>, Vec<ParseError>) {
+    let node = self.value();
+    (node, self.errors)
+  }
+
+  fn error(&mut self, kind: ErrorKind, span: Span) {
+    if self.limited || self.errors.len() >= self.limits.max_errors {
+      return;
+    }
+    let lines = self.lines.get_or_insert_with(|| LineIndex::new(self.text));
+    let position = lines.position(span.start);
+    self.errors.push(ParseError { kind, span, position });
+  }
+
+  // Report an exceeded limit and stop parsing by skipping to the end
+  fn limit(&mut self, limit: Limit, max: usize, span: Span) {
+    self.error(ErrorKind::LimitExceeded { limit, max }, span);
+    self.limited = true;
+    self.ahead.clear();
+  }
+
+  fn check_atom(&mut self, atom: &str, span: Span) {
+    if atom.len() > self.limits.max_atom_len {
+      self.limit(Limit::AtomLength, self.limits.max_atom_len, span);
+    }
+  }
+
+  fn count_nodes(&mut self, nodes: usize, span: Span) {
+    self.nodes += nodes;
+    if self.nodes > self.limits.max_nodes {
+      self.limit(Limit::Nodes, self.limits.max_nodes, span);
+    }
+  }
+
+  // Whether another entry or element exceeds the limit
+  fn check_entries(&mut self, len: usize, span: Span) -> bool {
+    let exceeded = len >= self.limits.max_entries;
+    if exceeded {
+      self.limit(Limit::Entries, self.limits.max_entries, span);
+    }
+    exceeded
+  }
+
+  fn peek(&mut self) -> &Lexeme { self.peek_at(0) }
+
+  // The lexeme `ahead` of the next one, the end after the last one or
+  // after an exceeded limit
+  fn peek_at(&mut self, ahead: usize) -> &Lexeme {
+    while !self.limited && self.ahead.len() <= ahead {
+      match self.lexemes.next() {
+        Some(lexeme) => self.ahead.push_back(lexeme),
+        None => break,
+      }
+    }
+    match self.ahead.get(ahead) {
+      Some(lexeme) => lexeme.borrow(),
+      None => &self.end,
+    }
+  }
+
+  fn bump(&mut self) {
+    if self.peek().kind == Kind::End {
+      return;
+    }
+    if let Some(lexeme) = self.ahead.pop_front() {
+      self.last = lexeme.borrow().span.end;
+      self.pos += 1;
+    }
+  }
+
+  fn is_entry_start(&mut self) -> bool {
+    matches!(self.peek().kind, Kind::Atom(_))
+      && self.peek_at(1).kind == Kind::Colon
+  }
+
+  // Entries until the end or a closing parenthesis, which is not consumed
+  // except at root where it is unmatched.
+  fn entries(&mut self, root: bool) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = vec![];
+    let mut keys = HashSet::new();
+    loop {
+      let lexeme = self.peek().clone();
+      match lexeme.kind {
+        Kind::End => break,
+        Kind::Close if root => {
+          self.error(ErrorKind::UnmatchedParen, lexeme.span);
+          self.bump();
+          continue;
+        }
+        Kind::Close => break,
+        _ => {}
+      }
+      if !entries.is_empty() && !lexeme.spaced {
+        self.error(ErrorKind::MissingWhitespace, lexeme.span);
+      }
+      if self.check_entries(entries.len(), lexeme.span) {
+        break;
+      }
+
+      let start = self.pos;
+      match self.entry() {
+        Some(entry) => {
+          if !keys.insert(entry.key.clone()) {
+            let kind = ErrorKind::DuplicateKey(entry.key.clone());
+            self.error(kind, entry.key_span);
+          }
+          entries.push(entry);
+        }
+        None => self.recover(start),
+      }
+    }
+    entries
+  }
+
+  fn entry(&mut self) -> Option<Entry> {
+    let lexeme = self.peek().clone();
+    let Kind::Atom(key) = lexeme.kind else {
+      let kind = match lexeme.kind {
+        Kind::End => ErrorKind::UnexpectedEnd,
+        _ => ErrorKind::ExpectedKey,
+      };
+      self.error(kind, lexeme.span);
+      return None;
+    };
+    self.bump();
+    self.check_atom(&key, lexeme.span);
+
+    let colon = self.peek().clone();
+    match colon.kind {
+      Kind::Colon => self.bump(),
+      Kind::End => {
+        self.error(ErrorKind::UnexpectedEnd, colon.span);
+        return None;
+      }
+      _ => {
+        self.error(ErrorKind::ExpectedColon, colon.span);
+        return None;
+      }
+    };
+
+    let value = self.value()?;
+    Some(Entry { key, key_span: lexeme.span, value, doc: lexeme.doc })
+  }
+
+  // Skip to the next entry, the end, or a closing parenthesis, skipping
+  // nested parentheses as a whole
+  fn recover(&mut self, start: usize) {
+    if self.pos == start && !matches!(self.peek().kind, Kind::End | Kind::Close)
+    {
+      self.bump();
+    }
+    loop {
+      if self.is_entry_start() {
+        return;
+      }
+      match self.peek().kind {
+        Kind::End | Kind::Close => return,
+        Kind::Open => self.skip_parens(),
+        _ => self.bump(),
+      }
+    }
+  }
+
+  fn skip_parens(&mut self) {
+    let mut depth = 0;
+    loop {
+      match self.peek().kind {
+        Kind::Open => depth += 1,
+        Kind::Close => depth -= 1,
+        Kind::End => return,
+        _ => {}
+      }
+      self.bump();
+      if depth == 0 {
+        return;
+      }
+    }
+  }
+
+  fn value(&mut self) -> Option<Node> {
+    let lexeme = self.peek().clone();
+    match lexeme.kind {
+      Kind::Atom(atom) => {
+        self.bump();
+        self.check_atom(&atom, lexeme.span);
+        self.count_nodes(1, lexeme.span);
+        Some(Node { span: lexeme.span, kind: NodeKind::Atom(atom) })
+      }
+      Kind::Special(atom) => {
+        self.bump();
+        self.count_nodes(1, lexeme.span);
+        let kind = special_atom(&atom).unwrap_or_else(|| {
+          // Unknown ones count as atoms, like invalid tokens
+          let kind = ErrorKind::UnknownSpecialAtom(atom.clone());
+          self.error(kind, lexeme.span);
+          NodeKind::Atom(atom)
+        });
+        Some(Node { span: lexeme.span, kind })
+      }
+      Kind::Open => {
+        let reuse = self.reuse.as_mut();
+        if let Some(node) = reuse.and_then(|r| r.take(lexeme.span.start)) {
+          // The subtree may now be nested deeper than before
+          let (depth, nodes) = measure(&node);
+          let nodes_left = self.limits.max_nodes.saturating_sub(self.nodes);
+          if self.depth + depth <= self.limits.max_depth && nodes <= nodes_left
+          {
+            self.nodes += nodes;
+            while self.peek().kind != Kind::End
+              && self.peek().span.start < node.span.end
+            {
+              self.bump();
+            }
+            return Some(node);
+          }
+        }
+        Some(self.parens())
+      }
+      Kind::End => {
+        self.error(ErrorKind::UnexpectedEnd, lexeme.span);
+        None
+      }
+      Kind::Colon | Kind::Close => {
+        self.error(ErrorKind::ExpectedValue, lexeme.span);
+        None
+      }
+    }
+  }
+
+  fn parens(&mut self) -> Node {
+    let open = self.peek().clone();
+    self.bump();
+    self.count_nodes(1, open.span);
+    if self.depth == self.limits.max_depth {
+      self.limit(Limit::Depth, self.limits.max_depth, open.span);
+    }
+
+    self.depth += 1;
+    let kind = if self.is_entry_start() {
+      NodeKind::Document(self.entries(false))
+    } else {
+      NodeKind::List(self.values())
+    };
+    self.depth -= 1;
+
+    let close = self.peek().clone();
+    let end = if close.kind == Kind::Close {
+      self.bump();
+      close.span.end
+    } else {
+      self.error(ErrorKind::UnclosedParen, open.span);
+      self.last.max(open.span.end)
+    };
+    Node { span: Span::new(open.span.start, end), kind }
+  }
+
+  fn values(&mut self) -> Vec<Node> {
+    let mut values = vec![];
+    loop {
+      let lexeme = self.peek().clone();
+      match lexeme.kind {
+        Kind::End | Kind::Close => break,
+        Kind::Colon => {
+          self.error(ErrorKind::ExpectedValue, lexeme.span);
+          self.bump();
+          continue;
+        }
+        _ => {}
+      }
+      if !values.is_empty() && !lexeme.spaced {
+        self.error(ErrorKind::MissingWhitespace, lexeme.span);
+      }
+      if self.check_entries(values.len(), lexeme.span) {
+        break;
+      }
+      values.extend(self.value());
+    }
+    values
+  }
+}
+
+// The value of a special atom. Only `#nil` is defined, the other names
+// are reserved for future use.
+fn special_atom(atom: &str) -> Option<NodeKind> {
+  match atom {
+    "#nil" => Some(NodeKind::Nil),
+    _ => None,
+  }
+}
+
+// The nesting depth and number of values of a subtree
+fn measure(node: &Node) -> (usize, usize) {
+  let children: Vec<_> = match &node.kind {
+    NodeKind::List(list) => list.iter().map(measure).collect(),
+    NodeKind::Document(entries) => {
+      entries.iter().map(|entry| measure(&entry.value)).collect()
+    }
+    _ => return (0, 1),
+  };
+  let depth = children.iter().map(|(depth, _)| *depth).max().unwrap_or(0);
+  (depth + 1, children.iter().map(|(_, nodes)| nodes).sum::<usize>() + 1)
+}
+
+// Unescape the part of an escape after the backslash
+pub(crate) fn unescape(esc: &str) -> Option<char> {
+  let hex = |s: &str| u32::from_str_radix(s, 16).ok().and_then(char::from_u32);
+  match esc {
+    "\"" => Some('"'),
+    "\\" => Some('\\'),
+    "e" => Some('\x1b'),
+    "n" => Some('\n'),
+    "r" => Some('\r'),
+    "t" => Some('\t'),
+    "0" => Some('\0'),
+    _ if esc.starts_with("x") => hex(&esc[1..]),
+    _ if esc.starts_with("u{") => hex(&esc[2..esc.len() - 1]),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::value::format_atom;
+
+  fn errors(text: &str) -> Vec<String> {
+    parse_tree(text).errors.iter().map(|err| err.to_string()).collect()
+  }
+
+  #[test]
+  fn test_parse() {
+    let ok = |text| parse(text).unwrap().to_string();
+    assert_eq!(ok(""), "()");
+    assert_eq!(ok("a:1"), "(a: 1)");
+    assert_eq!(ok("a: ()"), "(a: ())");
+    assert_eq!(ok(r#""": ()"#), r#"("": ())"#);
+    assert_eq!(ok("💩: 0"), "(💩: 0)");
+    assert_eq!(ok("\"key\" : \"value\""), "(key: value)");
+    assert_eq!(ok("a: (b: (c d) e: ())"), "(a: (b: (c d) e: ()))");
+    assert_eq!(ok("# comment\na: b # trailing\n#\n"), "(a: b)");
+    assert_eq!(
+      ok("a: #nil b: (#nil \"#nil\")"),
+      r##"(a: #nil b: (#nil "#nil"))"##
+    );
+  }
+
+  #[test]
+  fn test_strings() {
+    let atom = |text: &str| match parse(text).unwrap() {
+      Value::Document(document) => document["a"].clone(),
+      _ => unreachable!(),
+    };
+    assert_eq!(
+      atom(r#"a: "x\ty\"\\\x41\u{1f4a9}""#),
+      Value::Atom("x\ty\"\\A💩".into())
+    );
+    assert_eq!(
+      atom(r###"a: #"He said: "Hello!""#"###),
+      Value::Atom(r#"He said: "Hello!""#.into())
+    );
+    assert_eq!(atom("a: #1\"x\"#\"1#"), Value::Atom("x\"#".into()));
+    assert_eq!(atom("a: #\"two\nlines\"#"), Value::Atom("two\nlines".into()));
+  }
+
+  #[test]
+  fn test_errors() {
+    assert_eq!(errors(":"), ["expected key at 1:1"]);
+    assert_eq!(errors("a"), ["unexpected end of text at 1:2"]);
+    assert_eq!(errors("a("), ["expected colon after key at 1:2"]);
+    assert_eq!(errors(r"\0: 0"), [r"invalid token `\0` at 1:1"]);
+    assert_eq!(errors("a:(a())"), ["missing whitespace at 1:5"]);
+    assert_eq!(errors("a:(a ("), [
+      "unclosed parenthesis at 1:3",
+      "unclosed parenthesis at 1:6"
+    ]);
+    assert_eq!(errors("a: b)"), ["unmatched closing parenthesis at 1:5"]);
+    assert_eq!(errors("a: 1 a: 2"), ["duplicate key `a` at 1:6"]);
+    assert_eq!(errors("a: #none"), ["unknown special atom `#none` at 1:4"]);
+    assert_eq!(errors("#nil: 1"), ["expected key at 1:1"]);
+    assert_eq!(errors("a: \"x\ny: z"), ["unterminated string at 1:4"]);
+    assert_eq!(errors(r#"a: "\q\u{d800}""#), [
+      r"invalid escape `\q` at 1:5",
+      r"invalid escape `\u{d800}` at 1:7"
+    ]);
+  }
+
+  #[test]
+  fn test_recovery() {
+    let parsed = parse_tree("a: 1 b c: 2 d: (x: ) e: (1 2 :) f: 3");
+    assert_eq!(
+      parsed.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+      [
+        "expected colon after key at 1:8",
+        "expected value at 1:20",
+        "expected value at 1:30",
+      ]
+    );
+    assert_eq!(
+      parsed.root.to_value().to_string(),
+      "(a: 1 c: 2 d: () e: (1 2) f: 3)"
+    );
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let atoms =
+      ["", "a b", "\u{0}\u{1b}\n\r\t\"\\", "#", "(:)", "\u{a0}\u{10ffff}"];
+    for atom in atoms.into_iter().chain(["#nil"]) {
+      let text = format!("a: {}", format_atom(atom));
+      let value = parse(&text).unwrap();
+      assert_eq!(
+        value.to_string(),
+        format!("(a: {})", format_atom(atom)),
+        "{text}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_nil_round_trip() {
+    let value = parse("a: #nil b: (x #nil (c: #nil))").unwrap();
+    assert_eq!(parse(&crate::format::pretty(&value)).unwrap(), value);
+    let Value::Document(document) = &value else { unreachable!() };
+    assert_eq!(document["a"], Value::Nil);
+  }
+
+  #[test]
+  fn test_docs() {
+    let text = "\
+# Server
+#
+#  settings
+server: (
+  # not a doc comment
+
+  port: 80 # trailing
+  host: a
+  # of tls
+  tls: #nil
+)
+list: (x (
+  ## key
+  key: y))
+";
+    let root = parse_tree(text).root;
+    let doc = |path: &str| {
+      let entry = root.entry_at_path(&path.parse().unwrap()).unwrap();
+      entry.doc().map(str::to_owned)
+    };
+    assert_eq!(doc("server").as_deref(), Some("Server\n\n settings"));
+    assert_eq!(doc("server.port"), None);
+    assert_eq!(doc("server.host"), None);
+    assert_eq!(doc("server.tls").as_deref(), Some("of tls"));
+    assert_eq!(doc("list"), None);
+    assert_eq!(doc("list.1.key").as_deref(), Some("# key"));
+
+    let value = root.to_value();
+    let options = crate::format::Options::default();
+    let pretty =
+      crate::format::pretty_with_docs(&value, &options, &root.docs());
+    assert_eq!(parse_tree(&pretty).root.docs(), root.docs());
+  }
+
+  #[test]
+  fn test_limits() {
+    let limits = ParseLimits {
+      max_depth:     3,
+      max_input_len: 40,
+      max_atom_len:  4,
+      max_entries:   3,
+      max_nodes:     8,
+      max_errors:    2,
+    };
+    let error = |text: &str| {
+      let errors = parse_tree_with(text, &limits).errors;
+      errors.iter().map(|err| err.to_string()).collect::<Vec<_>>()
+    };
+
+    assert_eq!(error("a: ((((b))))"), ["nesting depth exceeds 3 at 1:7"]);
+    assert_eq!(error(&"a: b ".repeat(9)), ["input length exceeds 40 at 1:41"]);
+    assert_eq!(error("abcde: b"), ["atom length exceeds 4 at 1:1"]);
+    assert_eq!(error("a: \"abcde\""), ["atom length exceeds 4 at 1:4"]);
+    assert_eq!(error("a: (1 2 3 4)"), ["number of entries exceeds 3 at 1:11"]);
+    assert_eq!(error("a: 1 b: 2 c: 3 d: 4"), [
+      "number of entries exceeds 3 at 1:16"
+    ]);
+    assert_eq!(error("a: (1 2) b: (3 4) c: (5 6)"), [
+      "number of values exceeds 8 at 1:25"
+    ]);
+
+    // errors before the limit are kept, errors after it are not reported
+    assert_eq!(error("a: \\ b: ((((c \\))))"), [
+      r"invalid token `\` at 1:4",
+      "nesting depth exceeds 3 at 1:12"
+    ]);
+    assert_eq!(error("a: \\ b: \\ c: \\"), [
+      r"invalid token `\` at 1:4",
+      r"invalid token `\` at 1:9"
+    ]);
+    let deep = format!("a: {}", "(".repeat(100_000));
+    let err = parse(&deep).unwrap_err();
+    assert_eq!(err.to_string(), "nesting depth exceeds 128 at 1:132");
+
+    // lexing stops at an exceeded limit
+    let long = format!("x: ({})", "a ".repeat(100_000));
+    let mut lexer = Lexemes::new(&long, 0, true);
+    let mut count = 0;
+    let lexemes = lexer.by_ref().inspect(|_| count += 1);
+    let (_, errors) = Parser::new(&long, lexemes, &limits, None).root();
+    assert_eq!(errors[0].to_string(), "number of entries exceeds 3 at 1:11");
+    assert!(count < 10);
+    assert!(lexer.next().is_some());
+  }
+
+  #[test]
+  fn test_many_errors() {
+    // positions of errors on one long line are not counted from its start
+    let text = format!("a: \"{}\"", r"\q".repeat(160_000));
+    let errors = parse_tree(&text).errors;
+    assert_eq!(errors.len(), 100);
+    assert_eq!(errors[99].position, Position { line: 1, column: 203 });
+
+    let text = "a: b ".repeat(1 << 16);
+    let errors = parse_tree(&text).errors;
+    assert_eq!(errors.len(), 100);
+    assert_eq!(errors[0].to_string(), "duplicate key `a` at 1:6");
+  }
+}
@@ -0,0 +1,507 @@
+//! Patches: changes to atto values addressed by key paths
+//!
+//! Like JSON Patch a patch is a sequence of operations. In atto it is
+//! written as a list of documents, for example:
+//!
+//! ```text
+//! (
+//!   (op: add path: members.0 value: (name: Zed))
+//!   (op: remove path: debug)
+//!   (op: replace path: version value: 2)
+//!   (op: move from: old.name path: name)
+//!   (op: test path: name value: Zed)
+//! )
+//! ```
+//!
+//! `add` inserts into a list at the index, or appends if the last segment
+//! is `-`, and sets a document entry. `remove` and `replace` require the
+//! target to exist. `move` removes the value at `from` and adds it at
+//! `path`, and `test` compares the target with the value.
+//!
+//! `remove` and `replace` may have an `old` value as in the output of
+//! [`Diff::to_value()`](crate::diff::Diff::to_value), which is read as a
+//! `test` of the target before the operation. A diff is converted to a
+//! patch with [`Diff::to_patch()`](crate::diff::Diff::to_patch).
+
+use crate::path::{Path, Segment};
+use crate::value::{Document, Value};
+use std::fmt;
+
+/// A single patch operation
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+  Add { path: Path, value: Value },
+  Remove { path: Path },
+  Replace { path: Path, value: Value },
+  Move { from: Path, path: Path },
+  Test { path: Path, value: Value },
+}
+
+/// A sequence of operations applied in order
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Patch {
+  pub operations: Vec<Operation>,
+}
+
+/// Why an operation failed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reason {
+  /// Nothing found at the path
+  NotFound(Path),
+
+  /// The value at the path is neither a list nor a document
+  NotAContainer(Path),
+
+  /// A list index is not valid for adding
+  IndexOutOfBounds { path: Path, len: usize },
+
+  /// Root cannot be removed or moved
+  Root,
+
+  /// Move into a descendant of the moved value
+  MoveIntoItself,
+
+  /// The tested value differs
+  TestFailed { expected: Box<Value>, actual: Box<Value> },
+}
+
+impl fmt::Display for Reason {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Reason::NotFound(path) => write!(f, "nothing found at {path}"),
+      Reason::NotAContainer(path) => {
+        write!(f, "value at {path} is neither a list nor a document")
+      }
+      Reason::IndexOutOfBounds { path, len } => {
+        write!(f, "index {path} out of bounds for list of length {len}")
+      }
+      Reason::Root => write!(f, "root cannot be removed or moved"),
+      Reason::MoveIntoItself => write!(f, "cannot move a value into itself"),
+      Reason::TestFailed { expected, actual } => {
+        write!(f, "expected {expected} but found {actual}")
+      }
+    }
+  }
+}
+
+/// A failed operation of a patch, see [`apply()`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PatchError {
+  /// Index of the operation in the patch
+  pub index:     usize,
+  pub operation: Box<Operation>,
+  pub reason:    Reason,
+}
+
+impl fmt::Display for PatchError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let index = self.index;
+    let op = self.operation.name();
+    write!(f, "patch operation {index} ({op}) failed: {}", self.reason)
+  }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Apply a patch atomically: either all operations succeed or `value` is
+/// left unchanged.
+///
+/// ```
+/// # use atto::{patch::{apply, Operation, Patch}, Value};
+/// let mut value = Value::List(vec![Value::Atom("b".to_owned())]);
+/// let path = "0".parse().unwrap();
+/// let value_a = Value::Atom("a".to_owned());
+/// let patch =
+///   Patch { operations: vec![Operation::Add { path, value: value_a }] };
+///
+/// apply(&mut value, &patch).unwrap();
+/// assert_eq!(value.to_string(), "(a b)");
+/// ```
+pub fn apply(value: &mut Value, patch: &Patch) -> Result<(), PatchError> {
+  let mut patched = value.clone();
+  for (index, operation) in patch.operations.iter().enumerate() {
+    if let Err(reason) = apply_operation(&mut patched, operation) {
+      let operation = Box::new(operation.clone());
+      return Err(PatchError { index, operation, reason });
+    }
+  }
+  *value = patched;
+  Ok(())
+}
+
+pub(crate) fn apply_operation(
+  value: &mut Value,
+  operation: &Operation,
+) -> Result<(), Reason> {
+  match operation {
+    Operation::Add { path, value: new } => add(value, path, new.clone()),
+    Operation::Remove { path } => remove(value, path).map(|_| ()),
+    Operation::Replace { path, value: new } => {
+      let target = value.get_path_mut(path);
+      *target.ok_or_else(|| Reason::NotFound(path.clone()))? = new.clone();
+      Ok(())
+    }
+    Operation::Move { from, path } => {
+      if path.starts_with(from) && path != from {
+        return Err(Reason::MoveIntoItself);
+      }
+      let moved = remove(value, from)?;
+      add(value, path, moved)
+    }
+    Operation::Test { path, value: expected } => {
+      let actual = value.get_path(path);
+      match actual.ok_or_else(|| Reason::NotFound(path.clone()))? {
+        actual if actual == expected => Ok(()),
+        actual => Err(Reason::TestFailed {
+          expected: Box::new(expected.clone()),
+          actual:   Box::new(actual.clone()),
+        }),
+      }
+    }
+  }
+}
+
+// The container holding the target of `path` and the last segment
+fn parent<'v>(
+  value: &'v mut Value,
+  path: &Path,
+) -> Result<(&'v mut Value, Segment), Reason> {
+  let parent_path = path.parent().ok_or(Reason::Root)?;
+  let last = path.last().cloned().ok_or(Reason::Root)?;
+  let parent = value.get_path_mut(&parent_path);
+  let parent = parent.ok_or_else(|| Reason::NotFound(parent_path.clone()))?;
+  Ok((parent, last))
+}
+
+fn add(value: &mut Value, path: &Path, new: Value) -> Result<(), Reason> {
+  if path.is_root() {
+    *value = new;
+    return Ok(());
+  }
+
+  let (parent, last) = parent(value, path)?;
+  match (parent, last) {
+    (Value::List(list), Segment::Key(key)) if key == "-" => list.push(new),
+    (Value::List(list), Segment::Index(index)) if index <= list.len() => {
+      list.insert(index, new)
+    }
+    (Value::List(list), Segment::Index(_)) => {
+      let len = list.len();
+      return Err(Reason::IndexOutOfBounds { path: path.clone(), len });
+    }
+    (Value::Document(document), segment) => {
+      document.insert(key_of(segment), new);
+    }
+    _ => return Err(Reason::NotAContainer(path.parent().unwrap_or_default())),
+  }
+  Ok(())
+}
+
+fn remove(value: &mut Value, path: &Path) -> Result<Value, Reason> {
+  let (parent, last) = parent(value, path)?;
+  let removed = match (parent, last) {
+    (Value::List(list), Segment::Index(index)) if index < list.len() => {
+      Some(list.remove(index))
+    }
+    (Value::Document(document), segment) => {
+      document.shift_remove(&key_of(segment))
+    }
+    _ => None,
+  };
+  removed.ok_or_else(|| Reason::NotFound(path.clone()))
+}
+
+fn key_of(segment: Segment) -> String {
+  match segment {
+    Segment::Key(key) => key,
+    Segment::Index(index) => index.to_string(),
+  }
+}
+
+impl Operation {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Operation::Add { .. } => "add",
+      Operation::Remove { .. } => "remove",
+      Operation::Replace { .. } => "replace",
+      Operation::Move { .. } => "move",
+      Operation::Test { .. } => "test",
+    }
+  }
+
+  /// The operation as atto document.
+  pub fn to_value(&self) -> Value {
+    let mut document = Document::new();
+    let atom = |s: String| Value::Atom(s);
+    document.insert("op".to_owned(), atom(self.name().to_owned()));
+    match self {
+      Operation::Move { from, path } => {
+        document.insert("from".to_owned(), atom(from.to_string()));
+        document.insert("path".to_owned(), atom(path.to_string()));
+      }
+      Operation::Remove { path } => {
+        document.insert("path".to_owned(), atom(path.to_string()));
+      }
+      Operation::Add { path, value }
+      | Operation::Replace { path, value }
+      | Operation::Test { path, value } => {
+        document.insert("path".to_owned(), atom(path.to_string()));
+        document.insert("value".to_owned(), value.clone());
+      }
+    }
+    Value::Document(document)
+  }
+}
+
+impl Patch {
+  /// The patch as atto list of operation documents.
+  pub fn to_value(&self) -> Value {
+    Value::List(self.operations.iter().map(Operation::to_value).collect())
+  }
+}
+
+/// An atto value which is not a valid patch
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidPatch {
+  /// Index of the invalid operation, `None` if the patch is not a list
+  pub index:   Option<usize>,
+  pub message: String,
+}
+
+impl fmt::Display for InvalidPatch {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.index {
+      Some(index) => write!(f, "invalid patch operation {index}: "),
+      None => write!(f, "invalid patch: "),
+    }?;
+    f.write_str(&self.message)
+  }
+}
+
+impl std::error::Error for InvalidPatch {}
+
+impl TryFrom<&Value> for Patch {
+  type Error = InvalidPatch;
+
+  /// Read a patch from its atto form.
+  ///
+  /// ```
+  /// # use atto::{patch::{Operation, Patch}, value::Document, Value};
+  /// let mut op = Document::new();
+  /// op.insert("op".to_owned(), Value::Atom("remove".to_owned()));
+  /// op.insert("path".to_owned(), Value::Atom("a.0".to_owned()));
+  /// let value = Value::List(vec![Value::Document(op)]);
+  ///
+  /// let patch = Patch::try_from(&value).unwrap();
+  /// let path = "a.0".parse().unwrap();
+  /// assert_eq!(patch.operations, vec![Operation::Remove { path }]);
+  /// assert_eq!(patch.to_value(), value);
+  /// ```
+  fn try_from(value: &Value) -> Result<Self, Self::Error> {
+    let Value::List(list) = value else {
+      let message = "expected a list of operations".to_owned();
+      return Err(InvalidPatch { index: None, message });
+    };
+
+    let mut operations = vec![];
+    for (index, value) in list.iter().enumerate() {
+      let ops = operations_of(value);
+      let ops =
+        ops.map_err(|message| InvalidPatch { index: Some(index), message })?;
+      operations.extend(ops);
+    }
+    Ok(Patch { operations })
+  }
+}
+
+// The operation, after a test of the `old` value if there is one
+fn operations_of(value: &Value) -> Result<Vec<Operation>, String> {
+  let Value::Document(document) = value else {
+    return Err("expected a document".to_owned());
+  };
+
+  let atom = |key: &str| match document.get(key) {
+    Some(Value::Atom(atom)) => Ok(atom.as_str()),
+    Some(_) => Err(format!("`{key}` must be an atom")),
+    None => Err(format!("`{key}` missing")),
+  };
+  let path = |key: &str| {
+    let path = atom(key)?.parse::<Path>();
+    path.map_err(|err| format!("`{key}` is not a path: {err}"))
+  };
+  let value = || {
+    let value = document.get("value").cloned();
+    value.ok_or_else(|| "`value` missing".to_owned())
+  };
+
+  let op = atom("op")?;
+  let expected: &[&str] = match op {
+    "add" | "test" => &["op", "path", "value"],
+    "replace" => &["op", "path", "value", "old"],
+    "remove" => &["op", "path", "old"],
+    "move" => &["op", "from", "path"],
+    _ => return Err(format!("unknown op `{op}`")),
+  };
+  if let Some(key) = document.keys().find(|k| !expected.contains(&k.as_str())) {
+    return Err(format!("unexpected key `{key}` for op `{op}`"));
+  }
+
+  let operation = match op {
+    "add" => Operation::Add { path: path("path")?, value: value()? },
+    "replace" => Operation::Replace { path: path("path")?, value: value()? },
+    "test" => Operation::Test { path: path("path")?, value: value()? },
+    "remove" => Operation::Remove { path: path("path")? },
+    _ => Operation::Move { from: path("from")?, path: path("path")? },
+  };
+  Ok(match document.get("old") {
+    Some(old) => {
+      let test = Operation::Test { path: path("path")?, value: old.clone() };
+      vec![test, operation]
+    }
+    None => vec![operation],
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn atom(s: &str) -> Value { Value::Atom(s.to_owned()) }
+
+  fn path(s: &str) -> Path { s.parse().unwrap() }
+
+  fn doc(entries: &[(&str, Value)]) -> Value {
+    let entries = entries.iter().map(|(k, v)| (k.to_string(), v.clone()));
+    Value::Document(entries.collect())
+  }
+
+  fn sample() -> Value {
+    let powers = Value::List(vec![atom("fly")]);
+    doc(&[("name", atom("John")), ("powers", powers)])
+  }
+
+  #[test]
+  fn test_apply_operations() {
+    let mut value = sample();
+    let patch = Patch {
+      operations: vec![
+        Operation::Test { path: path("name"), value: atom("John") },
+        Operation::Add { path: path("powers.-"), value: atom("x ray") },
+        Operation::Add { path: path("powers.0"), value: atom("run") },
+        Operation::Replace { path: path("name"), value: atom("Jane") },
+        Operation::Move { from: path("name"), path: path("alias") },
+        Operation::Remove { path: path("powers.1") },
+      ],
+    };
+    apply(&mut value, &patch).unwrap();
+    assert_eq!(value.to_string(), r#"(powers: (run "x ray") alias: Jane)"#);
+  }
+
+  #[test]
+  fn test_apply_is_atomic() {
+    let mut value = sample();
+    let patch = Patch {
+      operations: vec![
+        Operation::Remove { path: path("name") },
+        Operation::Replace { path: path("powers.3"), value: atom("x") },
+      ],
+    };
+    let err = apply(&mut value, &patch).unwrap_err();
+    assert_eq!(err.index, 1);
+    assert_eq!(err.reason, Reason::NotFound(path("powers.3")));
+    assert_eq!(
+      err.to_string(),
+      "patch operation 1 (replace) failed: nothing found at powers.3"
+    );
+    assert_eq!(value, sample());
+  }
+
+  #[test]
+  fn test_apply_errors() {
+    let reason = |operation| {
+      let patch = Patch { operations: vec![operation] };
+      apply(&mut sample(), &patch).unwrap_err().reason
+    };
+
+    let expected = Box::new(atom("x"));
+    let expected = Reason::TestFailed { expected, actual: atom("John").into() };
+    assert_eq!(
+      reason(Operation::Test { path: path("name"), value: atom("x") }),
+      expected
+    );
+    assert_eq!(
+      reason(Operation::Add { path: path("powers.2"), value: atom("x") }),
+      Reason::IndexOutOfBounds { path: path("powers.2"), len: 1 }
+    );
+    assert_eq!(
+      reason(Operation::Add { path: path("name.x"), value: atom("x") }),
+      Reason::NotAContainer(path("name"))
+    );
+    assert_eq!(
+      reason(Operation::Move { from: path("powers"), path: path("powers.0") }),
+      Reason::MoveIntoItself
+    );
+    assert_eq!(reason(Operation::Remove { path: Path::root() }), Reason::Root);
+  }
+
+  #[test]
+  fn test_invalid_patch() {
+    let error = |value: &Value| Patch::try_from(value).unwrap_err().to_string();
+
+    assert_eq!(
+      error(&atom("x")),
+      "invalid patch: expected a list of operations"
+    );
+    let op = doc(&[("op", atom("copy"))]);
+    assert_eq!(
+      error(&Value::List(vec![op])),
+      "invalid patch operation 0: unknown op `copy`"
+    );
+    let op =
+      doc(&[("op", atom("remove")), ("path", atom("a")), ("x", atom("1"))]);
+    assert_eq!(
+      error(&Value::List(vec![op])),
+      "invalid patch operation 0: unexpected key `x` for op `remove`"
+    );
+    let op =
+      doc(&[("op", atom("add")), ("path", atom("a")), ("old", atom("1"))]);
+    assert_eq!(
+      error(&Value::List(vec![op])),
+      "invalid patch operation 0: unexpected key `old` for op `add`"
+    );
+    let op = doc(&[("op", atom("add")), ("path", atom("a..b"))]);
+    assert_eq!(
+      error(&Value::List(vec![op])),
+      "invalid patch operation 0: `path` is not a path: empty path segment at 2"
+    );
+  }
+
+  #[test]
+  fn test_old_values() {
+    let patch = Value::List(vec![
+      doc(&[
+        ("op", atom("remove")),
+        ("path", atom("name")),
+        ("old", atom("John")),
+      ]),
+      doc(&[
+        ("op", atom("replace")),
+        ("path", atom("powers.0")),
+        ("old", atom("fly")),
+        ("value", atom("run")),
+      ]),
+    ]);
+    let patch = Patch::try_from(&patch).unwrap();
+    assert_eq!(patch.operations, [
+      Operation::Test { path: path("name"), value: atom("John") },
+      Operation::Remove { path: path("name") },
+      Operation::Test { path: path("powers.0"), value: atom("fly") },
+      Operation::Replace { path: path("powers.0"), value: atom("run") },
+    ]);
+    let mut value = sample();
+    apply(&mut value, &patch).unwrap();
+    assert_eq!(value.to_string(), "(powers: (run))");
+    let err = apply(&mut value, &patch).unwrap_err();
+    assert_eq!(err.reason, Reason::NotFound(path("name")));
+  }
+}
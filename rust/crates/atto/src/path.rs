@@ -0,0 +1,309 @@
+//! Key paths addressing values inside documents and lists
+//!
+//! A path is written as its segments separated by dots, for example
+//! `members.2.powers`. Keys which are not bare, contain a dot or consist of
+//! digits only are quoted. The root path is written as a single dot.
+
+use crate::parser::unescape;
+use crate::value::{format_atom, Key, Value};
+use std::fmt;
+
+/// One step of a path: a key into a document or an index into a list
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Segment {
+  Key(Key),
+  Index(usize),
+}
+
+/// A sequence of segments leading from a value to one of its descendants
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Path(pub Vec<Segment>);
+
+impl Path {
+  pub fn root() -> Path { Path(vec![]) }
+
+  pub fn is_root(&self) -> bool { self.0.is_empty() }
+
+  pub fn segments(&self) -> &[Segment] { &self.0 }
+
+  pub fn push(&mut self, segment: Segment) { self.0.push(segment); }
+
+  pub fn pop(&mut self) -> Option<Segment> { self.0.pop() }
+
+  pub fn last(&self) -> Option<&Segment> { self.0.last() }
+
+  /// A new path with `segment` appended.
+  pub fn join(&self, segment: Segment) -> Path {
+    let mut path = self.clone();
+    path.push(segment);
+    path
+  }
+
+  /// The path without its last segment, `None` for the root path.
+  pub fn parent(&self) -> Option<Path> {
+    let (_, parent) = self.0.split_last()?;
+    Some(Path(parent.to_vec()))
+  }
+
+  pub fn starts_with(&self, other: &Path) -> bool {
+    self.0.starts_with(&other.0)
+  }
+}
+
+impl From<Vec<Segment>> for Path {
+  fn from(segments: Vec<Segment>) -> Self { Path(segments) }
+}
+
+impl From<&str> for Segment {
+  fn from(key: &str) -> Self { Segment::Key(key.to_owned()) }
+}
+
+impl From<usize> for Segment {
+  fn from(index: usize) -> Self { Segment::Index(index) }
+}
+
+impl fmt::Display for Segment {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Segment::Index(index) => write!(f, "{index}"),
+      Segment::Key(key) if needs_quotes(key) => {
+        let quoted = format_atom(key);
+        if quoted.starts_with('"') {
+          f.write_str(&quoted)
+        } else {
+          write!(f, "\"{quoted}\"")
+        }
+      }
+      Segment::Key(key) => f.write_str(key),
+    }
+  }
+}
+
+fn needs_quotes(key: &str) -> bool {
+  key.contains(['.', '"']) || key.bytes().all(|b| b.is_ascii_digit())
+}
+
+impl fmt::Display for Path {
+  /// Display a path with dots as separators.
+  ///
+  /// ```
+  /// # use atto::path::{Path, Segment};
+  /// let path = Path(vec!["members".into(), 2.into(), "a.b".into()]);
+  /// assert_eq!(path.to_string(), r#"members.2."a.b""#);
+  /// assert_eq!(Path::root().to_string(), ".");
+  /// ```
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_root() {
+      return f.write_str(".");
+    }
+    for (i, segment) in self.0.iter().enumerate() {
+      if i > 0 {
+        f.write_str(".")?;
+      }
+      write!(f, "{segment}")?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PathError {
+  EmptySegment(usize),
+  UnterminatedQuote(usize),
+  UnexpectedChar(usize),
+  InvalidEscape(usize),
+}
+
+impl fmt::Display for PathError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PathError::EmptySegment(at) => write!(f, "empty path segment at {at}"),
+      PathError::UnterminatedQuote(at) => {
+        write!(f, "unterminated quote in path at {at}")
+      }
+      PathError::UnexpectedChar(at) => {
+        write!(f, "unexpected character in path at {at}")
+      }
+      PathError::InvalidEscape(at) => {
+        write!(f, "invalid escape in path at {at}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for PathError {}
+
+impl std::str::FromStr for Path {
+  type Err = PathError;
+
+  /// Parse a path. Unquoted segments of digits only are indices.
+  ///
+  /// ```
+  /// # use atto::path::{Path, Segment};
+  /// let path = r#"a."b.c".0"#.parse::<Path>().unwrap();
+  /// assert_eq!(path, Path(vec!["a".into(), "b.c".into(), 0.into()]));
+  /// assert_eq!(".".parse::<Path>().unwrap(), Path::root());
+  /// ```
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s == "." {
+      return Ok(Path::root());
+    }
+
+    let mut segments = vec![];
+    let mut chars = s.char_indices().peekable();
+    loop {
+      let start = chars.peek().map_or(s.len(), |&(at, _)| at);
+      let segment = if let Some((_, '"')) = chars.peek() {
+        chars.next();
+        let mut key = String::new();
+        loop {
+          match chars.next() {
+            Some((_, '"')) => break,
+            Some((at, '\\')) => {
+              // The escape up to its last character, as in atom strings
+              let mut esc = String::new();
+              for (len, (_, c)) in (1..).zip(chars.by_ref()) {
+                esc.push(c);
+                let done = match esc.as_bytes()[0] {
+                  b'x' => len == 3,
+                  b'u' => c == '}' || len == 2 && c != '{',
+                  _ => true,
+                };
+                if done {
+                  break;
+                }
+              }
+              match esc.chars().next() {
+                None => return Err(PathError::UnterminatedQuote(start)),
+                Some('x' | 'u') => match unescape(&esc) {
+                  Some(c) => key.push(c),
+                  None => return Err(PathError::InvalidEscape(at)),
+                },
+                Some(c) => key.push(unescape(&esc).unwrap_or(c)),
+              }
+            }
+            Some((_, c)) => key.push(c),
+            None => return Err(PathError::UnterminatedQuote(start)),
+          }
+        }
+        Segment::Key(key)
+      } else {
+        let mut end = start;
+        while let Some(&(at, c)) = chars.peek() {
+          if c == '.' {
+            break;
+          }
+          end = at + c.len_utf8();
+          chars.next();
+        }
+        let text = &s[start..end];
+        if text.is_empty() {
+          return Err(PathError::EmptySegment(start));
+        }
+        match text.parse::<usize>() {
+          Ok(index) if text.bytes().all(|b| b.is_ascii_digit()) => {
+            Segment::Index(index)
+          }
+          _ => Segment::Key(text.to_owned()),
+        }
+      };
+      segments.push(segment);
+
+      match chars.next() {
+        None => return Ok(Path(segments)),
+        Some((_, '.')) => {}
+        Some((at, _)) => return Err(PathError::UnexpectedChar(at)),
+      }
+    }
+  }
+}
+
+impl Value {
+  /// The value at `path`, if there is one.
+  ///
+  /// An index segment also addresses a document entry with the index as
+  /// key, so that `a.0` finds `0` in `(a: (0: x))`.
+  pub fn get_path(&self, path: &Path) -> Option<&Value> {
+    path.0.iter().try_fold(self, |value, segment| value.get(segment))
+  }
+
+  /// The value at `path` for mutation, if there is one.
+  pub fn get_path_mut(&mut self, path: &Path) -> Option<&mut Value> {
+    path.0.iter().try_fold(self, |value, segment| value.get_mut(segment))
+  }
+
+  /// The child value addressed by one segment.
+  pub fn get(&self, segment: &Segment) -> Option<&Value> {
+    match (self, segment) {
+      (Value::List(list), Segment::Index(index)) => list.get(*index),
+      (Value::Document(document), Segment::Key(key)) => document.get(key),
+      (Value::Document(document), Segment::Index(index)) => {
+        document.get(&index.to_string())
+      }
+      _ => None,
+    }
+  }
+
+  /// The child value addressed by one segment for mutation.
+  pub fn get_mut(&mut self, segment: &Segment) -> Option<&mut Value> {
+    match (self, segment) {
+      (Value::List(list), Segment::Index(index)) => list.get_mut(*index),
+      (Value::Document(document), Segment::Key(key)) => document.get_mut(key),
+      (Value::Document(document), Segment::Index(index)) => {
+        document.get_mut(&index.to_string())
+      }
+      _ => None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_path_round_trip() {
+    let paths = [".", "a", "a.0.b", r#""0".1"#, r#""a.b".c"#, r#""x \" y\n""#];
+    for text in paths {
+      let path = text.parse::<Path>().unwrap();
+      assert_eq!(path.to_string(), text);
+    }
+    let path = Path(vec!["a.\u{1}".into(), "b".into()]);
+    assert_eq!(path.to_string(), r#""a.\u{001}".b"#);
+    assert_eq!(path.to_string().parse::<Path>(), Ok(path));
+    assert_eq!(
+      r#""\x41\u{1F4A9}\q""#.parse::<Path>().unwrap().to_string(),
+      "A💩q"
+    );
+  }
+
+  #[test]
+  fn test_path_errors() {
+    assert_eq!("a..b".parse::<Path>(), Err(PathError::EmptySegment(2)));
+    assert_eq!("".parse::<Path>(), Err(PathError::EmptySegment(0)));
+    assert_eq!(r#""a"#.parse::<Path>(), Err(PathError::UnterminatedQuote(0)));
+    assert_eq!(r#""a"b"#.parse::<Path>(), Err(PathError::UnexpectedChar(3)));
+    assert_eq!(r#""\x4""#.parse::<Path>(), Err(PathError::InvalidEscape(1)));
+    assert_eq!(
+      r#""a\u{d800}""#.parse::<Path>(),
+      Err(PathError::InvalidEscape(2))
+    );
+    assert_eq!(r#""\u""#.parse::<Path>(), Err(PathError::InvalidEscape(1)));
+  }
+
+  #[test]
+  fn test_get_path() {
+    let mut doc = crate::value::Document::new();
+    let list = Value::List(vec![Value::Atom("x".to_owned())]);
+    doc.insert("a".to_owned(), list);
+    let mut value = Value::Document(doc);
+
+    let path = "a.0".parse().unwrap();
+    assert_eq!(value.get_path(&path), Some(&Value::Atom("x".to_owned())));
+    assert_eq!(value.get_path(&"a.1".parse().unwrap()), None);
+    assert_eq!(value.get_path(&Path::root()), Some(&value.clone()));
+
+    *value.get_path_mut(&path).unwrap() = Value::Nil;
+    assert_eq!(value.get_path(&path), Some(&Value::Nil));
+  }
+}
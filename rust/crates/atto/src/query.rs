@@ -0,0 +1,849 @@
+//! Queries: a jq-like language to select and reshape values
+//!
+//! A query runs on a value and yields any number of values:
+//!
+//! - `.` is the value itself, `.name` and `."a b"` the entry of a document, or
+//!   nil if there is none, and `.[0]` an element of a list. Negative indices
+//!   count from the end.
+//! - `.[]` and `.*` yield all elements of a list or values of a document.
+//! - `..` yields the value and everything below it, `..name` all entries named
+//!   `name` at any depth.
+//! - `a | b` runs `b` on every result of `a`, `a, b` yields the results of
+//!   both.
+//! - `==`, `!=`, `<`, `<=`, `>` and `>=` compare atoms as numbers if both are
+//!   numbers, `and`, `or` and `not` combine conditions. Nil and `false` are
+//!   false, everything else is true.
+//! - `select(cond)` keeps the value if `cond` is true, `map(f)` runs `f` on all
+//!   elements, and `keys`, `length` and `type` describe the value.
+//! - `[...]` collects results into a list, `{name: f, (key): g, other}` builds
+//!   a document, where `other` is short for `other: .other`.
+//! - A `?` after an expression drops its errors.
+//!
+//! For example `.members[] | select(.age > 30) | {name, city: .address.city}`
+//! yields a document for every member older than 30.
+
+use crate::value::{Document, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum QueryError {
+  /// A character which starts no token, at a byte offset in the query
+  UnexpectedChar(usize),
+  UnterminatedString(usize),
+  UnexpectedToken(usize),
+  UnexpectedEnd,
+  UnknownFunction {
+    at:   usize,
+    name: String,
+  },
+  /// The operation does not apply to the type of a value while running
+  Type {
+    operation: &'static str,
+    found:     &'static str,
+  },
+}
+
+impl fmt::Display for QueryError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      QueryError::UnexpectedChar(at) => {
+        write!(f, "unexpected character in query at {at}")
+      }
+      QueryError::UnterminatedString(at) => {
+        write!(f, "unterminated string in query at {at}")
+      }
+      QueryError::UnexpectedToken(at) => {
+        write!(f, "unexpected token in query at {at}")
+      }
+      QueryError::UnexpectedEnd => write!(f, "unexpected end of query"),
+      QueryError::UnknownFunction { at, name } => {
+        write!(f, "unknown function `{name}` in query at {at}")
+      }
+      QueryError::Type { operation, found } => {
+        write!(f, "cannot {operation} {found}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for QueryError {}
+
+/// A parsed query
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+  expr: Expr,
+}
+
+impl std::str::FromStr for Query {
+  type Err = QueryError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser { tokens: tokens(s)?, next: 0 };
+    let expr = parser.pipe()?;
+    match parser.tokens.get(parser.next) {
+      None => Ok(Query { expr }),
+      Some(token) => Err(QueryError::UnexpectedToken(token.start)),
+    }
+  }
+}
+
+impl Query {
+  /// The results of the query on `value`.
+  ///
+  /// ```
+  /// # use atto::query::Query;
+  /// let value = atto::parse("members: ((name: a age: 31) (name: b age: 9))");
+  /// let query = ".members[] | select(.age > 30) | .name";
+  /// let results = query.parse::<Query>().unwrap().run(&value.unwrap());
+  ///
+  /// assert_eq!(results.unwrap(), [atto::Value::Atom("a".to_owned())]);
+  /// ```
+  pub fn run(&self, value: &Value) -> Result<Vec<Value>, QueryError> {
+    let mut results = vec![];
+    self.expr.eval(value, &mut results)?;
+    Ok(results)
+  }
+}
+
+/// Parse `query` and run it on `value`.
+pub fn query(query: &str, value: &Value) -> Result<Vec<Value>, QueryError> {
+  query.parse::<Query>()?.run(value)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Dot,
+  DotDot,
+  Name(String),
+  Number(String),
+  String(String),
+  Symbol(&'static str),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Spanned {
+  token: Token,
+  start: usize,
+  end:   usize,
+}
+
+const SYMBOLS: [&str; 16] = [
+  "==", "!=", "<=", ">=", "<", ">", "[", "]", "{", "}", "(", ")", "|", ",",
+  ":", "?",
+];
+
+fn tokens(text: &str) -> Result<Vec<Spanned>, QueryError> {
+  let mut tokens = vec![];
+  let mut at = 0;
+  while let Some(c) = text[at..].chars().next() {
+    let rest = &text[at..];
+    let start = at;
+    let token = if c.is_whitespace() {
+      at += c.len_utf8();
+      continue;
+    } else if rest.starts_with("..") {
+      at += 2;
+      Token::DotDot
+    } else if c == '.' {
+      at += 1;
+      Token::Dot
+    } else if c == '*' {
+      at += 1;
+      Token::Symbol("*")
+    } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+      at += symbol.len();
+      Token::Symbol(symbol)
+    } else if c == '"' {
+      let (string, len) =
+        string(rest).ok_or(QueryError::UnterminatedString(at))?;
+      at += len;
+      Token::String(string)
+    } else if c.is_ascii_digit()
+      || (c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+    {
+      let len = number(rest);
+      at += len;
+      Token::Number(rest[..len].to_owned())
+    } else if c.is_alphabetic() || c == '_' {
+      let len = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(rest.len());
+      at += len;
+      Token::Name(rest[..len].to_owned())
+    } else {
+      return Err(QueryError::UnexpectedChar(at));
+    };
+    tokens.push(Spanned { token, start, end: at });
+  }
+  Ok(tokens)
+}
+
+// The string at the start of `text` with its length, if it is terminated
+fn string(text: &str) -> Option<(String, usize)> {
+  let mut string = String::new();
+  let mut chars = text.char_indices().skip(1);
+  while let Some((at, c)) = chars.next() {
+    match c {
+      '"' => return Some((string, at + 1)),
+      '\\' => match chars.next()?.1 {
+        'e' => string.push('\x1b'),
+        'n' => string.push('\n'),
+        'r' => string.push('\r'),
+        't' => string.push('\t'),
+        '0' => string.push('\0'),
+        c => string.push(c),
+      },
+      c => string.push(c),
+    }
+  }
+  None
+}
+
+// The length of the number at the start of `text`
+fn number(text: &str) -> usize {
+  let bytes = text.as_bytes();
+  let digits =
+    |at: usize| bytes[at..].iter().take_while(|b| b.is_ascii_digit()).count();
+  let mut len = usize::from(bytes[0] == b'-');
+  len += digits(len);
+  if bytes.get(len) == Some(&b'.') && digits(len + 1) > 0 {
+    len += 1 + digits(len + 1);
+  }
+  if matches!(bytes.get(len), Some(b'e' | b'E')) {
+    let sign = usize::from(matches!(bytes.get(len + 1), Some(b'+' | b'-')));
+    if digits(len + 1 + sign) > 0 {
+      len += 1 + sign + digits(len + 1 + sign);
+    }
+  }
+  len
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Function {
+  Select,
+  Map,
+  Keys,
+  Length,
+  Not,
+  Type,
+}
+
+impl Function {
+  fn named(name: &str) -> Option<Function> {
+    match name {
+      "select" => Some(Function::Select),
+      "map" => Some(Function::Map),
+      "keys" => Some(Function::Keys),
+      "length" => Some(Function::Length),
+      "not" => Some(Function::Not),
+      "type" => Some(Function::Type),
+      _ => None,
+    }
+  }
+
+  fn takes_argument(self) -> bool {
+    matches!(self, Function::Select | Function::Map)
+  }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Comparison {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Key {
+  Name(String),
+  Expr(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+  Identity,
+  Field(String),
+  Iterate,
+  Recurse(Option<String>),
+  // The index is run on the input of the target, like in `.a[.i]`
+  Index(Box<Expr>, Box<Expr>),
+  Literal(Value),
+  Pipe(Box<Expr>, Box<Expr>),
+  Comma(Box<Expr>, Box<Expr>),
+  Compare(Box<Expr>, Comparison, Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Or(Box<Expr>, Box<Expr>),
+  Try(Box<Expr>),
+  Collect(Option<Box<Expr>>),
+  Document(Vec<(Key, Expr)>),
+  Call(Function, Option<Box<Expr>>),
+}
+
+struct Parser {
+  tokens: Vec<Spanned>,
+  next:   usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.next).map(|spanned| &spanned.token)
+  }
+
+  // Whether the next token directly follows the previous one
+  fn adjacent(&self) -> bool {
+    match (
+      self.tokens.get(self.next.wrapping_sub(1)),
+      self.tokens.get(self.next),
+    ) {
+      (Some(previous), Some(next)) => previous.end == next.start,
+      _ => false,
+    }
+  }
+
+  fn eat(&mut self, symbol: &str) -> bool {
+    let eaten = match self.peek() {
+      Some(Token::Symbol(name)) => *name == symbol,
+      Some(Token::Name(name)) => name == symbol,
+      _ => false,
+    };
+    if eaten {
+      self.next += 1;
+    }
+    eaten
+  }
+
+  fn expect(&mut self, symbol: &str) -> Result<(), QueryError> {
+    match self.eat(symbol) {
+      true => Ok(()),
+      false => Err(self.unexpected()),
+    }
+  }
+
+  fn unexpected(&self) -> QueryError {
+    match self.tokens.get(self.next) {
+      Some(token) => QueryError::UnexpectedToken(token.start),
+      None => QueryError::UnexpectedEnd,
+    }
+  }
+
+  fn pipe(&mut self) -> Result<Expr, QueryError> {
+    let mut expr = self.comma()?;
+    while self.eat("|") {
+      expr = Expr::Pipe(Box::new(expr), Box::new(self.comma()?));
+    }
+    Ok(expr)
+  }
+
+  fn comma(&mut self) -> Result<Expr, QueryError> {
+    let mut expr = self.or()?;
+    while self.eat(",") {
+      expr = Expr::Comma(Box::new(expr), Box::new(self.or()?));
+    }
+    Ok(expr)
+  }
+
+  fn or(&mut self) -> Result<Expr, QueryError> {
+    let mut expr = self.and()?;
+    while self.eat("or") {
+      expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+    }
+    Ok(expr)
+  }
+
+  fn and(&mut self) -> Result<Expr, QueryError> {
+    let mut expr = self.compare()?;
+    while self.eat("and") {
+      expr = Expr::And(Box::new(expr), Box::new(self.compare()?));
+    }
+    Ok(expr)
+  }
+
+  fn compare(&mut self) -> Result<Expr, QueryError> {
+    let expr = self.postfix()?;
+    let comparison = match self.peek() {
+      Some(Token::Symbol("==")) => Comparison::Eq,
+      Some(Token::Symbol("!=")) => Comparison::Ne,
+      Some(Token::Symbol("<")) => Comparison::Lt,
+      Some(Token::Symbol("<=")) => Comparison::Le,
+      Some(Token::Symbol(">")) => Comparison::Gt,
+      Some(Token::Symbol(">=")) => Comparison::Ge,
+      _ => return Ok(expr),
+    };
+    self.next += 1;
+    let other = self.postfix()?;
+    Ok(Expr::Compare(Box::new(expr), comparison, Box::new(other)))
+  }
+
+  fn postfix(&mut self) -> Result<Expr, QueryError> {
+    let mut expr = self.primary()?;
+    loop {
+      expr = match self.peek() {
+        Some(Token::Dot) => {
+          self.next += 1;
+          if !self.adjacent() {
+            return Err(self.unexpected());
+          }
+          match self.peek() {
+            Some(Token::Symbol("[")) => continue,
+            _ => Expr::Pipe(Box::new(expr), Box::new(self.field()?)),
+          }
+        }
+        Some(Token::DotDot) => {
+          Expr::Pipe(Box::new(expr), Box::new(self.primary()?))
+        }
+        Some(Token::Symbol("[")) => {
+          self.next += 1;
+          if self.eat("]") {
+            Expr::Pipe(Box::new(expr), Box::new(Expr::Iterate))
+          } else {
+            let index = self.pipe()?;
+            self.expect("]")?;
+            Expr::Index(Box::new(expr), Box::new(index))
+          }
+        }
+        Some(Token::Symbol("?")) => {
+          self.next += 1;
+          Expr::Try(Box::new(expr))
+        }
+        _ => return Ok(expr),
+      };
+    }
+  }
+
+  // The field or wildcard after a dot
+  fn field(&mut self) -> Result<Expr, QueryError> {
+    let expr = match self.peek() {
+      Some(Token::Name(name) | Token::String(name)) => {
+        Expr::Field(name.clone())
+      }
+      Some(Token::Symbol("*")) => Expr::Iterate,
+      _ => return Err(self.unexpected()),
+    };
+    self.next += 1;
+    Ok(expr)
+  }
+
+  fn primary(&mut self) -> Result<Expr, QueryError> {
+    let Some(spanned) = self.tokens.get(self.next).cloned() else {
+      return Err(QueryError::UnexpectedEnd);
+    };
+    self.next += 1;
+    let expr = match spanned.token {
+      Token::Dot => match self.peek() {
+        Some(Token::Name(_) | Token::String(_) | Token::Symbol("*"))
+          if self.adjacent() =>
+        {
+          self.field()?
+        }
+        _ => Expr::Identity,
+      },
+      Token::DotDot => match self.peek() {
+        Some(Token::Name(name) | Token::String(name)) if self.adjacent() => {
+          let name = name.clone();
+          self.next += 1;
+          Expr::Recurse(Some(name))
+        }
+        _ => Expr::Recurse(None),
+      },
+      Token::Number(atom) | Token::String(atom) => {
+        Expr::Literal(Value::Atom(atom))
+      }
+      Token::Name(name) => match name.as_str() {
+        "nil" => Expr::Literal(Value::Nil),
+        "true" | "false" => Expr::Literal(Value::Atom(name)),
+        _ => {
+          let Some(function) = Function::named(&name) else {
+            let at = spanned.start;
+            return Err(QueryError::UnknownFunction { at, name });
+          };
+          let argument = match function.takes_argument() {
+            true => {
+              self.expect("(")?;
+              let argument = self.pipe()?;
+              self.expect(")")?;
+              Some(Box::new(argument))
+            }
+            false => None,
+          };
+          Expr::Call(function, argument)
+        }
+      },
+      Token::Symbol("(") => {
+        let expr = self.pipe()?;
+        self.expect(")")?;
+        expr
+      }
+      Token::Symbol("[") => match self.eat("]") {
+        true => Expr::Collect(None),
+        false => {
+          let expr = self.pipe()?;
+          self.expect("]")?;
+          Expr::Collect(Some(Box::new(expr)))
+        }
+      },
+      Token::Symbol("{") => self.document()?,
+      Token::Symbol(_) => {
+        return Err(QueryError::UnexpectedToken(spanned.start))
+      }
+    };
+    Ok(expr)
+  }
+
+  // The entries of a document after the opening brace
+  fn document(&mut self) -> Result<Expr, QueryError> {
+    let mut entries = vec![];
+    while !self.eat("}") {
+      if !entries.is_empty() {
+        self.expect(",")?;
+      }
+      let key = match self.peek().cloned() {
+        Some(Token::Name(name) | Token::String(name)) => {
+          self.next += 1;
+          Key::Name(name)
+        }
+        Some(Token::Symbol("(")) => {
+          self.next += 1;
+          let key = self.pipe()?;
+          self.expect(")")?;
+          Key::Expr(key)
+        }
+        _ => return Err(self.unexpected()),
+      };
+      let value = match (self.eat(":"), &key) {
+        (true, _) => self.or()?,
+        (false, Key::Name(name)) => Expr::Field(name.clone()),
+        (false, Key::Expr(_)) => return Err(self.unexpected()),
+      };
+      entries.push((key, value));
+    }
+    Ok(Expr::Document(entries))
+  }
+}
+
+fn boolean(value: bool) -> Value { Value::Atom(value.to_string()) }
+
+fn truthy(value: &Value) -> bool {
+  !matches!(value, Value::Nil) && *value != boolean(false)
+}
+
+// Atoms compare as numbers if both are numbers, other values by their order
+fn compare(a: &Value, b: &Value) -> Ordering {
+  if let (Value::Atom(a), Value::Atom(b)) = (a, b) {
+    if let (Ok(a), Ok(b)) = (a.parse::<f64>(), b.parse::<f64>()) {
+      if let Some(ordering) = a.partial_cmp(&b) {
+        return ordering;
+      }
+    }
+  }
+  a.cmp(b)
+}
+
+impl Expr {
+  fn values(&self, input: &Value) -> Result<Vec<Value>, QueryError> {
+    let mut values = vec![];
+    self.eval(input, &mut values)?;
+    Ok(values)
+  }
+
+  fn eval(
+    &self,
+    input: &Value,
+    out: &mut Vec<Value>,
+  ) -> Result<(), QueryError> {
+    match self {
+      Expr::Identity => out.push(input.clone()),
+      Expr::Field(name) => match input {
+        Value::Document(document) => {
+          out.push(document.get(name).cloned().unwrap_or(Value::Nil));
+        }
+        Value::Nil => out.push(Value::Nil),
+        _ => {
+          let found = input.type_name();
+          return Err(QueryError::Type { operation: "index", found });
+        }
+      },
+      Expr::Iterate => match input {
+        Value::List(list) => out.extend(list.iter().cloned()),
+        Value::Document(document) => out.extend(document.values().cloned()),
+        _ => {
+          let found = input.type_name();
+          return Err(QueryError::Type { operation: "iterate over", found });
+        }
+      },
+      Expr::Recurse(name) => recurse(input, name.as_deref(), out),
+      Expr::Index(target, index) => {
+        let indices = index.values(input)?;
+        for target in target.values(input)? {
+          for index in &indices {
+            out.push(at_index(&target, index)?);
+          }
+        }
+      }
+      Expr::Literal(value) => out.push(value.clone()),
+      Expr::Pipe(left, right) => {
+        for value in left.values(input)? {
+          right.eval(&value, out)?;
+        }
+      }
+      Expr::Comma(left, right) => {
+        left.eval(input, out)?;
+        right.eval(input, out)?;
+      }
+      Expr::Compare(left, comparison, right) => {
+        let rights = right.values(input)?;
+        for left in left.values(input)? {
+          for right in &rights {
+            let ordering = compare(&left, right);
+            let result = match comparison {
+              Comparison::Eq => ordering.is_eq(),
+              Comparison::Ne => ordering.is_ne(),
+              Comparison::Lt => ordering.is_lt(),
+              Comparison::Le => ordering.is_le(),
+              Comparison::Gt => ordering.is_gt(),
+              Comparison::Ge => ordering.is_ge(),
+            };
+            out.push(boolean(result));
+          }
+        }
+      }
+      // The right side only runs if it decides the result
+      Expr::And(left, right) | Expr::Or(left, right) => {
+        let or = matches!(self, Expr::Or(..));
+        for left in left.values(input)? {
+          if truthy(&left) == or {
+            out.push(boolean(or));
+            continue;
+          }
+          for right in right.values(input)? {
+            out.push(boolean(truthy(&right)));
+          }
+        }
+      }
+      Expr::Try(expr) => {
+        let _ = expr.eval(input, out);
+      }
+      Expr::Collect(None) => out.push(Value::List(vec![])),
+      Expr::Collect(Some(expr)) => out.push(Value::List(expr.values(input)?)),
+      Expr::Document(entries) => {
+        let mut documents = vec![Document::new()];
+        for (key, value) in entries {
+          let keys = match key {
+            Key::Name(name) => vec![name.clone()],
+            Key::Expr(expr) => {
+              let keys = expr.values(input)?.into_iter().map(|key| match key {
+                Value::Atom(atom) => Ok(atom),
+                _ => {
+                  let found = key.type_name();
+                  Err(QueryError::Type { operation: "use as key", found })
+                }
+              });
+              keys.collect::<Result<Vec<_>, _>>()?
+            }
+          };
+          let values = value.values(input)?;
+          let mut products = vec![];
+          for document in &documents {
+            for key in &keys {
+              for value in &values {
+                let mut document = document.clone();
+                document.insert(key.clone(), value.clone());
+                products.push(document);
+              }
+            }
+          }
+          documents = products;
+        }
+        out.extend(documents.into_iter().map(Value::Document));
+      }
+      Expr::Call(function, argument) => {
+        call(*function, argument.as_deref(), input, out)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+fn recurse(value: &Value, name: Option<&str>, out: &mut Vec<Value>) {
+  match (value, name) {
+    (_, None) => out.push(value.clone()),
+    (Value::Document(document), Some(name)) => {
+      out.extend(document.get(name).cloned());
+    }
+    _ => {}
+  }
+  match value {
+    Value::List(list) => list.iter().for_each(|v| recurse(v, name, out)),
+    Value::Document(document) => {
+      document.values().for_each(|v| recurse(v, name, out));
+    }
+    _ => {}
+  }
+}
+
+fn at_index(target: &Value, index: &Value) -> Result<Value, QueryError> {
+  let value = match (target, index) {
+    (Value::List(list), Value::Atom(atom)) => {
+      let index = atom.parse::<i64>().ok().and_then(|index| match index {
+        0.. => usize::try_from(index).ok(),
+        _ => {
+          list.len().checked_sub(usize::try_from(index.unsigned_abs()).ok()?)
+        }
+      });
+      index.and_then(|index| list.get(index))
+    }
+    (Value::Document(document), Value::Atom(key)) => document.get(key),
+    (Value::Nil, _) => None,
+    _ => {
+      let found = target.type_name();
+      return Err(QueryError::Type { operation: "index", found });
+    }
+  };
+  Ok(value.cloned().unwrap_or(Value::Nil))
+}
+
+fn call(
+  function: Function,
+  argument: Option<&Expr>,
+  input: &Value,
+  out: &mut Vec<Value>,
+) -> Result<(), QueryError> {
+  let argument = || argument.expect("function takes an argument");
+  let value = match function {
+    Function::Select => {
+      for result in argument().values(input)? {
+        if truthy(&result) {
+          out.push(input.clone());
+        }
+      }
+      return Ok(());
+    }
+    Function::Map => {
+      let mut elements = vec![];
+      Expr::Iterate.eval(input, &mut elements)?;
+      let mut results = vec![];
+      for element in &elements {
+        argument().eval(element, &mut results)?;
+      }
+      Value::List(results)
+    }
+    Function::Keys => match input {
+      Value::Document(document) => {
+        Value::List(document.keys().cloned().map(Value::Atom).collect())
+      }
+      Value::List(list) => Value::List(
+        (0..list.len()).map(|i| Value::Atom(i.to_string())).collect(),
+      ),
+      _ => {
+        let found = input.type_name();
+        return Err(QueryError::Type { operation: "take the keys of", found });
+      }
+    },
+    Function::Length => {
+      let length = match input {
+        Value::Nil => 0,
+        Value::Atom(atom) => atom.chars().count(),
+        Value::List(list) => list.len(),
+        Value::Document(document) => document.len(),
+      };
+      Value::Atom(length.to_string())
+    }
+    Function::Not => boolean(!truthy(input)),
+    Function::Type => Value::Atom(input.type_name().to_owned()),
+  };
+  out.push(value);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TEXT: &str = "
+    team: red
+    members: (
+      (name: Ann age: 31 address: (city: Oslo))
+      (name: Bob age: 9 address: (city: Rome))
+      (name: Cid age: 100)
+    )
+  ";
+
+  fn run(text: &str) -> String {
+    let value = crate::parse(TEXT).unwrap();
+    let results = query(text, &value).unwrap();
+    let results = results.iter().map(Value::to_string).collect::<Vec<_>>();
+    results.join(" ")
+  }
+
+  #[test]
+  fn test_paths() {
+    assert_eq!(run(".team"), "red");
+    assert_eq!(run(".\"team\""), "red");
+    assert_eq!(run(".missing"), "#nil");
+    assert_eq!(run(".members[0].name"), "Ann");
+    assert_eq!(run(".members[-1].name"), "Cid");
+    assert_eq!(run(".members[5]"), "#nil");
+    assert_eq!(run(".members[-4]"), "#nil");
+    assert_eq!(run(".members[-9223372036854775808]"), "#nil");
+    assert_eq!(run(".members[].name"), "Ann Bob Cid");
+    assert_eq!(run(".members.*.age"), "31 9 100");
+    assert_eq!(run(".members[1][\"name\"]"), "Bob");
+    assert_eq!(run("..city"), "Oslo Rome");
+    assert_eq!(run("[.. | .city?] | length"), "6");
+    assert_eq!(run(".members[2].address.city"), "#nil");
+    assert_eq!(run(".team, .members | length"), "3 3");
+  }
+
+  #[test]
+  fn test_filters() {
+    assert_eq!(run(".members[] | select(.age > 30) | .name"), "Ann Cid");
+    assert_eq!(
+      run(".members[] | select(.age < 100 and .name != \"Ann\") | .name"),
+      "Bob"
+    );
+    assert_eq!(run(".members[] | select(.address | not) | .name"), "Cid");
+    assert_eq!(run(".members | map(.age >= 31)"), "(true false true)");
+    assert_eq!(
+      run("1.5e1 == 15, \"a\" < \"b\", 10 > 9, 10 > \"9x\""),
+      "true true true false"
+    );
+  }
+
+  #[test]
+  fn test_construction() {
+    assert_eq!(
+      run(".members[0] | {name, city: .address.city}"),
+      "(name: Ann city: Oslo)"
+    );
+    assert_eq!(
+      run("{(.members[].name): .team}"),
+      "(Ann: red) (Bob: red) (Cid: red)"
+    );
+    assert_eq!(run("[.members[].name, nil]"), "(Ann Bob Cid #nil)");
+    assert_eq!(run("([] | length), keys"), "0 (team members)");
+    assert_eq!(run(".members | keys"), "(0 1 2)");
+    assert_eq!(
+      run("[.team, .members, ., nil] | map(type)"),
+      "(atom list document nil)"
+    );
+  }
+
+  #[test]
+  fn test_errors() {
+    let value = crate::parse(TEXT).unwrap();
+    let error = |text: &str| query(text, &value).unwrap_err().to_string();
+    assert_eq!(error(".team[]"), "cannot iterate over atom");
+    assert_eq!(error(".team.x"), "cannot index atom");
+    assert_eq!(error("{(.members): 1}"), "cannot use as key list");
+    assert_eq!(error(".members |"), "unexpected end of query");
+    assert_eq!(error(". name"), "unexpected token in query at 2");
+    assert_eq!(error(".a ]"), "unexpected token in query at 3");
+    assert_eq!(error(".a = 1"), "unexpected character in query at 3");
+    assert_eq!(error("\"a"), "unterminated string in query at 0");
+    assert_eq!(error("sort"), "unknown function `sort` in query at 0");
+    assert_eq!(run("[.team[]?, .team.x?]"), "()");
+  }
+}
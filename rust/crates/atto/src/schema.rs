@@ -0,0 +1,466 @@
+//! Schemas: the expected shape of atto documents, and their inference
+//!
+//! A schema is written in atto. Atom types are names, other types are
+//! documents with one key for the kind of type:
+//!
+//! ```text
+//! document: (
+//!   name: string
+//!   port: integer
+//!   ratio: number
+//!   debug: boolean
+//!   level: (enum: (debug info warn))
+//!   hosts: (list: string)
+//!   labels: (map: string)
+//!   parent: (one-of: (nil string))
+//!   extra: any
+//! )
+//! optional: (debug labels)
+//! ```
+//!
+//! `nil` is the type of `#nil`. A `document` has the listed keys, those in
+//! `optional` may be missing, while a `map` has any keys with values of one
+//! type. A schema is read with [`decode::from_str`](crate::decode::from_str)
+//! and written with [`ToAtto`], so errors in hand-written schemas have
+//! positions like any other decode error.
+//!
+//! [`infer()`] guesses a schema from example documents.
+
+use crate::decode::{DecodeError, FromAtto};
+use crate::encode::ToAtto;
+use crate::path::Segment;
+use crate::value::{format_atom, Document, Value};
+use indexmap::{IndexMap, IndexSet};
+
+/// The type of a value
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Schema {
+  Any,
+  Nil,
+  String,
+  Integer,
+  Number,
+  Boolean,
+  /// One of the atoms
+  Enum(Vec<String>),
+  List(Box<Schema>),
+  /// A document with any keys
+  Map(Box<Schema>),
+  Document(IndexMap<String, Field>),
+  OneOf(Vec<Schema>),
+}
+
+/// A key of a [`Schema::Document`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+  pub schema:   Schema,
+  pub optional: bool,
+}
+
+const NAMES: [&str; 6] =
+  ["any", "nil", "string", "integer", "number", "boolean"];
+const KINDS: [&str; 5] = ["enum", "list", "map", "document", "one-of"];
+
+impl FromAtto for Schema {
+  fn from_atto(value: &Value) -> Result<Self, DecodeError> {
+    let form = match value {
+      Value::Atom(name) => {
+        return match name.as_str() {
+          "any" => Ok(Schema::Any),
+          "nil" => Ok(Schema::Nil),
+          "string" => Ok(Schema::String),
+          "integer" => Ok(Schema::Integer),
+          "number" => Ok(Schema::Number),
+          "boolean" => Ok(Schema::Boolean),
+          _ => Err(DecodeError::unknown("type", name, &NAMES)),
+        };
+      }
+      Value::Document(form) => form,
+      _ => return Err(DecodeError::expected("type", value)),
+    };
+
+    let kind = form.keys().find(|key| KINDS.contains(&key.as_str()));
+    let Some(kind) = kind else {
+      let key = form.keys().next().map_or("", String::as_str);
+      let err = DecodeError::unknown("key", key, &KINDS);
+      return Err(err.within(Segment::Key(key.to_owned())));
+    };
+    let known: &[&str] = match kind.as_str() {
+      "document" => &["document", "optional"],
+      _ => &[kind],
+    };
+    if let Some(key) = form.keys().find(|key| !known.contains(&key.as_str())) {
+      let err = DecodeError::unknown("key", key, known);
+      return Err(err.within(Segment::Key(key.clone())));
+    }
+
+    let within = |err: DecodeError| err.within(Segment::Key(kind.clone()));
+    let value = &form[kind];
+    match kind.as_str() {
+      "enum" => Vec::from_atto(value).map(Schema::Enum).map_err(within),
+      "list" => {
+        Ok(Schema::List(Box::new(Schema::from_atto(value).map_err(within)?)))
+      }
+      "map" => {
+        Ok(Schema::Map(Box::new(Schema::from_atto(value).map_err(within)?)))
+      }
+      "one-of" => Vec::from_atto(value).map(Schema::OneOf).map_err(within),
+      _ => {
+        let types =
+          IndexMap::<String, Schema>::from_atto(value).map_err(within)?;
+        let mut fields = types
+          .into_iter()
+          .map(|(key, schema)| (key, Field { schema, optional: false }))
+          .collect::<IndexMap<_, _>>();
+        let optional = match form.get("optional") {
+          Some(optional) => Vec::<String>::from_atto(optional)
+            .map_err(|err| err.within(Segment::Key("optional".to_owned())))?,
+          None => vec![],
+        };
+        for (index, key) in optional.iter().enumerate() {
+          let Some(field) = fields.get_mut(key) else {
+            let known = field_keys(&fields);
+            let err = DecodeError::unknown("key", key, &known);
+            let err = err.within(Segment::Index(index));
+            return Err(err.within(Segment::Key("optional".to_owned())));
+          };
+          field.optional = true;
+        }
+        Ok(Schema::Document(fields))
+      }
+    }
+  }
+}
+
+fn field_keys(fields: &IndexMap<String, Field>) -> Vec<&str> {
+  fields.keys().map(String::as_str).collect()
+}
+
+impl ToAtto for Schema {
+  fn to_atto(&self) -> Value {
+    let form = |kind: &str, value: Value| {
+      Value::Document(Document::from_iter([(kind.to_owned(), value)]))
+    };
+    let atom = |name: &str| Value::Atom(name.to_owned());
+    match self {
+      Schema::Any => atom("any"),
+      Schema::Nil => atom("nil"),
+      Schema::String => atom("string"),
+      Schema::Integer => atom("integer"),
+      Schema::Number => atom("number"),
+      Schema::Boolean => atom("boolean"),
+      Schema::Enum(atoms) => form("enum", atoms.to_atto()),
+      Schema::List(element) => form("list", element.to_atto()),
+      Schema::Map(value) => form("map", value.to_atto()),
+      Schema::OneOf(schemas) => form("one-of", schemas.to_atto()),
+      Schema::Document(fields) => {
+        let types = fields
+          .iter()
+          .map(|(key, field)| (key.clone(), field.schema.to_atto()));
+        let mut form = Document::new();
+        form.insert("document".to_owned(), Value::Document(types.collect()));
+        let optional = fields
+          .iter()
+          .filter(|(_, field)| field.optional)
+          .map(|(key, _)| Value::Atom(key.clone()))
+          .collect::<Vec<_>>();
+        if !optional.is_empty() {
+          form.insert("optional".to_owned(), Value::List(optional));
+        }
+        Value::Document(form)
+      }
+    }
+  }
+}
+
+/// Options for [`infer_with()`]
+#[derive(Clone, Debug)]
+pub struct InferOptions {
+  /// Atoms become an enum if there are at most this many different ones,
+  /// and some occur more than once
+  pub max_enum_values: usize,
+}
+
+impl Default for InferOptions {
+  fn default() -> Self { InferOptions { max_enum_values: 5 } }
+}
+
+/// Infer a schema from example values with default options.
+///
+/// Keys missing in some documents are optional, atoms which are all
+/// integers, numbers or booleans get these types, and a few words
+/// occurring repeatedly become an enum. Values of different kinds give a
+/// `one-of`.
+///
+/// ```
+/// # use atto::{encode::ToAtto, format::pretty, schema::infer};
+/// let a = atto::parse("name: a port: 80 level: info tags: (x y)").unwrap();
+/// let b = atto::parse("name: b port: 81 level: info").unwrap();
+///
+/// assert_eq!(
+///   pretty(&infer([&a, &b]).to_atto()),
+///   "\
+/// document: (
+///   name: string
+///   port: integer
+///   level: (enum: (info))
+///   tags: (list: string)
+/// )
+/// optional: (tags)
+/// "
+/// );
+/// ```
+pub fn infer<'v>(samples: impl IntoIterator<Item = &'v Value>) -> Schema {
+  infer_with(samples, &InferOptions::default())
+}
+
+/// Infer a schema from example values.
+pub fn infer_with<'v>(
+  samples: impl IntoIterator<Item = &'v Value>,
+  options: &InferOptions,
+) -> Schema {
+  let mut shape = Shape::default();
+  for sample in samples {
+    shape.add(sample, options);
+  }
+  shape.schema(options)
+}
+
+// What is known about the values at one place in the examples
+#[derive(Debug, Default)]
+struct Shape {
+  nil:         bool,
+  atoms:       Option<Atoms>,
+  /// The shape of all list elements, if there were lists
+  elements:    Option<Box<Shape>>,
+  /// Empty lists, which might be empty documents
+  empty_lists: usize,
+  documents:   usize,
+  /// Shapes of the values by key with the number of documents with it
+  entries:     IndexMap<String, (usize, Shape)>,
+}
+
+#[derive(Debug)]
+struct Atoms {
+  count:    usize,
+  integer:  bool,
+  number:   bool,
+  boolean:  bool,
+  /// All are words, which an enum could consist of
+  words:    bool,
+  /// The different atoms, up to one more than an enum can have
+  distinct: IndexSet<String>,
+}
+
+impl Shape {
+  fn add(&mut self, value: &Value, options: &InferOptions) {
+    match value {
+      Value::Nil => self.nil = true,
+      Value::Atom(atom) => {
+        let atoms = self.atoms.get_or_insert_with(|| Atoms {
+          count:    0,
+          integer:  true,
+          number:   true,
+          boolean:  true,
+          words:    true,
+          distinct: IndexSet::new(),
+        });
+        let numeric = atom.bytes().any(|b| b.is_ascii_digit());
+        atoms.count += 1;
+        atoms.integer &= atom.parse::<i128>().is_ok();
+        atoms.number &= numeric && atom.parse::<f64>().is_ok();
+        atoms.boolean &= atom == "true" || atom == "false";
+        atoms.words &=
+          !atom.is_empty() && !numeric && format_atom(atom) == *atom;
+        if atoms.distinct.len() <= options.max_enum_values {
+          atoms.distinct.insert(atom.clone());
+        }
+      }
+      Value::List(list) => {
+        if list.is_empty() {
+          self.empty_lists += 1;
+        }
+        let elements = self.elements.get_or_insert_with(Default::default);
+        for element in list {
+          elements.add(element, options);
+        }
+      }
+      Value::Document(document) => {
+        self.documents += 1;
+        for (key, value) in document {
+          let (count, shape) = self.entries.entry(key.clone()).or_default();
+          *count += 1;
+          shape.add(value, options);
+        }
+      }
+    }
+  }
+
+  fn schema(self, options: &InferOptions) -> Schema {
+    let mut schemas = vec![];
+    if self.nil {
+      schemas.push(Schema::Nil);
+    }
+    if let Some(atoms) = self.atoms {
+      let distinct = atoms.distinct.len();
+      schemas.push(if atoms.boolean {
+        Schema::Boolean
+      } else if atoms.integer {
+        Schema::Integer
+      } else if atoms.number {
+        Schema::Number
+      } else if atoms.words
+        && distinct <= options.max_enum_values
+        && distinct < atoms.count
+      {
+        Schema::Enum(atoms.distinct.into_iter().collect())
+      } else {
+        Schema::String
+      });
+    }
+
+    // Empty lists are empty documents if there are only documents
+    let only_empty_lists = self.elements.as_ref().is_some_and(|elements| {
+      elements.atoms.is_none()
+        && !elements.nil
+        && elements.elements.is_none()
+        && elements.documents == 0
+    });
+    let documents = match self.documents > 0 && only_empty_lists {
+      true => self.documents + self.empty_lists,
+      false => self.documents,
+    };
+    if let Some(elements) =
+      self.elements.filter(|_| documents == self.documents)
+    {
+      let element = match elements.is_empty() {
+        true => Schema::Any,
+        false => elements.schema(options),
+      };
+      schemas.push(Schema::List(Box::new(element)));
+    }
+    if documents > 0 {
+      let fields = self.entries.into_iter().map(|(key, (count, shape))| {
+        let field = Field {
+          schema:   shape.schema(options),
+          optional: count < documents,
+        };
+        (key, field)
+      });
+      schemas.push(Schema::Document(fields.collect()));
+    }
+
+    match schemas.len() {
+      0 => Schema::Any,
+      1 => schemas.pop().unwrap(),
+      _ => Schema::OneOf(schemas),
+    }
+  }
+
+  fn is_empty(&self) -> bool {
+    !self.nil
+      && self.atoms.is_none()
+      && self.elements.is_none()
+      && self.documents == 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::decode::from_str;
+  use crate::format::pretty;
+
+  fn inferred(samples: &[&str]) -> String {
+    let values = samples.iter().map(|text| crate::parse(text).unwrap());
+    let values = values.collect::<Vec<_>>();
+    pretty(&infer(&values).to_atto())
+  }
+
+  #[test]
+  fn test_infer() {
+    let schema = inferred(&[
+      "id: 1 ratio: 0.5 on: true level: warn tags: (a b) items: ((n: 1) (n: 2 x: y))",
+      "id: -2 ratio: 1 on: false level: warn tags: () items: () parent: #nil",
+      "id: 3 ratio: 1e3 on: true level: info tags: (c) items: () parent: p",
+    ]);
+    assert_eq!(
+      schema,
+      "\
+document: (
+  id: integer
+  ratio: number
+  on: boolean
+  level: (enum: (warn info))
+  tags: (list: string)
+  items: (list: (document: (n: integer x: string) optional: (x)))
+  parent: (one-of: (nil string))
+)
+optional: (parent)
+"
+    );
+
+    // Few repetitions or atoms which are no words are no enum
+    assert_eq!(inferred(&["a: x", "a: y"]), "document: (a: string)\n");
+    assert_eq!(
+      inferred(&["a: \"x y\"", "a: \"x y\""]),
+      "document: (a: string)\n"
+    );
+    assert_eq!(
+      inferred(&["a: inf", "a: inf"]),
+      "document: (a: (enum: (inf)))\n"
+    );
+
+    // Empty lists are empty documents next to documents
+    assert_eq!(
+      inferred(&["a: ()", "a: (b: 1)"]),
+      "document: (a: (document: (b: integer) optional: (b)))\n"
+    );
+    assert_eq!(
+      inferred(&["a: ()", "a: x"]),
+      "document: (a: (one-of: (string (list: any))))\n"
+    );
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let text = "\
+document: (
+  level: (enum: (debug info))
+  labels: (map: string)
+  parent: (one-of: (nil (list: any) (document: ())))
+)
+optional: (labels)
+";
+    let schema = from_str::<Schema>(text).unwrap();
+    let Schema::Document(fields) = &schema else { panic!() };
+    assert!(fields["labels"].optional);
+    assert_eq!(fields["labels"].schema, Schema::Map(Box::new(Schema::String)));
+    assert_eq!(pretty(&schema.to_atto()), text);
+  }
+
+  #[test]
+  fn test_errors() {
+    let error = |text: &str| from_str::<Schema>(text).unwrap_err().to_string();
+    assert_eq!(
+      error("document: (a: strng)"),
+      "document.a: unknown type `strng`, did you mean `string`? at 1:15"
+    );
+    assert_eq!(
+      error("document: (a: (lists: any))"),
+      "document.a.lists: unknown key `lists`, did you mean `list`? at 1:16"
+    );
+    assert_eq!(
+      error("document: (a: (list: any map: any))"),
+      "document.a.map: unknown key `map` at 1:26"
+    );
+    assert_eq!(
+      error("document: (a: any) optional: (b)"),
+      "optional.0: unknown key `b`, did you mean `a`? at 1:31"
+    );
+    assert_eq!(
+      error("document: (a: (1 2))"),
+      "document.a: expected type, found list at 1:15"
+    );
+  }
+}
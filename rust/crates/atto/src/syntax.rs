@@ -0,0 +1,295 @@
+//! Syntax tree: values with their location in the source text
+//!
+//! The tree keeps the byte span of every value and key, so that tools like
+//! editors and error reports can map paths back to the source.
+
+use crate::format::Docs;
+use crate::path::{Path, Segment};
+use crate::value::{Atom, Value};
+use std::fmt;
+
+/// A byte range `start..end` in a source text
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+  pub start: usize,
+  pub end:   usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize) -> Span { Span { start, end } }
+
+  pub fn len(&self) -> usize { self.end - self.start }
+
+  pub fn is_empty(&self) -> bool { self.start == self.end }
+
+  /// Whether `offset` is inside the span or at its end.
+  pub fn contains(&self, offset: usize) -> bool {
+    self.start <= offset && offset <= self.end
+  }
+
+  /// The smallest span covering both spans.
+  pub fn to(&self, other: Span) -> Span {
+    Span::new(self.start.min(other.start), self.end.max(other.end))
+  }
+
+  /// The line and column of the start of the span in `text`.
+  pub fn position(&self, text: &str) -> Position {
+    Position::of(text, self.start)
+  }
+}
+
+/// A line and column, both starting at 1, columns counted in characters
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Position {
+  pub line:   usize,
+  pub column: usize,
+}
+
+impl Position {
+  /// The position of the byte `offset` in `text`.
+  ///
+  /// ```
+  /// # use atto::syntax::Position;
+  /// let position = Position::of("a: b\nö: c", 8);
+  /// assert_eq!(position.to_string(), "2:3");
+  /// ```
+  pub fn of(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let before = &text.as_bytes()[..offset];
+    let line_start =
+      before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let column =
+      String::from_utf8_lossy(&before[line_start..]).chars().count() + 1;
+    Position { line, column }
+  }
+}
+
+/// The start offsets of the lines of a text, to find the positions of many
+/// offsets. A position is counted on from the last one if that is on the
+/// same line and not after it, so that increasing offsets on one long line
+/// do not count its characters again.
+pub(crate) struct LineIndex<'t> {
+  text:   &'t str,
+  starts: Vec<usize>,
+  last:   (usize, Position),
+}
+
+impl<'t> LineIndex<'t> {
+  pub(crate) fn new(text: &'t str) -> LineIndex<'t> {
+    let newlines = text.match_indices('\n').map(|(i, _)| i + 1);
+    let starts = std::iter::once(0).chain(newlines).collect();
+    LineIndex { text, starts, last: (0, Position { line: 1, column: 1 }) }
+  }
+
+  /// The position of the byte `offset`, like [`Position::of()`].
+  pub(crate) fn position(&mut self, offset: usize) -> Position {
+    let offset = offset.min(self.text.len());
+    let line = self.starts.partition_point(|&start| start <= offset);
+    let (from, column) = match self.last {
+      (at, last) if last.line == line && at <= offset => (at, last.column),
+      _ => (self.starts[line - 1], 1),
+    };
+    // Characters are counted by their first byte
+    let bytes = &self.text.as_bytes()[from..offset];
+    let chars = bytes.iter().filter(|&&b| b & 0xc0 != 0x80).count();
+    let position = Position { line, column: column + chars };
+    self.last = (offset, position);
+    position
+  }
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+/// A value with its span
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Node {
+  pub span: Span,
+  pub kind: NodeKind,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NodeKind {
+  Nil,
+  Atom(Atom),
+  List(Vec<Node>),
+  Document(Vec<Entry>),
+}
+
+/// A document entry with the span of its key
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+  pub key:        Atom,
+  pub key_span:   Span,
+  pub value:      Node,
+  pub(crate) doc: Option<String>,
+}
+
+impl Entry {
+  /// The span from the start of the key to the end of the value.
+  pub fn span(&self) -> Span { self.key_span.to(self.value.span) }
+
+  /// The doc comment: the block of comment lines right before the key,
+  /// without their `#` and the space after it.
+  ///
+  /// ```
+  /// let text = "# The port\n# to listen on\nport: 8080";
+  /// let root = atto::parser::parse_tree(text).root;
+  /// assert_eq!(root.entry("port").unwrap().doc(), Some("The port\nto listen on"));
+  /// ```
+  pub fn doc(&self) -> Option<&str> { self.doc.as_deref() }
+}
+
+impl Node {
+  /// The value without spans.
+  pub fn to_value(&self) -> Value {
+    match &self.kind {
+      NodeKind::Nil => Value::Nil,
+      NodeKind::Atom(atom) => Value::Atom(atom.clone()),
+      NodeKind::List(list) => {
+        Value::List(list.iter().map(Node::to_value).collect())
+      }
+      NodeKind::Document(entries) => {
+        let entries =
+          entries.iter().map(|e| (e.key.clone(), e.value.to_value()));
+        Value::Document(entries.collect())
+      }
+    }
+  }
+
+  pub fn entries(&self) -> &[Entry] {
+    match &self.kind {
+      NodeKind::Document(entries) => entries,
+      _ => &[],
+    }
+  }
+
+  /// The entry with `key`, the last one if the key is duplicated.
+  pub fn entry(&self, key: &str) -> Option<&Entry> {
+    self.entries().iter().rev().find(|entry| entry.key == key)
+  }
+
+  /// The child node addressed by one segment.
+  pub fn get(&self, segment: &Segment) -> Option<&Node> {
+    match (&self.kind, segment) {
+      (NodeKind::List(list), Segment::Index(index)) => list.get(*index),
+      (NodeKind::Document(_), Segment::Key(key)) => {
+        Some(&self.entry(key)?.value)
+      }
+      (NodeKind::Document(_), Segment::Index(index)) => {
+        Some(&self.entry(&index.to_string())?.value)
+      }
+      _ => None,
+    }
+  }
+
+  /// The node at `path`, if there is one.
+  pub fn get_path(&self, path: &Path) -> Option<&Node> {
+    path.segments().iter().try_fold(self, |node, segment| node.get(segment))
+  }
+
+  /// The entry whose key is at the end of `path`.
+  pub fn entry_at_path(&self, path: &Path) -> Option<&Entry> {
+    let parent = self.get_path(&path.parent()?)?;
+    match path.last()? {
+      Segment::Key(key) => parent.entry(key),
+      Segment::Index(index) => parent.entry(&index.to_string()),
+    }
+  }
+
+  /// The doc comments of all entries, for writing them back with
+  /// [`pretty_with_docs`](crate::format::pretty_with_docs).
+  pub fn docs(&self) -> Docs {
+    fn collect(node: &Node, path: &mut Path, docs: &mut Docs) {
+      match &node.kind {
+        NodeKind::List(list) => {
+          for (index, child) in list.iter().enumerate() {
+            path.push(Segment::Index(index));
+            collect(child, path, docs);
+            path.pop();
+          }
+        }
+        NodeKind::Document(entries) => {
+          for entry in entries {
+            path.push(Segment::Key(entry.key.clone()));
+            if let Some(doc) = &entry.doc {
+              docs.insert(path.clone(), doc.clone());
+            }
+            collect(&entry.value, path, docs);
+            path.pop();
+          }
+        }
+        _ => {}
+      }
+    }
+
+    let mut docs = Docs::new();
+    collect(self, &mut Path::root(), &mut docs);
+    docs
+  }
+
+  /// The path of the innermost node whose span contains `offset`.
+  ///
+  /// A key counts as part of its entry, so the path of a key is the path
+  /// of its value.
+  pub fn path_at(&self, offset: usize) -> Option<Path> {
+    if !self.span.contains(offset) {
+      return None;
+    }
+    let mut path = Path::root();
+    let mut node = self;
+    'descend: loop {
+      match &node.kind {
+        NodeKind::List(list) => {
+          for (index, child) in list.iter().enumerate() {
+            if child.span.contains(offset) {
+              path.push(Segment::Index(index));
+              node = child;
+              continue 'descend;
+            }
+          }
+        }
+        NodeKind::Document(entries) => {
+          for entry in entries {
+            if entry.span().contains(offset) {
+              path.push(Segment::Key(entry.key.clone()));
+              node = &entry.value;
+              continue 'descend;
+            }
+          }
+        }
+        _ => {}
+      }
+      return Some(path);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_position() {
+    assert_eq!(Position::of("", 0), Position { line: 1, column: 1 });
+    assert_eq!(Position::of("ab\n", 3), Position { line: 2, column: 1 });
+    assert_eq!(Position::of("ab", 9), Position { line: 1, column: 3 });
+  }
+
+  #[test]
+  fn test_line_index() {
+    let text = "ab\nö x\n\nyz";
+    let mut index = LineIndex::new(text);
+    for offset in [0, 2, 3, 4, 6, 5, 7, 8, 9, 10, 11, 1, 99] {
+      assert_eq!(
+        index.position(offset),
+        Position::of(text, offset),
+        "{offset}"
+      );
+    }
+  }
+}
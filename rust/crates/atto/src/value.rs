@@ -1,5 +1,9 @@
+use core::cmp::Ordering;
 use core::fmt;
+use core::hash::{Hash, Hasher};
 use indexmap::IndexMap;
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
 
 pub type Atom = String;
 
@@ -74,7 +78,130 @@ impl fmt::Display for Value {
   }
 }
 
-fn format_atom(atom: &str) -> String { atom.to_owned() }
+// Hash and Ord treat documents like Eq does: the order of entries does not
+// matter, so entries are visited sorted by key.
+fn sorted_entries(document: &Document) -> Vec<(&Key, &Value)> {
+  let mut entries = document.iter().collect::<Vec<_>>();
+  entries.sort_unstable_by_key(|(key, _)| *key);
+  entries
+}
+
+impl Value {
+  /// The name of the variant for messages, like `"atom"`.
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::Nil => "nil",
+      Value::Atom(_) => "atom",
+      Value::List(_) => "list",
+      Value::Document(_) => "document",
+    }
+  }
+
+  fn rank(&self) -> u8 {
+    match self {
+      Value::Nil => 0,
+      Value::Atom(_) => 1,
+      Value::List(_) => 2,
+      Value::Document(_) => 3,
+    }
+  }
+}
+
+impl Hash for Value {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    state.write_u8(self.rank());
+    match self {
+      Value::Nil => {}
+      Value::Atom(atom) => atom.hash(state),
+      Value::List(list) => list.hash(state),
+      Value::Document(document) => {
+        state.write_usize(document.len());
+        for entry in sorted_entries(document) {
+          entry.hash(state);
+        }
+      }
+    }
+  }
+}
+
+impl Ord for Value {
+  /// Total order of values: nil, then atoms, lists and documents. Atoms
+  /// compare as strings, lists element-wise and documents by their entries
+  /// sorted by key.
+  ///
+  /// ```
+  /// # use atto::Value::{self, Atom, List, Nil};
+  /// let a = Atom("a".to_owned());
+  /// let b = Atom("b".to_owned());
+  /// let mut values = vec![List(vec![b.clone()]), b.clone(), Nil, a.clone()];
+  /// values.sort();
+  ///
+  /// assert_eq!(values, vec![Nil, a, b.clone(), List(vec![b])]);
+  /// ```
+  fn cmp(&self, other: &Self) -> Ordering {
+    match (self, other) {
+      (Value::Atom(a), Value::Atom(b)) => a.cmp(b),
+      (Value::List(a), Value::List(b)) => a.cmp(b),
+      (Value::Document(a), Value::Document(b)) => {
+        sorted_entries(a).cmp(&sorted_entries(b))
+      }
+      (a, b) => a.rank().cmp(&b.rank()),
+    }
+  }
+}
+
+impl PartialOrd for Value {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+// Same character class as the lexer's bare rule
+static BARE: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(r#"^[^\(\):"\\#\p{Cc}\p{Cn}\p{Co}\pZ]+$"#).unwrap()
+});
+
+/// Whether an atom can be written without quotes.
+///
+/// ```
+/// # use atto::value::is_bare;
+/// assert!(is_bare("John"));
+/// assert!(!is_bare("x ray"));
+/// assert!(!is_bare(""));
+/// ```
+pub fn is_bare(atom: &str) -> bool { BARE.is_match(atom) }
+
+// Characters which cannot appear verbatim in a string
+static ESCAPE: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#"[[\p{Cc}\p{Cn}\p{Co}\pZ"\\]--[ ]]"#).unwrap());
+
+/// Format an atom, as a string with escapes if it cannot be bare.
+///
+/// ```
+/// # use atto::value::format_atom;
+/// assert_eq!(format_atom("x ray"), r#""x ray""#);
+/// assert_eq!(format_atom("a\"b\n"), r#""a\"b\n""#);
+/// ```
+pub fn format_atom(atom: &str) -> String {
+  if is_bare(atom) {
+    return atom.to_owned();
+  }
+
+  let escaped = ESCAPE.replace_all(atom, |caps: &Captures| {
+    match caps[0].chars().next().unwrap_or_default() {
+      '"' => r#"\""#.to_owned(),
+      '\\' => r"\\".to_owned(),
+      '\x1b' => r"\e".to_owned(),
+      '\n' => r"\n".to_owned(),
+      '\r' => r"\r".to_owned(),
+      '\t' => r"\t".to_owned(),
+      '\0' => r"\0".to_owned(),
+      c => format!("\\u{{{:03x}}}", c as u32),
+    }
+  });
+
+  format!("\"{escaped}\"")
+}
 
 fn format_list(list: &[Value]) -> String {
   let list = list.iter().map(|v| v.format());
@@ -90,9 +217,9 @@ fn format_entry(key: &str, value: &Value) -> String {
   format!("{key}: {value}")
 }
 
-fn format_document(document: &IndexMap<String, Value>) -> String {
+pub(crate) fn format_document(document: &IndexMap<String, Value>) -> String {
   let entries = document.iter().map(|(k, v)| format_entry(k, v));
   let entries = entries.collect::<Vec<String>>().join(" ");
 
-  format!("({entries}")
+  format!("({entries})")
 }
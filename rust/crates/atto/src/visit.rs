@@ -0,0 +1,487 @@
+//! Traversal and transformation of value trees
+//!
+//! [`Visit`] walks a value, [`VisitMut`] walks it for changes in place, and
+//! [`Fold`] rebuilds it, which can also rename or remove entries. Every
+//! method gets the path of its value and has a default which walks on, so
+//! a visitor only implements the methods for the kinds it cares about.
+//! Returning [`ControlFlow::Break`] ends the traversal early.
+//!
+//! The path is the one of the walk: visitors which change it must restore
+//! it before they return.
+
+use crate::path::{Path, Segment};
+use crate::value::{Atom, Document, Key, List, Value};
+use std::ops::ControlFlow::{self, Continue};
+
+/// Read-only traversal of a value tree
+///
+/// ```
+/// # use atto::{path::Path, visit::Visit};
+/// # use std::ops::ControlFlow::{self, Break, Continue};
+/// // The path of the first atom containing a string
+/// struct Find(&'static str);
+///
+/// impl Visit for Find {
+///   type Break = Path;
+///
+///   fn visit_atom(&mut self, path: &mut Path, atom: &str) -> ControlFlow<Path> {
+///     if atom.contains(self.0) {
+///       Break(path.clone())
+///     } else {
+///       Continue(())
+///     }
+///   }
+/// }
+///
+/// let value = atto::parse("a: (b: x c: (y xyz))").unwrap();
+/// let found = value.visit(&mut Find("yz"));
+/// assert_eq!(found.break_value().unwrap().to_string(), "a.c.1");
+/// ```
+pub trait Visit {
+  type Break;
+
+  fn visit_value(
+    &mut self,
+    path: &mut Path,
+    value: &Value,
+  ) -> ControlFlow<Self::Break> {
+    walk_value(self, path, value)
+  }
+
+  fn visit_nil(&mut self, _path: &mut Path) -> ControlFlow<Self::Break> {
+    Continue(())
+  }
+
+  fn visit_atom(
+    &mut self,
+    _path: &mut Path,
+    _atom: &str,
+  ) -> ControlFlow<Self::Break> {
+    Continue(())
+  }
+
+  fn visit_list(
+    &mut self,
+    path: &mut Path,
+    list: &[Value],
+  ) -> ControlFlow<Self::Break> {
+    walk_list(self, path, list)
+  }
+
+  fn visit_document(
+    &mut self,
+    path: &mut Path,
+    document: &Document,
+  ) -> ControlFlow<Self::Break> {
+    walk_document(self, path, document)
+  }
+
+  /// Visit an entry, `path` already ends with its key.
+  fn visit_entry(
+    &mut self,
+    path: &mut Path,
+    _key: &str,
+    value: &Value,
+  ) -> ControlFlow<Self::Break> {
+    self.visit_value(path, value)
+  }
+}
+
+/// Visit the value with the method for its kind.
+pub fn walk_value<V: Visit + ?Sized>(
+  visitor: &mut V,
+  path: &mut Path,
+  value: &Value,
+) -> ControlFlow<V::Break> {
+  match value {
+    Value::Nil => visitor.visit_nil(path),
+    Value::Atom(atom) => visitor.visit_atom(path, atom),
+    Value::List(list) => visitor.visit_list(path, list),
+    Value::Document(document) => visitor.visit_document(path, document),
+  }
+}
+
+/// Visit the elements of a list.
+pub fn walk_list<V: Visit + ?Sized>(
+  visitor: &mut V,
+  path: &mut Path,
+  list: &[Value],
+) -> ControlFlow<V::Break> {
+  for (index, value) in list.iter().enumerate() {
+    path.push(Segment::Index(index));
+    let flow = visitor.visit_value(path, value);
+    path.pop();
+    flow?;
+  }
+  Continue(())
+}
+
+/// Visit the entries of a document.
+pub fn walk_document<V: Visit + ?Sized>(
+  visitor: &mut V,
+  path: &mut Path,
+  document: &Document,
+) -> ControlFlow<V::Break> {
+  for (key, value) in document {
+    path.push(Segment::Key(key.clone()));
+    let flow = visitor.visit_entry(path, key, value);
+    path.pop();
+    flow?;
+  }
+  Continue(())
+}
+
+/// Traversal of a value tree for changes in place
+///
+/// ```
+/// # use atto::{path::Path, visit::VisitMut};
+/// # use std::ops::ControlFlow::{self, Continue};
+/// struct Redact;
+///
+/// impl VisitMut for Redact {
+///   type Break = ();
+///
+///   fn visit_entry_mut(
+///     &mut self,
+///     path: &mut Path,
+///     key: &str,
+///     value: &mut atto::Value,
+///   ) -> ControlFlow<()> {
+///     if key == "password" {
+///       *value = atto::Value::Atom("***".to_owned());
+///       return Continue(());
+///     }
+///     self.visit_value_mut(path, value)
+///   }
+/// }
+///
+/// let mut value = atto::parse("db: (user: me password: secret)").unwrap();
+/// value.visit_mut(&mut Redact);
+/// assert_eq!(value.to_string(), "(db: (user: me password: ***))");
+/// ```
+pub trait VisitMut {
+  type Break;
+
+  fn visit_value_mut(
+    &mut self,
+    path: &mut Path,
+    value: &mut Value,
+  ) -> ControlFlow<Self::Break> {
+    walk_value_mut(self, path, value)
+  }
+
+  fn visit_nil_mut(&mut self, _path: &mut Path) -> ControlFlow<Self::Break> {
+    Continue(())
+  }
+
+  fn visit_atom_mut(
+    &mut self,
+    _path: &mut Path,
+    _atom: &mut Atom,
+  ) -> ControlFlow<Self::Break> {
+    Continue(())
+  }
+
+  fn visit_list_mut(
+    &mut self,
+    path: &mut Path,
+    list: &mut List,
+  ) -> ControlFlow<Self::Break> {
+    walk_list_mut(self, path, list)
+  }
+
+  fn visit_document_mut(
+    &mut self,
+    path: &mut Path,
+    document: &mut Document,
+  ) -> ControlFlow<Self::Break> {
+    walk_document_mut(self, path, document)
+  }
+
+  /// Visit an entry, `path` already ends with its key.
+  fn visit_entry_mut(
+    &mut self,
+    path: &mut Path,
+    _key: &str,
+    value: &mut Value,
+  ) -> ControlFlow<Self::Break> {
+    self.visit_value_mut(path, value)
+  }
+}
+
+/// Visit the value with the method for its kind.
+pub fn walk_value_mut<V: VisitMut + ?Sized>(
+  visitor: &mut V,
+  path: &mut Path,
+  value: &mut Value,
+) -> ControlFlow<V::Break> {
+  match value {
+    Value::Nil => visitor.visit_nil_mut(path),
+    Value::Atom(atom) => visitor.visit_atom_mut(path, atom),
+    Value::List(list) => visitor.visit_list_mut(path, list),
+    Value::Document(document) => visitor.visit_document_mut(path, document),
+  }
+}
+
+/// Visit the elements of a list.
+pub fn walk_list_mut<V: VisitMut + ?Sized>(
+  visitor: &mut V,
+  path: &mut Path,
+  list: &mut List,
+) -> ControlFlow<V::Break> {
+  for (index, value) in list.iter_mut().enumerate() {
+    path.push(Segment::Index(index));
+    let flow = visitor.visit_value_mut(path, value);
+    path.pop();
+    flow?;
+  }
+  Continue(())
+}
+
+/// Visit the entries of a document.
+pub fn walk_document_mut<V: VisitMut + ?Sized>(
+  visitor: &mut V,
+  path: &mut Path,
+  document: &mut Document,
+) -> ControlFlow<V::Break> {
+  for (key, value) in document.iter_mut() {
+    path.push(Segment::Key(key.clone()));
+    let flow = visitor.visit_entry_mut(path, key, value);
+    path.pop();
+    flow?;
+  }
+  Continue(())
+}
+
+/// Transformation of a value tree into a new one
+///
+/// ```
+/// # use atto::{path::Path, visit::Fold, Value};
+/// # use std::ops::ControlFlow::{self, Continue};
+/// // Remove nil entries and make keys lowercase
+/// struct Normalize;
+///
+/// impl Fold for Normalize {
+///   type Break = ();
+///
+///   fn fold_entry(
+///     &mut self,
+///     path: &mut Path,
+///     key: String,
+///     value: Value,
+///   ) -> ControlFlow<(), Option<(String, Value)>> {
+///     if value == Value::Nil {
+///       return Continue(None);
+///     }
+///     let value = self.fold_value(path, value)?;
+///     Continue(Some((key.to_lowercase(), value)))
+///   }
+/// }
+///
+/// let value = atto::parse("A: (B: x C: y)").unwrap();
+/// let mut document = atto::value::Document::new();
+/// document.insert("C".to_owned(), Value::Nil);
+/// let value = Value::List(vec![value, Value::Document(document)]);
+///
+/// let folded = value.fold(&mut Normalize).continue_value().unwrap();
+/// assert_eq!(folded.to_string(), "((a: (b: x c: y)) ())");
+/// ```
+pub trait Fold {
+  type Break;
+
+  fn fold_value(
+    &mut self,
+    path: &mut Path,
+    value: Value,
+  ) -> ControlFlow<Self::Break, Value> {
+    match value {
+      Value::Nil => self.fold_nil(path),
+      Value::Atom(atom) => self.fold_atom(path, atom),
+      Value::List(list) => self.fold_list(path, list),
+      Value::Document(document) => self.fold_document(path, document),
+    }
+  }
+
+  fn fold_nil(&mut self, _path: &mut Path) -> ControlFlow<Self::Break, Value> {
+    Continue(Value::Nil)
+  }
+
+  fn fold_atom(
+    &mut self,
+    _path: &mut Path,
+    atom: Atom,
+  ) -> ControlFlow<Self::Break, Value> {
+    Continue(Value::Atom(atom))
+  }
+
+  fn fold_list(
+    &mut self,
+    path: &mut Path,
+    list: List,
+  ) -> ControlFlow<Self::Break, Value> {
+    fold_elements(self, path, list).map_continue(Value::List)
+  }
+
+  fn fold_document(
+    &mut self,
+    path: &mut Path,
+    document: Document,
+  ) -> ControlFlow<Self::Break, Value> {
+    fold_entries(self, path, document).map_continue(Value::Document)
+  }
+
+  /// Fold an entry, `path` already ends with its key. Returning `None`
+  /// removes the entry, a returned key replaces the old one.
+  fn fold_entry(
+    &mut self,
+    path: &mut Path,
+    key: Key,
+    value: Value,
+  ) -> ControlFlow<Self::Break, Option<(Key, Value)>> {
+    let value = self.fold_value(path, value)?;
+    Continue(Some((key, value)))
+  }
+}
+
+/// Fold the elements of a list.
+pub fn fold_elements<F: Fold + ?Sized>(
+  folder: &mut F,
+  path: &mut Path,
+  list: List,
+) -> ControlFlow<F::Break, List> {
+  let mut folded = List::with_capacity(list.len());
+  for (index, value) in list.into_iter().enumerate() {
+    path.push(Segment::Index(index));
+    let flow = folder.fold_value(path, value);
+    path.pop();
+    folded.push(flow?);
+  }
+  Continue(folded)
+}
+
+/// Fold the entries of a document. If keys are renamed to the same key,
+/// the last entry wins.
+pub fn fold_entries<F: Fold + ?Sized>(
+  folder: &mut F,
+  path: &mut Path,
+  document: Document,
+) -> ControlFlow<F::Break, Document> {
+  let mut folded = Document::with_capacity(document.len());
+  for (key, value) in document {
+    path.push(Segment::Key(key.clone()));
+    let flow = folder.fold_entry(path, key, value);
+    path.pop();
+    folded.extend(flow?);
+  }
+  Continue(folded)
+}
+
+impl Value {
+  /// Walk the value with `visitor`, starting at the root path.
+  pub fn visit<V: Visit>(&self, visitor: &mut V) -> ControlFlow<V::Break> {
+    visitor.visit_value(&mut Path::root(), self)
+  }
+
+  /// Walk the value with `visitor` for changes in place.
+  pub fn visit_mut<V: VisitMut>(
+    &mut self,
+    visitor: &mut V,
+  ) -> ControlFlow<V::Break> {
+    visitor.visit_value_mut(&mut Path::root(), self)
+  }
+
+  /// Transform the value with `folder`.
+  pub fn fold<F: Fold>(self, folder: &mut F) -> ControlFlow<F::Break, Value> {
+    folder.fold_value(&mut Path::root(), self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::ops::ControlFlow::Break;
+
+  #[derive(Default)]
+  struct Paths(Vec<String>);
+
+  impl Visit for Paths {
+    type Break = ();
+
+    fn visit_value(
+      &mut self,
+      path: &mut Path,
+      value: &Value,
+    ) -> ControlFlow<()> {
+      self.0.push(path.to_string());
+      walk_value(self, path, value)
+    }
+  }
+
+  #[test]
+  fn test_paths() {
+    let value = crate::parse("a: (x (b: y)) c: ()").unwrap();
+    let mut paths = Paths::default();
+    assert_eq!(value.visit(&mut paths), Continue(()));
+    assert_eq!(paths.0, [".", "a", "a.0", "a.1", "a.1.b", "c"]);
+  }
+
+  #[test]
+  fn test_break() {
+    // Stop at the third atom
+    struct Count(usize);
+
+    impl VisitMut for Count {
+      type Break = usize;
+
+      fn visit_atom_mut(
+        &mut self,
+        _: &mut Path,
+        atom: &mut Atom,
+      ) -> ControlFlow<usize> {
+        self.0 += 1;
+        atom.push('!');
+        if self.0 == 3 {
+          Break(self.0)
+        } else {
+          Continue(())
+        }
+      }
+    }
+
+    let mut value = crate::parse("a: (1 2 (3 4)) b: 5").unwrap();
+    assert_eq!(value.visit_mut(&mut Count(0)), Break(3));
+    assert_eq!(value.to_string(), "(a: (1! 2! (3! 4)) b: 5)");
+  }
+
+  #[test]
+  fn test_fold_path() {
+    // Replace atoms by their path, and fail on nil
+    struct ToPath;
+
+    impl Fold for ToPath {
+      type Break = String;
+
+      fn fold_atom(
+        &mut self,
+        path: &mut Path,
+        _: Atom,
+      ) -> ControlFlow<String, Value> {
+        Continue(Value::Atom(path.to_string()))
+      }
+
+      fn fold_nil(&mut self, path: &mut Path) -> ControlFlow<String, Value> {
+        Break(path.to_string())
+      }
+    }
+
+    let value = crate::parse("a: (x (b: y))").unwrap();
+    let folded = value.fold(&mut ToPath);
+    assert_eq!(
+      folded.continue_value().unwrap().to_string(),
+      "(a: (a.0 (b: a.1.b)))"
+    );
+
+    let value = Value::List(vec![Value::Atom("x".to_owned()), Value::Nil]);
+    assert_eq!(value.fold(&mut ToPath), Break("1".to_owned()));
+  }
+}
@@ -0,0 +1,297 @@
+//! Reloading a configuration file while a program runs
+//!
+//! A [`Watched`] handle holds the value decoded from a file. Polling reads
+//! the file again and, if it changed and is still valid, swaps in the new
+//! value. An invalid file keeps the old value. Subscribers are told about
+//! every reload with the [`Diff`] of the values. Polling only reads the
+//! file, so it works on every platform and file system.
+
+use crate::config::{FileSystem, OsFileSystem};
+use crate::decode::{self, DecodeError, FromAtto};
+use crate::diff::{diff, Diff};
+use crate::value::Value;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+use std::{fmt, io, thread};
+
+#[derive(Debug)]
+pub enum WatchErrorKind {
+  Io(io::Error),
+  Decode(DecodeError),
+}
+
+impl fmt::Display for WatchErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      WatchErrorKind::Io(err) => write!(f, "{err}"),
+      WatchErrorKind::Decode(err) => write!(f, "{err}"),
+    }
+  }
+}
+
+/// A file which could not be read or decoded
+#[derive(Debug)]
+pub struct WatchError {
+  pub path: PathBuf,
+  pub kind: WatchErrorKind,
+}
+
+impl fmt::Display for WatchError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.path.display(), self.kind)
+  }
+}
+
+impl std::error::Error for WatchError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match &self.kind {
+      WatchErrorKind::Io(err) => Some(err),
+      WatchErrorKind::Decode(err) => Some(err),
+    }
+  }
+}
+
+/// What subscribers are sent after a poll
+#[derive(Clone, Debug)]
+pub enum Reload {
+  /// The new value is in use
+  Changed(Diff),
+  /// The file changed but is invalid, the old value stays in use
+  Rejected(DecodeError),
+}
+
+// The file as last read
+struct State {
+  text:        String,
+  value:       Value,
+  subscribers: Vec<Sender<Reload>>,
+}
+
+struct Inner<T> {
+  path:    PathBuf,
+  fs:      Box<dyn FileSystem + Send + Sync>,
+  current: RwLock<Arc<T>>,
+  state:   Mutex<State>,
+}
+
+/// A value decoded from a file, reloaded when polled.
+///
+/// Clones share the value. Readers get the value as an [`Arc`], so a
+/// reload never changes a value while it is used.
+///
+/// ```no_run
+/// use atto::watch::{Reload, Watched};
+/// use std::collections::BTreeMap;
+/// use std::time::Duration;
+///
+/// let watched = Watched::<BTreeMap<String, u16>>::open("ports.atto")?;
+/// let reloads = watched.subscribe();
+/// watched.spawn(Duration::from_secs(2));
+///
+/// println!("http: {:?}", watched.get().get("http"));
+/// for reload in reloads {
+///   match reload {
+///     Reload::Changed(diff) => println!("reloaded:\n{diff}"),
+///     Reload::Rejected(err) => println!("ports.atto: {err}"),
+///   }
+/// }
+/// # Ok::<(), atto::watch::WatchError>(())
+/// ```
+pub struct Watched<T> {
+  inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Watched<T> {
+  fn clone(&self) -> Self { Watched { inner: self.inner.clone() } }
+}
+
+impl<T> fmt::Debug for Watched<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Watched").field("path", &self.inner.path).finish()
+  }
+}
+
+impl<T: FromAtto + Send + Sync + 'static> Watched<T> {
+  /// Read and decode the file at `path`.
+  pub fn open(path: impl Into<PathBuf>) -> Result<Watched<T>, WatchError> {
+    Watched::with_filesystem(path, OsFileSystem)
+  }
+
+  /// Read and decode the file at `path` in `fs`.
+  pub fn with_filesystem(
+    path: impl Into<PathBuf>,
+    fs: impl FileSystem + Send + Sync + 'static,
+  ) -> Result<Watched<T>, WatchError> {
+    let path = path.into();
+    let text = read(&fs, &path)?;
+    let (value, decoded) = decode_text(&text).map_err(|err| WatchError {
+      path: path.clone(),
+      kind: WatchErrorKind::Decode(err),
+    })?;
+    let state = State { text, value, subscribers: vec![] };
+    let inner = Inner {
+      path,
+      fs: Box::new(fs),
+      current: RwLock::new(Arc::new(decoded)),
+      state: Mutex::new(state),
+    };
+    Ok(Watched { inner: Arc::new(inner) })
+  }
+
+  /// The current value.
+  pub fn get(&self) -> Arc<T> { self.inner.current.read().unwrap().clone() }
+
+  /// Receive a [`Reload`] for every later change of the file.
+  pub fn subscribe(&self) -> Receiver<Reload> {
+    let (sender, receiver) = mpsc::channel();
+    self.inner.state.lock().unwrap().subscribers.push(sender);
+    receiver
+  }
+
+  /// Read the file again and swap in its value if it changed.
+  ///
+  /// Returns the diff if the value changed. A changed text with the same
+  /// value, like an edited comment, is no change. On error the old value
+  /// stays in use. Subscribers are told about invalid texts, but not about
+  /// files which cannot be read, like while an editor replaces the file.
+  pub fn poll(&self) -> Result<Option<Diff>, WatchError> {
+    let inner = &self.inner;
+    let mut state = inner.state.lock().unwrap();
+    let text = read(inner.fs.as_ref(), &inner.path)?;
+    if text == state.text {
+      return Ok(None);
+    }
+    state.text = text;
+    let (value, decoded) = match decode_text(&state.text) {
+      Ok(decoded) => decoded,
+      Err(err) => {
+        notify(&mut state, Reload::Rejected(err.clone()));
+        let kind = WatchErrorKind::Decode(err);
+        return Err(WatchError { path: inner.path.clone(), kind });
+      }
+    };
+    let changes = diff(&state.value, &value);
+    if changes.changes.is_empty() {
+      return Ok(None);
+    }
+    state.value = value;
+    *inner.current.write().unwrap() = Arc::new(decoded);
+    notify(&mut state, Reload::Changed(changes.clone()));
+    Ok(Some(changes))
+  }
+
+  /// Poll every `interval` on a new thread, until all handles are dropped.
+  pub fn spawn(&self, interval: Duration) -> thread::JoinHandle<()> {
+    let weak = Arc::downgrade(&self.inner);
+    thread::spawn(move || loop {
+      thread::sleep(interval);
+      let Some(inner) = Weak::upgrade(&weak) else { return };
+      // Subscribers are told about invalid texts
+      let _ = Watched { inner }.poll();
+    })
+  }
+}
+
+fn read(
+  fs: &dyn FileSystem,
+  path: &std::path::Path,
+) -> Result<String, WatchError> {
+  fs.read(path).map_err(|err| WatchError {
+    path: path.to_owned(),
+    kind: WatchErrorKind::Io(err),
+  })
+}
+
+fn decode_text<T: FromAtto>(text: &str) -> Result<(Value, T), DecodeError> {
+  let root = decode::parse_root(text)?;
+  let value = root.to_value();
+  let decoded = T::from_atto(&value).map_err(|err| err.locate(text, &root))?;
+  Ok((value, decoded))
+}
+
+// Send `reload` to all subscribers, dropping those who are gone
+fn notify(state: &mut State, reload: Reload) {
+  state.subscribers.retain(|sender| sender.send(reload.clone()).is_ok());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::MemoryFileSystem;
+
+  // A file system whose files change while watched
+  #[derive(Clone, Default)]
+  struct Shared(Arc<Mutex<MemoryFileSystem>>);
+
+  impl Shared {
+    fn write(&self, text: &str) {
+      self.0.lock().unwrap().insert("app.atto", text);
+    }
+  }
+
+  impl FileSystem for Shared {
+    fn read(&self, path: &std::path::Path) -> io::Result<String> {
+      self.0.lock().unwrap().read(path)
+    }
+  }
+
+  type Ports = std::collections::BTreeMap<String, u16>;
+
+  #[test]
+  fn test_poll() {
+    let fs = Shared::default();
+    fs.write("http: 80");
+    let watched = Watched::<Ports>::with_filesystem("app.atto", fs.clone());
+    let watched = watched.unwrap();
+    let reloads = watched.subscribe();
+    let before = watched.get();
+    assert!(watched.poll().unwrap().is_none());
+
+    fs.write("# ports\nhttp: 80");
+    assert!(watched.poll().unwrap().is_none());
+
+    fs.write("http: 8080\nhttps: 443");
+    let changes = watched.poll().unwrap().unwrap();
+    assert_eq!(changes.to_string(), "~ http: 80 -> 8080\n+ https: 443\n");
+    assert_eq!(before["http"], 80);
+    assert_eq!(watched.get()["http"], 8080);
+    assert!(
+      matches!(reloads.try_recv(), Ok(Reload::Changed(d)) if d == changes)
+    );
+
+    fs.write("http: 8080\nhttps: 70000");
+    let err = watched.poll().unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "app.atto: https: invalid integer: out of range at 2:8"
+    );
+    assert_eq!(watched.get()["https"], 443);
+    assert!(matches!(reloads.try_recv(), Ok(Reload::Rejected(_))));
+    // The invalid text is only reported once
+    assert!(watched.poll().unwrap().is_none());
+
+    drop(reloads);
+    fs.write("https: 443");
+    let changes = watched.poll().unwrap().unwrap();
+    assert_eq!(changes.to_string(), "- http: 8080\n");
+    assert_eq!(watched.inner.state.lock().unwrap().subscribers.len(), 0);
+  }
+
+  #[test]
+  fn test_spawn() {
+    let fs = Shared::default();
+    fs.write("http: 80");
+    let watched =
+      Watched::<Ports>::with_filesystem("app.atto", fs.clone()).unwrap();
+    let reloads = watched.subscribe();
+    let thread = watched.spawn(Duration::from_millis(1));
+    fs.write("http: 81");
+    let reload = reloads.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(matches!(reload, Reload::Changed(_)));
+    assert_eq!(watched.get()["http"], 81);
+    drop(watched);
+    thread.join().unwrap();
+  }
+}
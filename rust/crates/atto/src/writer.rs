@@ -0,0 +1,562 @@
+//! Streaming output of atto texts
+//!
+//! An [`AttoWriter`] writes a text piece by piece, without building a
+//! [`Value`] first, and gives the same text as the pretty printer
+//! [`pretty_with`](crate::format::pretty_with). To decide whether a list
+//! or document fits on one line, it holds back the beginning of the value
+//! until it ends or grows wider than the line, so it never buffers more
+//! than a line.
+//!
+//! Doc comments are written with [`AttoWriter::doc`] before a key, or
+//! from [`Docs`] with [`AttoWriter::value_with_docs`].
+
+use crate::format::{write_doc, Docs, Options};
+use crate::path::{Path, Segment};
+use crate::value::{format_atom, Value};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Debug)]
+pub enum WriterError {
+  Io(io::Error),
+  /// A value in a document where a key was expected
+  ExpectedKey,
+  /// The end of a document after a key without its value
+  ExpectedValue,
+  KeyOutsideDocument,
+  DuplicateKey(String),
+  /// An end without an open list or document
+  UnmatchedEnd,
+  /// The number of lists and documents not ended when finishing
+  Unclosed(usize),
+  /// A value after the root value
+  SecondRoot,
+}
+
+impl fmt::Display for WriterError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      WriterError::Io(err) => write!(f, "{err}"),
+      WriterError::ExpectedKey => write!(f, "expected key"),
+      WriterError::ExpectedValue => write!(f, "expected value after key"),
+      WriterError::KeyOutsideDocument => write!(f, "key outside of document"),
+      WriterError::DuplicateKey(key) => write!(f, "duplicate key `{key}`"),
+      WriterError::UnmatchedEnd => write!(f, "end without list or document"),
+      WriterError::Unclosed(n) => write!(f, "{n} lists or documents not ended"),
+      WriterError::SecondRoot => write!(f, "value after the root value"),
+    }
+  }
+}
+
+impl std::error::Error for WriterError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      WriterError::Io(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for WriterError {
+  fn from(err: io::Error) -> Self { WriterError::Io(err) }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+  Document,
+  List,
+}
+
+#[derive(Clone, Debug)]
+enum Event {
+  Begin(Kind),
+  Key(String),
+  Doc(String),
+  /// A formatted atom or nil
+  Atom(String),
+  End,
+}
+
+#[derive(Debug)]
+struct Frame {
+  kind:  Kind,
+  /// Indentation level of the line the list or document starts on
+  level: usize,
+  /// The root document, written without parentheses
+  root:  bool,
+  items: usize,
+  /// A key has been written and its value not yet
+  key:   bool,
+  keys:  HashSet<String>,
+}
+
+impl Frame {
+  fn child_level(&self) -> usize {
+    match self.root {
+      true => self.level,
+      false => self.level + 1,
+    }
+  }
+}
+
+// A list or document which is written on one line if it fits
+#[derive(Debug)]
+struct Pending {
+  /// Length of the stack with the list or document on top
+  depth:  usize,
+  /// The width left on its line
+  budget: usize,
+  text:   String,
+  width:  usize,
+  /// Events after its beginning, to write it again if it does not fit
+  events: Vec<Event>,
+}
+
+/// Writes an atto text to an [`io::Write`].
+///
+/// Calls are checked to nest correctly. A document at the root is written
+/// as root document, one entry per line. Writes are small, so a
+/// [`io::BufWriter`] helps with files and sockets.
+///
+/// ```
+/// use atto::writer::AttoWriter;
+///
+/// let mut writer = AttoWriter::new(Vec::new());
+/// writer.begin_document()?;
+/// writer.key("name")?;
+/// writer.atom("John Doe")?;
+/// writer.key("powers")?;
+/// writer.begin_list()?;
+/// writer.atom("fly")?;
+/// writer.atom("x ray")?;
+/// writer.end()?;
+/// writer.end()?;
+/// let text = writer.finish()?;
+///
+/// assert_eq!(text, b"name: \"John Doe\"\npowers: (fly \"x ray\")\n");
+/// # Ok::<(), atto::writer::WriterError>(())
+/// ```
+#[derive(Debug)]
+pub struct AttoWriter<W: Write> {
+  out:     W,
+  options: Options,
+  stack:   Vec<Frame>,
+  pending: Option<Pending>,
+  /// Width of the key before the value on the current line
+  prefix:  usize,
+  done:    bool,
+}
+
+impl<W: Write> AttoWriter<W> {
+  pub fn new(out: W) -> AttoWriter<W> {
+    AttoWriter::with_options(out, Options::default())
+  }
+
+  pub fn with_options(out: W, options: Options) -> AttoWriter<W> {
+    AttoWriter {
+      out,
+      options,
+      stack: vec![],
+      pending: None,
+      prefix: 0,
+      done: false,
+    }
+  }
+
+  pub fn begin_document(&mut self) -> Result<(), WriterError> {
+    self.check_value()?;
+    self.emit(Event::Begin(Kind::Document))
+  }
+
+  pub fn begin_list(&mut self) -> Result<(), WriterError> {
+    self.check_value()?;
+    self.emit(Event::Begin(Kind::List))
+  }
+
+  pub fn key(&mut self, key: &str) -> Result<(), WriterError> {
+    self.check_key()?;
+    if self.stack.last().unwrap().keys.contains(key) {
+      return Err(WriterError::DuplicateKey(key.to_owned()));
+    }
+    self.emit(Event::Key(key.to_owned()))
+  }
+
+  /// Write a doc comment for the next key, as `#` lines before it. The
+  /// document containing it is broken into lines.
+  pub fn doc(&mut self, doc: &str) -> Result<(), WriterError> {
+    self.check_key()?;
+    self.emit(Event::Doc(doc.to_owned()))
+  }
+
+  pub fn atom(&mut self, atom: &str) -> Result<(), WriterError> {
+    self.check_value()?;
+    self.emit(Event::Atom(format_atom(atom)))
+  }
+
+  pub fn nil(&mut self) -> Result<(), WriterError> {
+    self.check_value()?;
+    self.emit(Event::Atom("#nil".to_owned()))
+  }
+
+  /// Write a whole value.
+  pub fn value(&mut self, value: &Value) -> Result<(), WriterError> {
+    self.value_with_docs(value, &Docs::new())
+  }
+
+  /// Write a whole value with the doc comments of its entries, by paths
+  /// relative to the value.
+  pub fn value_with_docs(
+    &mut self,
+    value: &Value,
+    docs: &Docs,
+  ) -> Result<(), WriterError> {
+    self.value_at(value, docs, &mut Path::root())
+  }
+
+  fn value_at(
+    &mut self,
+    value: &Value,
+    docs: &Docs,
+    path: &mut Path,
+  ) -> Result<(), WriterError> {
+    match value {
+      Value::Nil => self.nil(),
+      Value::Atom(atom) => self.atom(atom),
+      Value::List(list) => {
+        self.begin_list()?;
+        for (index, value) in list.iter().enumerate() {
+          path.push(Segment::Index(index));
+          self.value_at(value, docs, path)?;
+          path.pop();
+        }
+        self.end()
+      }
+      Value::Document(document) => {
+        self.begin_document()?;
+        for (key, value) in document {
+          path.push(Segment::Key(key.clone()));
+          if let Some(doc) = docs.get(path) {
+            self.doc(doc)?;
+          }
+          self.key(key)?;
+          self.value_at(value, docs, path)?;
+          path.pop();
+        }
+        self.end()
+      }
+    }
+  }
+
+  /// End the innermost list or document.
+  pub fn end(&mut self) -> Result<(), WriterError> {
+    match self.stack.last() {
+      None => Err(WriterError::UnmatchedEnd),
+      Some(frame) if frame.key => Err(WriterError::ExpectedValue),
+      Some(_) => self.emit(Event::End),
+    }
+  }
+
+  /// Check that every list and document has ended, flush and return the
+  /// output.
+  pub fn finish(mut self) -> Result<W, WriterError> {
+    if !self.stack.is_empty() {
+      return Err(WriterError::Unclosed(self.stack.len()));
+    }
+    self.out.flush()?;
+    Ok(self.out)
+  }
+
+  fn check_key(&self) -> Result<(), WriterError> {
+    match self.stack.last() {
+      Some(frame) if frame.kind == Kind::Document && frame.key => {
+        Err(WriterError::ExpectedValue)
+      }
+      Some(frame) if frame.kind == Kind::Document => Ok(()),
+      _ => Err(WriterError::KeyOutsideDocument),
+    }
+  }
+
+  fn check_value(&self) -> Result<(), WriterError> {
+    match self.stack.last() {
+      None if self.done => Err(WriterError::SecondRoot),
+      Some(frame) if frame.kind == Kind::Document && !frame.key => {
+        Err(WriterError::ExpectedKey)
+      }
+      _ => Ok(()),
+    }
+  }
+
+  // Update the nesting for `event`
+  fn apply(&mut self, event: &Event) {
+    match event {
+      Event::Begin(kind) => {
+        let level = self.stack.last().map_or(0, Frame::child_level);
+        self.stack.push(Frame {
+          kind: *kind,
+          level,
+          root: self.stack.is_empty() && *kind == Kind::Document,
+          items: 0,
+          key: false,
+          keys: HashSet::new(),
+        });
+      }
+      Event::Key(key) => {
+        let frame = self.stack.last_mut().unwrap();
+        frame.key = true;
+        frame.keys.insert(key.clone());
+      }
+      Event::Doc(_) => {}
+      Event::Atom(_) | Event::End => {
+        if let Event::End = event {
+          self.stack.pop();
+        }
+        match self.stack.last_mut() {
+          Some(frame) => {
+            frame.items += 1;
+            frame.key = false;
+          }
+          None => self.done = true,
+        }
+      }
+    }
+  }
+
+  fn emit(&mut self, event: Event) -> Result<(), WriterError> {
+    if self.pending.is_some() {
+      return self.hold(event);
+    }
+
+    match &event {
+      Event::Begin(Kind::Document) if self.stack.is_empty() => {}
+      Event::Begin(_) => {
+        let used = self.start_value()?;
+        let budget = self.options.width.saturating_sub(used);
+        self.pending = Some(Pending {
+          depth: self.stack.len() + 1,
+          budget,
+          text: "(".to_owned(),
+          width: 1,
+          events: vec![],
+        });
+      }
+      Event::Key(key) => {
+        let level = self.stack.last().map_or(0, Frame::child_level);
+        let key = format_atom(key);
+        self.indent(level)?;
+        write!(self.out, "{key}: ")?;
+        self.prefix = key.chars().count() + 2;
+      }
+      Event::Doc(doc) => {
+        let level = self.stack.last().map_or(0, Frame::child_level);
+        let mut lines = String::new();
+        write_doc(&mut lines, doc, level, &self.options);
+        self.out.write_all(lines.as_bytes())?;
+      }
+      Event::Atom(atom) => {
+        self.start_value()?;
+        writeln!(self.out, "{atom}")?;
+      }
+      Event::End => {
+        let frame = self.stack.last().unwrap();
+        if !frame.root {
+          self.indent(frame.level)?;
+          writeln!(self.out, ")")?;
+        }
+      }
+    }
+    self.apply(&event);
+    Ok(())
+  }
+
+  // Start a value on the current line after a key, or on a new line, and
+  // return the width used before it
+  fn start_value(&mut self) -> Result<usize, WriterError> {
+    let Some(frame) = self.stack.last() else { return Ok(0) };
+    let level = frame.child_level();
+    let indent = level * self.options.indent;
+    match frame.key {
+      true => Ok(indent + self.prefix),
+      false => {
+        self.indent(level)?;
+        Ok(indent)
+      }
+    }
+  }
+
+  fn indent(&mut self, level: usize) -> Result<(), WriterError> {
+    let indent = level * self.options.indent;
+    write!(self.out, "{:indent$}", "")?;
+    Ok(())
+  }
+
+  // Add `event` to the pending list or document, and write it when it ends
+  // or does not fit on the line
+  fn hold(&mut self, event: Event) -> Result<(), WriterError> {
+    let frame = self.stack.last().unwrap();
+    let separator = match frame.items > 0 && !frame.key {
+      true => " ",
+      false => "",
+    };
+    let piece = match &event {
+      Event::Begin(_) => format!("{separator}("),
+      Event::Key(key) => format!("{separator}{}: ", format_atom(key)),
+      Event::Atom(atom) => format!("{separator}{atom}"),
+      Event::End => ")".to_owned(),
+      Event::Doc(_) => String::new(),
+    };
+    let doc = matches!(event, Event::Doc(_));
+    self.apply(&event);
+
+    let pending = self.pending.as_mut().unwrap();
+    pending.text.push_str(&piece);
+    pending.width += piece.chars().count();
+    pending.events.push(event);
+    if self.stack.len() < pending.depth {
+      writeln!(self.out, "{}", pending.text)?;
+      self.pending = None;
+    } else if doc || pending.width + 1 > pending.budget {
+      self.break_pending()?;
+    }
+    Ok(())
+  }
+
+  // Write the pending list or document on multiple lines
+  fn break_pending(&mut self) -> Result<(), WriterError> {
+    let pending = self.pending.take().unwrap();
+    self.stack.truncate(pending.depth);
+    let frame = self.stack.last_mut().unwrap();
+    frame.items = 0;
+    frame.key = false;
+    frame.keys.clear();
+    writeln!(self.out, "(")?;
+    pending.events.into_iter().try_for_each(|event| self.emit(event))
+  }
+}
+
+/// Write `value` as with [`pretty_with`](crate::format::pretty_with).
+pub fn write_value<W: Write>(
+  out: W,
+  value: &Value,
+  options: &Options,
+) -> Result<W, WriterError> {
+  let mut writer = AttoWriter::with_options(out, options.clone());
+  writer.value(value)?;
+  writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn written(value: &Value, options: &Options) -> String {
+    let out = write_value(Vec::new(), value, options).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn test_same_as_formatter() {
+    let text = r#"
+      squadName: "Super hero squad"
+      members: (
+        (name: "Molecule Man" age: 29 powers: (radiation "turning tiny"))
+        (name: "Madame Uppercut" powers: ())
+        (name: "Eternal Flame" age: 1000000 powers: (
+          immortality "heat immunity" inferno teleportation
+          "interdimensional travel"
+        ))
+      )
+      empty: ()
+      numbers: (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21)
+    "#;
+    let value = crate::parse(text).unwrap();
+    for width in [0, 10, 20, 30, 40, 60, 80, 120, 400] {
+      for indent in [0, 2, 4] {
+        let options = Options { indent, width };
+        assert_eq!(
+          written(&value, &options),
+          crate::format::pretty_with(&value, &options),
+          "width {width}, indent {indent}"
+        );
+      }
+    }
+
+    let list = crate::parse("a: (x (y z))").unwrap();
+    let Value::Document(document) = list else { panic!() };
+    let options = Options { indent: 2, width: 7 };
+    assert_eq!(written(&document["a"], &options), "(\n  x\n  (y z)\n)\n");
+    assert_eq!(written(&Value::Atom("a b".into()), &options), "\"a b\"\n");
+    let nil = Value::List(vec![Value::Nil, Value::Atom("#nil".into())]);
+    assert_eq!(written(&nil, &options), "(\n  #nil\n  \"#nil\"\n)\n");
+  }
+
+  #[test]
+  fn test_docs() {
+    let text = "# A\na: (b: 1 c: (2 (\n# D\n\n# of d\nd: 3)))\n# E\ne: (f g)";
+    let root = crate::parser::parse_tree(text).root;
+    let (value, docs) = (root.to_value(), root.docs());
+    for width in [0, 10, 80] {
+      let options = Options { indent: 2, width };
+      let mut writer = AttoWriter::with_options(Vec::new(), options.clone());
+      writer.value_with_docs(&value, &docs).unwrap();
+      assert_eq!(
+        String::from_utf8(writer.finish().unwrap()).unwrap(),
+        crate::format::pretty_with_docs(&value, &options, &docs),
+        "width {width}"
+      );
+    }
+
+    let mut writer = AttoWriter::new(Vec::new());
+    assert_eq!(
+      writer.doc("x").unwrap_err().to_string(),
+      "key outside of document"
+    );
+    writer.begin_document().unwrap();
+    writer.key("a").unwrap();
+    assert_eq!(
+      writer.doc("x").unwrap_err().to_string(),
+      "expected value after key"
+    );
+    writer.begin_document().unwrap();
+    writer.doc("first\n\nsecond").unwrap();
+    writer.key("b").unwrap();
+    writer.atom("c").unwrap();
+    writer.end().unwrap();
+    writer.end().unwrap();
+    assert_eq!(
+      writer.finish().unwrap(),
+      b"a: (\n  # first\n  #\n  # second\n  b: c\n)\n"
+    );
+  }
+
+  #[test]
+  fn test_nesting() {
+    let mut writer = AttoWriter::new(Vec::new());
+    writer.begin_document().unwrap();
+    let err = writer.atom("x").unwrap_err();
+    assert_eq!(err.to_string(), "expected key");
+    writer.key("a").unwrap();
+    let err = writer.key("b").unwrap_err();
+    assert_eq!(err.to_string(), "expected value after key");
+    let err = writer.end().unwrap_err();
+    assert_eq!(err.to_string(), "expected value after key");
+    writer.begin_list().unwrap();
+    let err = writer.key("c").unwrap_err();
+    assert_eq!(err.to_string(), "key outside of document");
+    writer.end().unwrap();
+    let err = writer.key("a").unwrap_err();
+    assert_eq!(err.to_string(), "duplicate key `a`");
+    writer.end().unwrap();
+    let err = writer.end().unwrap_err();
+    assert_eq!(err.to_string(), "end without list or document");
+    let err = writer.atom("x").unwrap_err();
+    assert_eq!(err.to_string(), "value after the root value");
+    assert_eq!(writer.finish().unwrap(), b"a: ()\n");
+
+    let mut writer = AttoWriter::new(Vec::new());
+    writer.begin_list().unwrap();
+    writer.begin_document().unwrap();
+    let err = writer.finish().unwrap_err();
+    assert_eq!(err.to_string(), "2 lists or documents not ended");
+  }
+}
@@ -14,7 +14,7 @@ pub mod rule;
 pub mod token;
 
 pub use crate::rule::rule_of;
-pub use crate::token::Token;
+pub use crate::token::{Token, TokenSpan};
 use axlog::*;
 use std::fmt;
 
@@ -40,10 +40,19 @@ impl<'i, S: StateBounds> TokenIterator<'i, S> {
   }
 }
 
-impl<'i, S: StateBounds> Iterator for TokenIterator<'i, S> {
-  type Item = Token;
+impl<'i, S: StateBounds> TokenIterator<'i, S> {
+  /// The next token by its position in the input, without copying its
+  /// data. Only rules with an action get the data, and changes of an action
+  /// to the data are not reflected in the span.
+  pub fn next_span(&mut self) -> Option<TokenSpan> {
+    let (token, start) = self.advance(false)?;
+    let Token { rule_id, group_id, index, .. } = token;
+    Some(TokenSpan { rule_id, group_id, start, index })
+  }
 
-  fn next(&mut self) -> Option<Self::Item> {
+  // The next token with the index of its first byte. Tokens of rules
+  // without an action only get their data with `copy`.
+  fn advance(&mut self, copy: bool) -> Option<(Token, usize)> {
     {
       let group_id = self.group_id;
       let index = self.index;
@@ -60,40 +69,47 @@ impl<'i, S: StateBounds> Iterator for TokenIterator<'i, S> {
         trace!("unexpected_end");
         let group_id = self.group_id;
         self.group_id = start_id; // this ends the iterator on the next iteration
-        return Some(Token {
+        let token = Token {
           index: self.index,
           rule_id: self.lexer.unexpected_end.rule_id,
           group_id,
           data: vec![],
-        });
+        };
+        return Some((token, self.index));
       }
     }
 
     let group = self.lexer.groups[self.group_id as usize].iter();
-    let all = self.lexer.groups[0].iter();
+    // ALL is the second to last group, followed by SPECIAL
+    let all = self.lexer.groups[self.lexer.groups.len() - 2].iter();
     let catch_all = std::iter::once(&self.lexer.catch_all);
     for rule in group.chain(all).chain(catch_all) {
       let rx = &rule.lazy_regex;
       trace!("{rule}");
       if let Some(found) = rx.find(&self.input[self.index..]) {
-        let data = found.as_bytes().to_vec();
+        let identity = rule.action_name == "identity";
+        let data = match copy || !identity {
+          true => found.as_bytes().to_vec(),
+          false => vec![],
+        };
         let group_id = rule.to_group_id.unwrap_or(self.group_id);
         let token = Token {
           rule_id: rule.rule_id,
           group_id,
-          index: self.index + data.len(),
-          data: found.as_bytes().to_vec(),
+          index: self.index + found.len(),
+          data,
         };
         trace!("found  {token} index={}", self.index);
 
         let state = &mut self.state;
         if let Some(token) = (rule.action_fn)(token, state) {
+          let start = self.index;
           self.index = token.index;
           trace!("return {token} index={}", self.index);
 
           self.group_id = token.group_id;
 
-          return Some(token);
+          return Some((token, start));
         }
         trace!("rejected in action");
       } else {
@@ -102,16 +118,25 @@ impl<'i, S: StateBounds> Iterator for TokenIterator<'i, S> {
     }
 
     self.index = self.input.len();
-    Some(Token {
+    let token = Token {
       rule_id:  self.lexer.unexpected_end.rule_id,
       group_id: self.group_id,
       data:     vec![],
       index:    self.index,
-    })
+    };
+    Some((token, self.index))
     // unreachable!("catch all rule should have caught invalid input");
   }
 }
 
+impl<'i, S: StateBounds> Iterator for TokenIterator<'i, S> {
+  type Item = Token;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.advance(true).map(|(token, _)| token)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -161,6 +186,22 @@ mod tests {
     assert_eq!(tokens[2], token(R_ID_digit, G_ID_init, b"zero", 6));
     assert_eq!(tokens.len(), 3);
 
+    // spans have the matched data, not the data of the action
+    let mut iter = TokenIterator::start(b"test.0", &LEXER, ());
+    let spans = std::iter::from_fn(|| iter.next_span()).collect::<Vec<_>>();
+    let span = |rule_id, group_id, start, index| TokenSpan {
+      rule_id,
+      group_id,
+      start,
+      index,
+    };
+    assert_eq!(spans, [
+      span(R_ID_alpha, G_ID_second, 0, 4),
+      span(R_ID_dot, G_ID_second, 4, 5),
+      span(R_ID_digit, G_ID_init, 5, 6),
+    ]);
+    assert_eq!(spans[2].data(b"test.0"), b"0");
+
     let tokens = start(b"some_text42");
     dbg(&tokens);
     assert_eq!(tokens[0], token(R_ID_alpha, G_ID_second, b"some_text", 9));
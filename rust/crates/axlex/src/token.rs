@@ -16,6 +16,29 @@ pub struct Token {
   pub index: usize,
 }
 
+/// A token by its position in the text, without a copy of its data
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TokenSpan {
+  /// The rule id the lexer has used to match this token
+  pub rule_id: u16,
+
+  /// The group id the lexer will switch to (except unexpected_end)
+  pub group_id: u8,
+
+  /// The index into the text of the token's first byte
+  pub start: usize,
+
+  /// The index into the text for the next token
+  pub index: usize,
+}
+
+impl TokenSpan {
+  /// The matched bytes of `input`, the text the token is from.
+  pub fn data<'i>(&self, input: &'i [u8]) -> &'i [u8] {
+    &input[self.start..self.index]
+  }
+}
+
 fn split_point(width: usize, len: usize) -> (usize, usize) {
   ((width + 1) / 2, len - width / 2)
 }
@@ -0,0 +1,21 @@
+#![feature(lazy_cell, const_type_name, const_option)]
+
+use axlex::{lexer, Token, TokenIterator};
+
+// The rules of ALL match in every group, not only in the first one
+#[test]
+pub fn test() {
+  lexer! {
+    all<()> {
+      ALL: [ bang("!") ],
+      init: [ alpha("[a-z]+", to=second) ],
+      second: [ digit("[0-9]+", to=init) ],
+    };
+  }
+
+  let tokens = TokenIterator::start(b"a!1!b2", &LEXER, ());
+  let rule_ids = tokens.map(|t: Token| t.rule_id).collect::<Vec<_>>();
+  assert_eq!(rule_ids, [
+    R_ID_alpha, R_ID_bang, R_ID_digit, R_ID_bang, R_ID_alpha, R_ID_digit
+  ]);
+}